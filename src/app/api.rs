@@ -0,0 +1,247 @@
+use super::{article_key, completion, Backend, Db, Gui, Mutation, Store};
+use axum::{
+	extract::{Query, State},
+	http::StatusCode,
+	routing::{get, post},
+	Json, Router,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::{
+	net::TcpListener,
+	sync::{mpsc::Sender, watch},
+};
+
+/// A feed, as `GET /feeds` lists it.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct FeedSummary {
+	pub url: String,
+	pub title: String,
+	pub category: Option<String>,
+	pub unread: usize,
+}
+
+/// An article, as `GET /articles?feed=<url>` lists it.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ArticleSummary {
+	/// What identifies the article to `POST /read`.
+	pub key: String,
+	pub title: Option<String>,
+	pub link: Option<String>,
+	pub pub_date: Option<String>,
+	/// From 0 (untouched) to 1 (done).
+	pub read: f64,
+	pub starred: bool,
+}
+
+#[derive(Deserialize)]
+struct FeedQuery {
+	feed: String,
+}
+
+/// The body of `POST /read`.
+#[derive(Serialize, Deserialize)]
+pub struct MarkRead {
+	pub feed: String,
+	pub key: String,
+	/// Defaults to the whole article.
+	#[serde(default = "whole")]
+	pub fraction: f64,
+}
+
+fn whole() -> f64 {
+	1.0
+}
+
+/// The running app, as the HTTP API sees it: the DB as the backend last saved it, and the same
+/// queue of changes the GUI sends to.
+#[derive(Clone)]
+pub struct Api {
+	db: watch::Receiver<Arc<Db>>,
+	mutations: Sender<Mutation>,
+}
+
+impl Api {
+	pub fn new<S: Store>(gui: &Gui, backend: &Backend<S>) -> Self {
+		Self {
+			db: backend.subscribe(),
+			mutations: gui.mutations.clone(),
+		}
+	}
+
+	/// Answers requests on `listener` until the app closes.
+	pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+		let router = Router::new()
+			.route("/feeds", get(feeds))
+			.route("/articles", get(articles))
+			.route("/read", post(mark_read))
+			.with_state(self);
+		axum::serve(listener, router).await
+	}
+}
+
+/// Every feed, by URL.
+async fn feeds(State(api): State<Api>) -> Json<Vec<FeedSummary>> {
+	let db = api.db.borrow().clone();
+	let mut feeds: Vec<_> = db
+		.feeds
+		.iter()
+		.map(|(url, feed)| FeedSummary {
+			url: url.clone(),
+			title: feed.title().to_string(),
+			category: feed.category.clone(),
+			unread: feed.unread(),
+		})
+		.collect();
+	feeds.sort_by(|a, b| a.url.cmp(&b.url));
+	Json(feeds)
+}
+
+/// The feed's articles, in the order the feed lists them.
+async fn articles(
+	State(api): State<Api>,
+	Query(FeedQuery { feed: url }): Query<FeedQuery>,
+) -> Result<Json<Vec<ArticleSummary>>, StatusCode> {
+	let db = api.db.borrow().clone();
+	let feed = db.feeds.get(&url).ok_or(StatusCode::NOT_FOUND)?;
+	let articles = feed
+		.feed
+		.items
+		.iter()
+		.map(|article| {
+			let key = article_key(article);
+			ArticleSummary {
+				read: completion(feed, &key),
+				starred: feed.starred.contains(key.as_ref()),
+				key: key.into_owned(),
+				title: article.title().map(str::to_string),
+				link: article.link().map(str::to_string),
+				pub_date: article.pub_date().map(str::to_string),
+			}
+		})
+		.collect();
+	Ok(Json(articles))
+}
+
+/// Queues the change like the GUI would, so it's answered before it's saved.
+async fn mark_read(
+	State(api): State<Api>,
+	Json(MarkRead {
+		feed,
+		key,
+		fraction,
+	}): Json<MarkRead>,
+) -> StatusCode {
+	let known = api.db.borrow().feeds.get(&feed).is_some_and(|feed| {
+		feed.feed
+			.items
+			.iter()
+			.any(|article| article_key(article) == key.as_str())
+	});
+	if !known {
+		return StatusCode::NOT_FOUND;
+	}
+	if !(0.0..=1.0).contains(&fraction) {
+		return StatusCode::UNPROCESSABLE_ENTITY;
+	}
+	let mutation: Mutation = Box::new(move |db, _| {
+		if let Some(feed) = db.feeds.get_mut(&feed) {
+			feed.set_read(key, fraction, Utc::now());
+		}
+		Ok(())
+	});
+	match api.mutations.send(mutation).await {
+		Ok(()) => StatusCode::ACCEPTED,
+		Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Api, ArticleSummary, FeedSummary};
+	use crate::app::{Db, Feed};
+	use reqwest::header::CONTENT_TYPE;
+	use rss::{Channel, Guid, Item};
+	use serde_json::json;
+	use std::sync::Arc;
+	use tokio::{net::TcpListener, sync::watch};
+
+	#[tokio::test]
+	async fn lists_feeds_and_articles_and_queues_reads() {
+		let mut db = Db::default();
+		db.feeds.insert(
+			"https://example.com/feed".to_string(),
+			Feed {
+				feed: Channel {
+					title: "Example".to_string(),
+					items: vec![Item {
+						title: Some("Hello".to_string()),
+						guid: Some(Guid {
+							value: "hello".to_string(),
+							..Default::default()
+						}),
+						..Default::default()
+					}],
+					..Default::default()
+				},
+				..Default::default()
+			},
+		);
+		let (_latest, recv_db) = watch::channel(Arc::new(db.clone()));
+		let (mutations, mut recv_mutations) = tokio::sync::mpsc::channel(8);
+		let api = Api {
+			db: recv_db,
+			mutations,
+		};
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let base = format!("http://{}", listener.local_addr().unwrap());
+		tokio::spawn(api.serve(listener));
+		let client = reqwest::Client::new();
+		let get = |path: String| async {
+			let body = client.get(path).send().await.unwrap().text().await.unwrap();
+			serde_json::from_str::<serde_json::Value>(&body).unwrap()
+		};
+
+		let feeds: Vec<FeedSummary> =
+			serde_json::from_value(get(format!("{base}/feeds")).await).unwrap();
+		assert_eq!(
+			feeds,
+			[FeedSummary {
+				url: "https://example.com/feed".to_string(),
+				title: "Example".to_string(),
+				category: None,
+				unread: 1,
+			}]
+		);
+		let articles: Vec<ArticleSummary> = serde_json::from_value(
+			get(format!(
+				"{base}/articles?feed=https%3A%2F%2Fexample.com%2Ffeed"
+			))
+			.await,
+		)
+		.unwrap();
+		assert_eq!(articles[0].key, "hello");
+		assert_eq!(articles[0].read, 0.0);
+		let missing = client
+			.get(format!("{base}/articles?feed=nope"))
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(missing.status(), 404);
+
+		let read = |key: &str| {
+			client
+				.post(format!("{base}/read"))
+				.header(CONTENT_TYPE, "application/json")
+				.body(json!({"feed": "https://example.com/feed", "key": key}).to_string())
+				.send()
+		};
+		assert_eq!(read("nope").await.unwrap().status(), 404);
+		assert_eq!(read("hello").await.unwrap().status(), 202);
+		let mutation = recv_mutations.recv().await.unwrap();
+		let (toast, _) = tokio::sync::mpsc::channel(1);
+		mutation(&mut db, &toast).unwrap();
+		assert_eq!(db.feeds["https://example.com/feed"].unread(), 0);
+	}
+}
@@ -0,0 +1,105 @@
+use feed_rs::model::{Entry, Feed, Link};
+use rss::{Channel, Enclosure, Guid, Item};
+
+/// Reshapes an Atom feed parsed by `feed-rs` into the RSS `Channel` the rest of the app stores.
+pub fn to_channel(feed: Feed) -> Channel {
+	Channel {
+		title: feed.title.map(|t| t.content).unwrap_or_default(),
+		link: alternate(&feed.links).unwrap_or_default(),
+		description: feed.description.map(|t| t.content).unwrap_or_default(),
+		pub_date: feed.published.map(|d| d.to_rfc2822()),
+		last_build_date: feed.updated.map(|d| d.to_rfc2822()),
+		items: feed.entries.into_iter().map(to_item).collect(),
+		..Default::default()
+	}
+}
+
+fn to_item(entry: Entry) -> Item {
+	let enclosure = entry
+		.links
+		.iter()
+		.find(|l| l.rel.as_deref() == Some("enclosure"))
+		.map(|l| Enclosure {
+			url: l.href.clone(),
+			length: l.length.unwrap_or(0).to_string(),
+			mime_type: l.media_type.clone().unwrap_or_default(),
+		});
+	let content = entry.content.and_then(|c| c.body);
+	Item {
+		title: entry.title.map(|t| t.content),
+		link: alternate(&entry.links),
+		// Atom entries may only have one of the two, and the list shows the description.
+		description: entry.summary.map(|t| t.content).or_else(|| content.clone()),
+		content,
+		author: entry.authors.into_iter().find_map(|a| a.name),
+		guid: Some(Guid {
+			value: entry.id,
+			permalink: false,
+		}),
+		pub_date: entry.published.or(entry.updated).map(|d| d.to_rfc2822()),
+		enclosure,
+		..Default::default()
+	}
+}
+
+/// The page a feed or entry is about: its `alternate` link, which is also what a link with no
+/// `rel` means.
+fn alternate(links: &[Link]) -> Option<String> {
+	links
+		.iter()
+		.find(|l| matches!(l.rel.as_deref(), None | Some("alternate")))
+		.or(links.first())
+		.map(|l| l.href.clone())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::to_channel;
+
+	#[test]
+	fn atom_entries_become_items() {
+		let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+	<title>Releases</title>
+	<link rel="self" href="https://example.com/releases.atom"/>
+	<link href="https://example.com/releases"/>
+	<updated>2024-01-02T00:00:00Z</updated>
+	<id>tag:example.com,2024:releases</id>
+	<entry>
+		<title>v1.0</title>
+		<link rel="alternate" href="https://example.com/releases/v1.0"/>
+		<link rel="enclosure" type="audio/mpeg" length="1234" href="https://example.com/v1.0.mp3"/>
+		<id>tag:example.com,2024:v1.0</id>
+		<updated>2024-01-02T00:00:00Z</updated>
+		<author><name>Someone</name></author>
+		<content type="html">&lt;p&gt;Out now&lt;/p&gt;</content>
+	</entry>
+</feed>"#;
+		let channel = to_channel(feed_rs::parser::parse(atom.as_bytes()).unwrap());
+		assert_eq!(channel.title, "Releases");
+		assert_eq!(channel.link, "https://example.com/releases");
+		assert_eq!(
+			channel.last_build_date.as_deref(),
+			Some("Tue, 2 Jan 2024 00:00:00 +0000")
+		);
+		let [item] = &channel.items[..] else {
+			panic!("expected one item, got {:?}", channel.items);
+		};
+		assert_eq!(item.title.as_deref(), Some("v1.0"));
+		assert_eq!(
+			item.link.as_deref(),
+			Some("https://example.com/releases/v1.0")
+		);
+		assert_eq!(
+			item.guid.as_ref().map(|g| g.value.as_str()),
+			Some("tag:example.com,2024:v1.0")
+		);
+		assert_eq!(item.author.as_deref(), Some("Someone"));
+		assert_eq!(item.description.as_deref(), Some("<p>Out now</p>"));
+		assert_eq!(item.pub_date, channel.last_build_date);
+		let enclosure = item.enclosure.as_ref().unwrap();
+		assert_eq!(enclosure.url, "https://example.com/v1.0.mp3");
+		assert_eq!(enclosure.length, "1234");
+		assert_eq!(enclosure.mime_type, "audio/mpeg");
+	}
+}
@@ -0,0 +1,214 @@
+use super::{fetch::FetchClient, Settings};
+use reqwest::{
+	header::{HeaderMap, HeaderName, HeaderValue},
+	Proxy, Url,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+/// How Winter2 is set up before the DB is opened, from a TOML file. Anything missing from it
+/// takes its default, and command line flags override it.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	/// Where the DB is kept.
+	pub db: PathBuf,
+	pub storage: Storage,
+	/// What a new DB's settings start as. Once it exists they're kept in it, and changed in the
+	/// settings window.
+	pub settings: Settings,
+	/// Sent with every download, since some hosts turn away requests that don't say who they're
+	/// from.
+	pub user_agent: String,
+	/// Sent with every download, by name. A feed's own headers replace these.
+	pub headers: BTreeMap<String, String>,
+	/// What every download goes through, like `http://proxy:8080` or `socks5h://127.0.0.1:9050`
+	/// for Tor. If unset, the `HTTP_PROXY` and `HTTPS_PROXY` environment variables are used.
+	pub proxy: Option<String>,
+	/// How long a download may take in all before it's given up on.
+	pub timeout_secs: u64,
+	/// How long connecting to a server may take.
+	pub connect_timeout_secs: u64,
+	/// Whether to show a desktop notification when refreshing brings in new articles.
+	pub notify_new_articles: bool,
+	/// A command to run for each new article a refresh brings in, as the program and its
+	/// arguments, like `["notify-send", "{feed_title}", "{article_title}"]`. `{feed_title}`,
+	/// `{feed_url}`, `{article_title}` and `{link}` are filled in. Empty to run nothing.
+	pub new_article_hook: Vec<String>,
+	/// Whether to put an icon in the tray, and keep running there when the window's closed (on
+	/// X11; elsewhere closing still quits). Only builds with the `tray` feature have one.
+	pub tray: bool,
+	/// Where episodes are downloaded to for playing offline, such as `~/.cache/winter2/episodes`
+	/// on Linux.
+	pub download_dir: PathBuf,
+}
+
+/// How the DB is kept on disk.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+	/// A directory with a file for every field, which can be read and edited by hand.
+	#[default]
+	Fs,
+	/// One SQLite file, which is much faster with thousands of articles.
+	Sqlite,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+	#[error("Reading config file {0}")]
+	Read(PathBuf, #[source] std::io::Error),
+	#[error("Config file {0} is invalid")]
+	Parse(PathBuf, #[source] toml::de::Error),
+	#[error("Header {0:?} in the config isn't a valid HTTP header")]
+	BadHeader(String),
+	#[error("Setting up the HTTP client")]
+	Client(#[source] reqwest::Error),
+	#[error("Proxy {0:?} in the config isn't a valid proxy URL")]
+	BadProxy(String, #[source] reqwest::Error),
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			db: PathBuf::from("./.winter2db"),
+			storage: Storage::default(),
+			settings: Settings::default(),
+			user_agent: format!("winter2/{}", env!("CARGO_PKG_VERSION")),
+			headers: BTreeMap::new(),
+			proxy: None,
+			timeout_secs: 30,
+			connect_timeout_secs: 10,
+			notify_new_articles: false,
+			new_article_hook: vec![],
+			tray: false,
+			download_dir: directories::ProjectDirs::from("", "", "winter2").map_or_else(
+				|| PathBuf::from("./.winter2-episodes"),
+				|dirs| dirs.cache_dir().join("episodes"),
+			),
+		}
+	}
+}
+
+impl Config {
+	/// Where the config file is looked for if `--config` isn't given, such as
+	/// `~/.config/winter2/config.toml` on Linux.
+	pub fn default_path() -> Option<PathBuf> {
+		directories::ProjectDirs::from("", "", "winter2")
+			.map(|dirs| dirs.config_dir().join("config.toml"))
+	}
+
+	/// Reads the config file at `path`, or at [`Config::default_path`] if `None`. Only a file that
+	/// was asked for by name has to exist; otherwise the defaults are used.
+	pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+		let (path, required) = match path {
+			Some(path) => (path.to_path_buf(), true),
+			None => match Self::default_path() {
+				Some(path) => (path, false),
+				None => return Ok(Self::default()),
+			},
+		};
+		let text = match std::fs::read_to_string(&path) {
+			Ok(text) => text,
+			Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+				return Ok(Self::default());
+			}
+			Err(e) => return Err(ConfigError::Read(path, e)),
+		};
+		toml::from_str(&text).map_err(|e| ConfigError::Parse(path, e))
+	}
+
+	/// The HTTP client every download goes through, so connections to a host are reused.
+	pub fn client(&self) -> Result<FetchClient, ConfigError> {
+		let mut headers = HeaderMap::new();
+		for (name, value) in &self.headers {
+			let header = HeaderName::try_from(name)
+				.ok()
+				.zip(HeaderValue::try_from(value).ok());
+			let Some((name, value)) = header else {
+				return Err(ConfigError::BadHeader(name.clone()));
+			};
+			headers.insert(name, value);
+		}
+		let mut builder = reqwest::Client::builder()
+			.user_agent(&self.user_agent)
+			.default_headers(headers)
+			.timeout(Duration::from_secs(self.timeout_secs))
+			.connect_timeout(Duration::from_secs(self.connect_timeout_secs));
+		// Without one, reqwest uses the environment's proxy itself. That may skip some hosts with
+		// NO_PROXY, so connection errors aren't blamed on it.
+		if let Some(proxy) = &self.proxy {
+			let all = Proxy::all(proxy).map_err(|e| ConfigError::BadProxy(proxy.clone(), e))?;
+			builder = builder.proxy(all);
+		}
+		Ok(FetchClient {
+			client: builder.build().map_err(ConfigError::Client)?,
+			proxy: self.proxy.as_deref().map(without_password),
+		})
+	}
+}
+
+/// Leaves any password out of a proxy URL, so it can go in an error message.
+fn without_password(proxy: &str) -> String {
+	match Url::parse(proxy) {
+		Ok(mut url) if url.password().is_some() => {
+			let _ = url.set_password(None);
+			url.to_string()
+		}
+		_ => proxy.to_string(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Config, ConfigError, Storage};
+	use crate::app::{Settings, Theme};
+	use std::path::Path;
+	use uuid::Uuid;
+
+	#[test]
+	fn missing_keys_take_defaults() {
+		let config: Config = toml::from_str(
+			r#"
+			storage = "sqlite"
+
+			[settings]
+			theme = "Light"
+			"#,
+		)
+		.unwrap();
+		assert_eq!(config.db, Config::default().db);
+		assert_eq!(config.storage, Storage::Sqlite);
+		assert_eq!(config.settings.theme, Theme::Light);
+		assert_eq!(
+			config.settings.refresh_interval_mins,
+			Settings::default().refresh_interval_mins
+		);
+		assert!(toml::from_str::<Config>("").unwrap() == Config::default());
+		assert!(toml::from_str::<Config>("databse = \"typo\"").is_err());
+	}
+
+	#[test]
+	fn only_named_files_have_to_exist() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}.toml", Uuid::new_v4()));
+		assert!(matches!(
+			Config::load(Some(&path)),
+			Err(ConfigError::Read(..))
+		));
+		std::fs::write(&path, "db = \"/tmp/feeds\"").unwrap();
+		assert_eq!(
+			Config::load(Some(&path)).unwrap().db,
+			Path::new("/tmp/feeds")
+		);
+		std::fs::write(&path, "db = 3").unwrap();
+		assert!(matches!(
+			Config::load(Some(&path)),
+			Err(ConfigError::Parse(..))
+		));
+		std::fs::remove_file(&path).unwrap();
+	}
+}
@@ -0,0 +1,219 @@
+use super::{
+	fetch::{FeedAuth, FetchClient, FetchError},
+	player::Episode,
+};
+use reqwest::{
+	header::{CONTENT_LENGTH, RANGE},
+	StatusCode,
+};
+use sha2::{Digest, Sha256};
+use std::{
+	ffi::OsString,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+use tokio::{io::AsyncWriteExt, task::JoinHandle};
+
+/// How long a download may go without receiving anything before it's given up on. The client's
+/// own timeout is meant for feeds, and would cut most episodes short.
+const STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Replaces the client's timeout for the download as a whole.
+const MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum DownloadError {
+	#[error(transparent)]
+	Fetch(#[from] FetchError),
+	#[error("Writing the file failed: {0}")]
+	Write(#[from] std::io::Error),
+	#[error("The server stopped sending anything")]
+	Stalled,
+}
+
+/// How far along a download is.
+#[derive(Default)]
+pub struct Progress {
+	pub done: AtomicU64,
+	/// 0 if the server didn't say.
+	pub total: AtomicU64,
+}
+
+impl Progress {
+	/// Like "45%", or "12.3 MB" if the length isn't known.
+	pub fn describe(&self) -> String {
+		let done = self.done.load(Ordering::Relaxed);
+		match self.total.load(Ordering::Relaxed) {
+			0 => super::human_size(done),
+			total => format!("{}%", done * 100 / total),
+		}
+	}
+}
+
+/// An enclosure being downloaded, for the GUI to show.
+pub struct Download {
+	pub episode: Episode,
+	pub progress: Arc<Progress>,
+	pub task: JoinHandle<()>,
+}
+
+/// Where the enclosure at `url`, of article `guid` in `feed_url`, is kept once downloaded. It's
+/// named after a hash of the feed and article, keeping the URL's extension since players go by it.
+pub fn local_path(dir: &Path, feed_url: &str, guid: &str, url: &str) -> PathBuf {
+	let mut hasher = Sha256::new();
+	hasher.update(feed_url);
+	hasher.update([0]);
+	hasher.update(guid);
+	let hash: String = hasher.finalize()[..16]
+		.iter()
+		.map(|byte| format!("{byte:02x}"))
+		.collect();
+	let extension = reqwest::Url::parse(url).ok().and_then(|url| {
+		let name = url.path_segments()?.next_back()?.to_string();
+		let (_, extension) = name.rsplit_once('.')?;
+		let short = !extension.is_empty() && extension.len() <= 5;
+		(short && extension.chars().all(|c| c.is_ascii_alphanumeric()))
+			.then(|| extension.to_string())
+	});
+	match extension {
+		Some(extension) => dir.join(format!("{hash}.{extension}")),
+		None => dir.join(hash),
+	}
+}
+
+/// Where a download is written until it's done. What's there is kept if it fails, to resume from.
+pub fn partial_path(path: &Path) -> PathBuf {
+	let mut partial = OsString::from(path.as_os_str());
+	partial.push(".part");
+	partial.into()
+}
+
+/// Downloads `url` to `path`, picking up where an earlier attempt left off if the server allows
+/// it, and moving it into place once it's all there.
+pub async fn download(
+	client: &FetchClient,
+	url: &str,
+	auth: &FeedAuth,
+	path: &Path,
+	progress: &Progress,
+) -> Result<(), DownloadError> {
+	let partial = partial_path(path);
+	if let Some(dir) = path.parent() {
+		tokio::fs::create_dir_all(dir).await?;
+	}
+	let have = tokio::fs::metadata(&partial).await.map_or(0, |m| m.len());
+	let mut request = auth.apply(client.client.get(url)).timeout(MAX_DURATION);
+	if have > 0 {
+		request = request.header(RANGE, format!("bytes={have}-"));
+	}
+	let mut response = request
+		.send()
+		.await
+		.map_err(|e| client.error(e, FetchError::Download))?;
+	let status = response.status();
+	if status == StatusCode::RANGE_NOT_SATISFIABLE && have > 0 {
+		// There's nothing past what we already have.
+		tokio::fs::rename(&partial, path).await?;
+		return Ok(());
+	}
+	if !status.is_success() {
+		return Err(FetchError::Status(status).into());
+	}
+	// A server that ignores the range sends the whole file again.
+	let resumed = status == StatusCode::PARTIAL_CONTENT;
+	let start = if resumed { have } else { 0 };
+	let mut file = tokio::fs::OpenOptions::new()
+		.create(true)
+		.write(true)
+		.append(resumed)
+		.truncate(!resumed)
+		.open(&partial)
+		.await?;
+	progress.done.store(start, Ordering::Relaxed);
+	// Read from the header, since `content_length` can be missing for bodies that aren't in yet.
+	let length = response
+		.headers()
+		.get(CONTENT_LENGTH)
+		.and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+	let total = length.map_or(0, |length| start + length);
+	progress.total.store(total, Ordering::Relaxed);
+	while let Some(chunk) = tokio::time::timeout(STALL_TIMEOUT, response.chunk())
+		.await
+		.map_err(|_| DownloadError::Stalled)?
+		.map_err(|e| client.error(e, FetchError::Read))?
+	{
+		file.write_all(&chunk).await?;
+		progress
+			.done
+			.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+	}
+	file.sync_all().await?;
+	drop(file);
+	tokio::fs::rename(&partial, path).await?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{download, local_path, partial_path, Progress};
+	use crate::app::{
+		fetch::{FeedAuth, FetchClient},
+		test_server::serve,
+	};
+	use std::path::Path;
+	use uuid::Uuid;
+
+	#[test]
+	fn files_are_named_after_the_article_and_keep_their_extension() {
+		let dir = Path::new("/cache");
+		let a = local_path(dir, "https://a/feed", "1", "https://cdn/ep.mp3?token=x");
+		assert_eq!(a.extension().unwrap(), "mp3");
+		assert_ne!(
+			a,
+			local_path(dir, "https://a/feed", "2", "https://cdn/ep.mp3")
+		);
+		assert_ne!(
+			a,
+			local_path(dir, "https://b/feed", "1", "https://cdn/ep.mp3")
+		);
+		assert_eq!(
+			local_path(dir, "https://a/feed", "1", "https://cdn/episode").extension(),
+			None
+		);
+		assert_eq!(partial_path(&a).extension().unwrap(), "part");
+	}
+
+	#[tokio::test]
+	async fn interrupted_downloads_resume() {
+		let (addr, mut requests) = serve(|_| {
+			"HTTP/1.1 206 Partial Content\r\nContent-Length: 6\r\nConnection: close\r\n\r\nworld!"
+				.to_string()
+		})
+		.await;
+		let url = format!("http://{addr}/episode.mp3");
+		let dir = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let path = local_path(&dir, "feed", "guid", &url);
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(partial_path(&path), "hello ").unwrap();
+		let progress = Progress::default();
+		download(
+			&FetchClient::default(),
+			&url,
+			&FeedAuth::None,
+			&path,
+			&progress,
+		)
+		.await
+		.unwrap();
+		let request = requests.try_recv().unwrap().to_lowercase();
+		assert!(request.contains("range: bytes=6-"));
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world!");
+		assert!(!partial_path(&path).exists());
+		assert_eq!(progress.describe(), "100%");
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,85 @@
+use super::{migrations::migrate_unversioned, store::Store, Db, MkAppError};
+use std::path::Path;
+
+/// Loads the DB at `path` and renders it as a single pretty-printed JSON document, for backups
+/// and sharing. Private feeds' logins are included as they're stored, in plain text.
+pub fn export_json<S: Store>(path: &Path) -> Result<String, MkAppError> {
+	let db = S::open(path, false)?.load()?.db;
+	Ok(serde_json::to_string_pretty(&db)?)
+}
+
+/// Replaces the DB at `path` (creating it if needed) with one from [`export_json`], from this
+/// version of Winter2 or an older one.
+pub fn import_json<S: Store>(path: &Path, json: &str) -> Result<(), MkAppError> {
+	let mut value = serde_json::from_str(json)?;
+	migrate_unversioned(&mut value);
+	let db: Db = serde_json::from_value(value)?;
+	S::open(path, !path.exists())?.replace(&db)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{export_json, import_json};
+	use crate::{
+		app::{Db, Feed, FsStore, ReadState, SqliteStore},
+		db_fmt::fs_to_value,
+	};
+	use rss::Channel;
+	use uuid::Uuid;
+
+	#[test]
+	fn export_import_round_trip() {
+		let [from, to] =
+			[(); 2].map(|_| std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4())));
+		let mut db = Db::default();
+		db.feeds.insert(
+			"https://example.com/feed".to_string(),
+			Feed {
+				feed: Channel {
+					title: "Example".to_string(),
+					..Default::default()
+				},
+				read_articles: [(
+					"guid".to_string(),
+					ReadState {
+						fraction: 1.0,
+						read_at: Some(chrono::Utc::now()),
+					},
+				)]
+				.into(),
+				custom_title: Some("Mine".to_string()),
+				last_refreshed: Some(chrono::Utc::now()),
+				etag: Some("\"v1\"".to_string()),
+				..Default::default()
+			},
+		);
+		import_json::<FsStore>(&from, &serde_json::to_string(&db).unwrap()).unwrap();
+		let json = export_json::<FsStore>(&from).unwrap();
+		import_json::<FsStore>(&to, &json).unwrap();
+		assert!(fs_to_value::<Db>(&to).unwrap() == db);
+		assert!(import_json::<FsStore>(&to, "{\"feeds\": 1}").is_err());
+		assert_eq!(export_json::<FsStore>(&to).unwrap(), json);
+		std::fs::remove_dir_all(&from).unwrap();
+		std::fs::remove_dir_all(&to).unwrap();
+
+		// Exporting from one kind of store and importing into the other moves a DB between them.
+		let sqlite = std::env::temp_dir().join(format!("winter2_test_{}.sqlite", Uuid::new_v4()));
+		import_json::<SqliteStore>(&sqlite, &json).unwrap();
+		assert_eq!(export_json::<SqliteStore>(&sqlite).unwrap(), json);
+		std::fs::remove_file(&sqlite).unwrap();
+		std::fs::remove_file(sqlite.with_extension("sqlite.lock")).unwrap();
+	}
+
+	#[test]
+	fn exports_from_before_read_times_import() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let old = serde_json::json!({"feeds": {"feed": {
+			"feed": Channel::default(),
+			"read_articles": {"guid": 1.0},
+		}}});
+		import_json::<FsStore>(&path, &old.to_string()).unwrap();
+		let db: Db = fs_to_value(&path).unwrap();
+		assert_eq!(db.feeds["feed"].read_articles["guid"].fraction, 1.0);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+}
@@ -0,0 +1,555 @@
+use super::{atom, html::feed_links, json_feed, Feed};
+use feed_rs::parser::ParseFeedError;
+use reqwest::{
+	header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+	Client, RequestBuilder, StatusCode, Url,
+};
+use rss::Channel;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, future::Future, time::Duration};
+use thiserror::Error;
+
+/// How long to wait before each retry of a failed fetch.
+pub const BACKOFF: &[Duration] = &[
+	Duration::from_secs(1),
+	Duration::from_secs(2),
+	Duration::from_secs(4),
+];
+
+#[derive(Error, Debug)]
+pub enum FetchError {
+	#[error("Downloading failed: {0}")]
+	Download(#[source] reqwest::Error),
+	/// The server took longer than the config's `timeout_secs` to answer.
+	#[error("Timed out waiting for the server")]
+	TimedOut(#[source] reqwest::Error),
+	/// Connecting failed while going through a proxy, so the proxy is the likely culprit.
+	#[error("Couldn't connect through the proxy {proxy}; check that it's running and that the proxy setting is right ({source})")]
+	Proxy {
+		proxy: String,
+		#[source]
+		source: reqwest::Error,
+	},
+	#[error("Server responded {0}")]
+	Status(StatusCode),
+	#[error("Reading failed: {0}")]
+	Read(#[source] reqwest::Error),
+	#[error("Parsing failed: {0}")]
+	Parse(#[from] rss::Error),
+	#[error("Parsing Atom failed: {0}")]
+	ParseAtom(#[from] ParseFeedError),
+	#[error("Parsing JSON Feed failed: {0}")]
+	ParseJson(#[from] serde_json::Error),
+	/// The URL is a web page, which links to these feeds.
+	#[error("This is a web page, not a feed ({} feeds linked)", .0.len())]
+	NotAFeed(Vec<String>),
+}
+
+impl FetchError {
+	/// Whether trying again later might work, as opposed to the feed being gone or broken.
+	pub fn is_retryable(&self) -> bool {
+		match self {
+			FetchError::TimedOut(_) => true,
+			FetchError::Download(e) | FetchError::Read(e) | FetchError::Proxy { source: e, .. } => {
+				e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+			}
+			FetchError::Status(status) => {
+				status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+			}
+			FetchError::Parse(_)
+			| FetchError::ParseAtom(_)
+			| FetchError::ParseJson(_)
+			| FetchError::NotAFeed(_) => false,
+		}
+	}
+}
+
+pub enum Fetched {
+	/// The server says the copy we have is still current.
+	NotModified,
+	Changed {
+		channel: Box<Channel>,
+		etag: Option<String>,
+		last_modified: Option<String>,
+	},
+}
+
+/// How a private feed proves the user may read it. The credentials are kept in the DB as plain
+/// text, like everything else there, so anyone who can read the DB can read them.
+#[derive(Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FeedAuth {
+	#[default]
+	None,
+	/// HTTP Basic auth.
+	Basic { user: String, password: String },
+	/// A token sent as `Authorization: Bearer`.
+	Bearer { token: String },
+}
+
+impl FeedAuth {
+	pub(crate) fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+		match self {
+			FeedAuth::None => request,
+			FeedAuth::Basic { user, password } => request.basic_auth(user, Some(password)),
+			FeedAuth::Bearer { token } => request.bearer_auth(token),
+		}
+	}
+}
+
+/// Leaves the secrets out, so they can't end up in a log.
+impl fmt::Debug for FeedAuth {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FeedAuth::None => f.write_str("None"),
+			FeedAuth::Basic { user, .. } => write!(f, "Basic {{ user: {user:?}, .. }}"),
+			FeedAuth::Bearer { .. } => f.write_str("Bearer { .. }"),
+		}
+	}
+}
+
+/// The HTTP client every download shares, so connections are reused, along with the proxy it
+/// was configured to go through, if any.
+#[derive(Clone, Default)]
+pub struct FetchClient {
+	pub client: Client,
+	/// Without any password, so it can be shown.
+	pub proxy: Option<String>,
+}
+
+impl FetchClient {
+	/// Tells timeouts and proxy trouble apart from other failures, which become `other`.
+	pub(crate) fn error(
+		&self,
+		e: reqwest::Error,
+		other: fn(reqwest::Error) -> FetchError,
+	) -> FetchError {
+		if e.is_timeout() {
+			return FetchError::TimedOut(e);
+		}
+		match &self.proxy {
+			Some(proxy) if e.is_connect() => FetchError::Proxy {
+				proxy: proxy.clone(),
+				source: e,
+			},
+			_ => other(e),
+		}
+	}
+}
+
+/// How to ask for a feed, besides where it is.
+#[derive(Clone, Default)]
+pub struct FetchOptions {
+	pub auth: FeedAuth,
+	/// Sent on top of the client's own, replacing any with the same name.
+	pub headers: BTreeMap<String, String>,
+	/// The `ETag` and `Last-Modified` of the copy we have, if any, so the server can skip the body
+	/// when nothing changed.
+	pub etag: Option<String>,
+	pub last_modified: Option<String>,
+}
+
+impl FetchOptions {
+	/// For refreshing a feed that's already in the DB.
+	pub fn for_feed(feed: &Feed) -> Self {
+		Self {
+			auth: feed.auth.clone(),
+			headers: feed.headers.clone(),
+			etag: feed.etag.clone(),
+			last_modified: feed.last_modified.clone(),
+		}
+	}
+}
+
+/// Downloads the feed through `client`, which should be shared so connections are reused.
+pub async fn fetch_channel(
+	client: &FetchClient,
+	url: &str,
+	options: &FetchOptions,
+) -> Result<Fetched, FetchError> {
+	let mut request = options.auth.apply(client.client.get(url));
+	for (name, value) in &options.headers {
+		request = request.header(name, value);
+	}
+	if let Some(etag) = &options.etag {
+		request = request.header(IF_NONE_MATCH, etag);
+	}
+	if let Some(last_modified) = &options.last_modified {
+		request = request.header(IF_MODIFIED_SINCE, last_modified);
+	}
+	let response = request
+		.send()
+		.await
+		.map_err(|e| client.error(e, FetchError::Download))?;
+	tracing::debug!(url, status = %response.status(), "Fetched");
+	if response.status() == StatusCode::NOT_MODIFIED {
+		return Ok(Fetched::NotModified);
+	}
+	if !response.status().is_success() {
+		return Err(FetchError::Status(response.status()));
+	}
+	let header = |name| {
+		response
+			.headers()
+			.get(name)
+			.and_then(|v| v.to_str().ok())
+			.map(str::to_string)
+	};
+	let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+	let content_type = header(CONTENT_TYPE);
+	let bytes = response
+		.bytes()
+		.await
+		.map_err(|e| client.error(e, FetchError::Read))?;
+	let channel = match parse_channel(&bytes, content_type.as_deref()) {
+		Err(_) if looks_like_html(&bytes) => {
+			return Err(FetchError::NotAFeed(discover_feeds(
+				url,
+				&String::from_utf8_lossy(&bytes),
+			)))
+		}
+		result => result?,
+	};
+	Ok(Fetched::Changed {
+		channel: Box::new(channel),
+		etag,
+		last_modified,
+	})
+}
+
+/// Parses a JSON Feed if the server says it's JSON or it looks like it, and otherwise RSS, or
+/// failing that, Atom.
+pub fn parse_channel(bytes: &[u8], content_type: Option<&str>) -> Result<Channel, FetchError> {
+	let json_type = content_type.is_some_and(|t| t.contains("json"));
+	if json_type || bytes.trim_ascii_start().starts_with(b"{") {
+		return Ok(json_feed::to_channel(bytes)?);
+	}
+	match Channel::read_from(bytes) {
+		// Anything but an `<rss>` root is worth a try as Atom.
+		Err(rss::Error::InvalidStartTag) => Ok(atom::to_channel(feed_rs::parser::parse(bytes)?)),
+		result => Ok(result?),
+	}
+}
+
+fn looks_like_html(bytes: &[u8]) -> bool {
+	let start = &bytes[..bytes.len().min(1024)];
+	String::from_utf8_lossy(start)
+		.to_ascii_lowercase()
+		.contains("<html")
+}
+
+/// The feeds the page at `page_url` links to, as absolute URLs.
+pub fn discover_feeds(page_url: &str, html: &str) -> Vec<String> {
+	let base = Url::parse(page_url).ok();
+	feed_links(html)
+		.into_iter()
+		.filter_map(|href| match &base {
+			Some(base) => base.join(&href).ok().map(String::from),
+			None => Url::parse(&href).ok().map(String::from),
+		})
+		.collect()
+}
+
+/// Runs `attempt` until it succeeds, fails permanently, or `backoff` runs out. Errors come with
+/// the number of attempts made.
+pub async fn with_retries<T, F, Fut>(
+	backoff: &[Duration],
+	mut attempt: F,
+) -> Result<T, (FetchError, usize)>
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<T, FetchError>>,
+{
+	let mut attempts = 0;
+	loop {
+		attempts += 1;
+		match attempt().await {
+			Ok(v) => return Ok(v),
+			Err(e) if e.is_retryable() && attempts <= backoff.len() => {
+				tracing::debug!(attempts, error = %e, "Retrying");
+				tokio::time::sleep(backoff[attempts - 1]).await;
+			}
+			Err(e) => return Err((e, attempts)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		discover_feeds, fetch_channel, parse_channel, with_retries, FeedAuth, FetchClient,
+		FetchError, FetchOptions, Fetched,
+	};
+	use crate::app::{
+		test_server::{empty, feed, ok, serve},
+		Config,
+	};
+	use reqwest::StatusCode;
+	use std::time::Duration;
+	use tokio::net::TcpListener;
+
+	#[tokio::test]
+	async fn unchanged_feeds_are_not_downloaded_again() {
+		let (addr, _) = serve(|request| {
+			if request.to_lowercase().contains("if-none-match: \"v1\"") {
+				return empty("304 Not Modified");
+			}
+			let body = feed("T");
+			format!(
+				"HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Mon, 01 Jan 2024 00:00:00 GMT\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+				body.len()
+			)
+		})
+		.await;
+		let url = format!("http://{addr}/feed");
+
+		let Fetched::Changed {
+			channel,
+			etag,
+			last_modified,
+		} = fetch_channel(&FetchClient::default(), &url, &FetchOptions::default())
+			.await
+			.unwrap()
+		else {
+			panic!("first fetch should download the feed");
+		};
+		assert_eq!(channel.title, "T");
+		assert_eq!(etag.as_deref(), Some("\"v1\""));
+		assert_eq!(
+			last_modified.as_deref(),
+			Some("Mon, 01 Jan 2024 00:00:00 GMT")
+		);
+		assert!(matches!(
+			fetch_channel(
+				&FetchClient::default(),
+				&url,
+				&FetchOptions {
+					etag,
+					last_modified,
+					..Default::default()
+				}
+			)
+			.await,
+			Ok(Fetched::NotModified)
+		));
+	}
+
+	#[tokio::test]
+	async fn private_feeds_send_their_credentials() {
+		let (addr, _) = serve(|request| {
+			let request = request.to_lowercase();
+			// "user:pass", base64 encoded.
+			if request.contains("authorization: bearer secret")
+				|| request.contains("authorization: basic dxnlcjpwyxnz")
+			{
+				ok(&feed("T"))
+			} else {
+				empty("401 Unauthorized")
+			}
+		})
+		.await;
+		let url = format!("http://{addr}/feed");
+
+		let fetch = |auth| {
+			let url = url.clone();
+			async move {
+				let options = FetchOptions {
+					auth,
+					..Default::default()
+				};
+				fetch_channel(&FetchClient::default(), &url, &options).await
+			}
+		};
+		assert!(matches!(
+			fetch(FeedAuth::None).await,
+			Err(FetchError::Status(StatusCode::UNAUTHORIZED))
+		));
+		let bearer = FeedAuth::Bearer {
+			token: "secret".to_string(),
+		};
+		assert!(matches!(
+			fetch(bearer.clone()).await,
+			Ok(Fetched::Changed { .. })
+		));
+		let basic = FeedAuth::Basic {
+			user: "user".to_string(),
+			password: "pass".to_string(),
+		};
+		assert!(matches!(
+			fetch(basic.clone()).await,
+			Ok(Fetched::Changed { .. })
+		));
+		assert!(!format!("{bearer:?} {basic:?}").contains("secret"));
+		assert!(!format!("{basic:?}").contains("pass"));
+	}
+
+	#[tokio::test]
+	async fn configured_and_per_feed_headers_are_sent() {
+		let (addr, mut requests) = serve(|_| ok(&feed("T"))).await;
+		let url = format!("http://{addr}/feed");
+
+		let mut config = Config::default();
+		config
+			.headers
+			.insert("X-Api-Key".to_string(), "abc".to_string());
+		let client = config.client().unwrap();
+		fetch_channel(&client, &url, &FetchOptions::default())
+			.await
+			.unwrap();
+		let options = FetchOptions {
+			headers: [("User-Agent".to_string(), "Special/1.0".to_string())].into(),
+			..Default::default()
+		};
+		fetch_channel(&client, &url, &options).await.unwrap();
+
+		let mut next = || requests.try_recv().unwrap().to_lowercase();
+		let requests = [next(), next()];
+		let version = env!("CARGO_PKG_VERSION");
+		assert!(requests[0].contains(&format!("user-agent: winter2/{version}\r\n")));
+		assert!(requests[0].contains("x-api-key: abc\r\n"));
+		assert!(requests[1].contains("user-agent: special/1.0\r\n"));
+		assert!(!requests[1].contains("winter2/"));
+		assert!(requests[1].contains("x-api-key: abc\r\n"));
+
+		config
+			.headers
+			.insert("Bad Name".to_string(), "x".to_string());
+		assert!(config.client().is_err());
+	}
+
+	#[tokio::test]
+	async fn fetches_go_through_the_configured_proxy() {
+		let (proxy, mut requests) = serve(|_| ok(&feed("T"))).await;
+		let mut config = Config {
+			proxy: Some(format!("http://me:hunter2@{proxy}")),
+			..Default::default()
+		};
+		let client = config.client().unwrap();
+		let url = "http://feeds.invalid/feed";
+		fetch_channel(&client, url, &FetchOptions::default())
+			.await
+			.unwrap();
+		let request = requests.try_recv().unwrap();
+		assert!(request.starts_with(&format!("GET {url} HTTP/1.1\r\n")));
+		assert!(request.contains("proxy-authorization: Basic"));
+
+		// Nothing's listening on a port that was just freed.
+		let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		config.proxy = Some(format!(
+			"http://me:hunter2@{}",
+			closed.local_addr().unwrap()
+		));
+		drop(closed);
+		let client = config.client().unwrap();
+		let e = fetch_channel(&client, url, &FetchOptions::default())
+			.await
+			.err()
+			.unwrap();
+		assert!(matches!(e, FetchError::Proxy { .. }));
+		assert!(e.is_retryable());
+		assert!(!e.to_string().contains("hunter2"));
+
+		config.proxy = Some("not a proxy".to_string());
+		assert!(config.client().is_err());
+	}
+
+	#[tokio::test]
+	async fn hung_servers_time_out() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}/feed", listener.local_addr().unwrap());
+		tokio::spawn(async move {
+			// Accepts, then never answers.
+			let (_socket, _) = listener.accept().await.unwrap();
+			std::future::pending::<()>().await;
+		});
+		let config = Config {
+			timeout_secs: 1,
+			..Default::default()
+		};
+		let e = fetch_channel(&config.client().unwrap(), &url, &FetchOptions::default())
+			.await
+			.err()
+			.unwrap();
+		assert!(matches!(e, FetchError::TimedOut(_)));
+		assert!(e.is_retryable());
+	}
+
+	#[tokio::test]
+	async fn retries_only_transient_failures() {
+		let backoff = [Duration::ZERO; 3];
+		let mut calls = 0;
+		let result = with_retries(&backoff, || {
+			calls += 1;
+			let calls = calls;
+			async move {
+				if calls < 3 {
+					Err(FetchError::Status(StatusCode::BAD_GATEWAY))
+				} else {
+					Ok(calls)
+				}
+			}
+		})
+		.await;
+		assert_eq!(result.unwrap(), 3);
+
+		let result: Result<(), _> = with_retries(&backoff, || async {
+			Err(FetchError::Status(StatusCode::SERVICE_UNAVAILABLE))
+		})
+		.await;
+		assert!(matches!(result, Err((FetchError::Status(_), 4))));
+
+		let result: Result<(), _> = with_retries(&backoff, || async {
+			Err(FetchError::Status(StatusCode::NOT_FOUND))
+		})
+		.await;
+		assert!(matches!(result, Err((FetchError::Status(_), 1))));
+	}
+
+	#[test]
+	fn atom_is_the_fallback_for_non_rss() {
+		let rss = "<rss version=\"2.0\"><channel><title>R</title></channel></rss>";
+		assert_eq!(parse_channel(rss.as_bytes(), None).unwrap().title, "R");
+		let atom = "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>A</title></feed>";
+		assert_eq!(parse_channel(atom.as_bytes(), None).unwrap().title, "A");
+		assert!(matches!(
+			parse_channel(b"<html><body>Not a feed</body></html>", None),
+			Err(FetchError::ParseAtom(_))
+		));
+		// Broken RSS stays an RSS error rather than a confusing Atom one.
+		assert!(matches!(
+			parse_channel(b"<rss version=\"2.0\"><channel>", None),
+			Err(FetchError::Parse(_))
+		));
+	}
+
+	#[test]
+	fn json_feeds_are_detected() {
+		let json = br#"{"version": "https://jsonfeed.org/version/1.1", "title": "J", "items": []}"#;
+		assert_eq!(parse_channel(json, None).unwrap().title, "J");
+		let spaced = [&b"\n  "[..], json].concat();
+		assert_eq!(
+			parse_channel(&spaced, Some("text/plain")).unwrap().title,
+			"J"
+		);
+		assert!(matches!(
+			parse_channel(b"[]", Some("application/feed+json")),
+			Err(FetchError::ParseJson(_))
+		));
+	}
+
+	#[test]
+	fn discovered_feeds_are_absolute() {
+		let page = r#"<html><head>
+			<link rel="alternate" type="application/rss+xml" href="/feed.xml">
+			<link rel="alternate" type="application/atom+xml" href="atom.xml">
+			<link rel="alternate" type="application/rss+xml" href="https://feeds.example.net/x">
+		</head></html>"#;
+		assert_eq!(
+			discover_feeds("https://example.com/blog/post", page),
+			[
+				"https://example.com/feed.xml",
+				"https://example.com/blog/atom.xml",
+				"https://feeds.example.net/x"
+			]
+		);
+	}
+}
@@ -0,0 +1,244 @@
+use super::{
+	add_feed,
+	fetch::{
+		fetch_channel, with_retries, FeedAuth, FetchClient, FetchError, FetchOptions, BACKOFF,
+	},
+	fetch_new_feed,
+	store::Store,
+	url_list::{fetch_url_list, parse_url_list, UrlImport},
+	MkAppError,
+};
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use std::{collections::BTreeMap, path::Path};
+use tracing::Instrument;
+
+/// Downloads the feed at `url` and adds it to the DB at `path`. If it's already there it's
+/// replaced, keeping its read state. Returns the feed's title.
+pub async fn subscribe<S: Store>(
+	path: &Path,
+	client: &FetchClient,
+	url: &str,
+) -> eyre::Result<String> {
+	let feed = fetch_new_feed(client, url, FeedAuth::None).await?;
+	let title = feed.title().to_string();
+	let mut store = S::open(path, false)?;
+	let old = store.load()?.db;
+	let mut db = old.clone();
+	add_feed(&mut db, url.to_string(), feed);
+	store.save(&old, &db).await?;
+	Ok(title)
+}
+
+/// Subscribes the DB at `path` to every feed in `list`, one URL per line as
+/// [`parse_url_list`] reads it. Feeds it already has are skipped. Returns how each URL went, in
+/// the list's order.
+pub async fn import_urls<S: Store>(
+	path: &Path,
+	client: &FetchClient,
+	list: &str,
+) -> eyre::Result<Vec<(String, UrlImport)>> {
+	let mut store = S::open(path, false)?;
+	let old = store.load()?.db;
+	let limit = old.settings.max_concurrent_fetches;
+	let fetched = fetch_url_list(
+		client,
+		parse_url_list(list),
+		|url| old.feeds.contains_key(url),
+		limit,
+	)
+	.await;
+	let mut db = old.clone();
+	let mut results = vec![];
+	for (url, result, feed) in fetched {
+		if let Some(feed) = feed {
+			add_feed(&mut db, url.clone(), feed);
+		}
+		results.push((url, result));
+	}
+	store.save(&old, &db).await?;
+	Ok(results)
+}
+
+/// Every feed in the DB at `path` as its URL, title and number of unread articles, by URL.
+pub fn list_feeds<S: Store>(path: &Path) -> Result<Vec<(String, String, usize)>, MkAppError> {
+	let db = S::open(path, false)?.load()?.db;
+	let mut feeds: Vec<_> = db
+		.feeds
+		.iter()
+		.map(|(url, feed)| (url.clone(), feed.title().to_string(), feed.unread()))
+		.collect();
+	feeds.sort();
+	Ok(feeds)
+}
+
+/// Removes the feed at `url` from the DB at `path`, forgetting which of its articles were read.
+/// Returns its title.
+pub async fn unsubscribe<S: Store>(path: &Path, url: &str) -> eyre::Result<String> {
+	let mut store = S::open(path, false)?;
+	let old = store.load()?.db;
+	let mut db = old.clone();
+	let Some(feed) = db.feeds.remove(url) else {
+		eyre::bail!("Not subscribed to {url}");
+	};
+	store.save(&old, &db).await?;
+	Ok(feed.title().to_string())
+}
+
+/// Refreshes the DB at `path` without the GUI, the way "Refresh all" does: every feed that's
+/// included in it is downloaded, as many at once as the settings allow, and the results are saved.
+/// Returns how each feed went, by URL.
+pub async fn refresh_all<S: Store>(
+	path: &Path,
+	client: &FetchClient,
+) -> eyre::Result<BTreeMap<String, Result<(), FetchError>>> {
+	let mut store = S::open(path, false)?;
+	let old = store.load()?.db;
+	let limit = old.settings.max_concurrent_fetches.max(1);
+	let fetches = old
+		.feeds
+		.iter()
+		.filter(|(_, feed)| feed.refresh_enabled)
+		.map(|(url, feed)| async move {
+			let options = FetchOptions::for_feed(feed);
+			let attempt = || fetch_channel(client, url, &options);
+			let fetched = with_retries(BACKOFF, attempt)
+				.instrument(tracing::info_span!("fetch", %url))
+				.await;
+			(url, fetched)
+		});
+	let fetched: Vec<_> = stream::iter(fetches)
+		.buffer_unordered(limit)
+		.collect()
+		.await;
+
+	let mut db = old.clone();
+	let grace = db.settings.read_state_grace();
+	let mut results = BTreeMap::new();
+	for (url, fetched) in fetched {
+		let result = match fetched {
+			Ok(fetched) => {
+				if let Some(feed) = db.feeds.get_mut(url) {
+					feed.refreshed(fetched, Utc::now(), grace);
+				}
+				Ok(())
+			}
+			Err((e, attempts)) => {
+				tracing::warn!(url, attempts, error = %e, "Refreshing failed");
+				Err(e)
+			}
+		};
+		results.insert(url.clone(), result);
+	}
+	store.save(&old, &db).await?;
+	Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{import_urls, list_feeds, refresh_all, subscribe, unsubscribe};
+	use crate::app::test_server::{empty, feed, ok, serve};
+	use crate::app::url_list::UrlImport;
+	use crate::app::{fetch::FetchClient, store::Store, Db, Feed, FsStore};
+	use uuid::Uuid;
+
+	/// Serves a feed titled "Good" at `/good`, and nothing anywhere else. Returns the server's URL.
+	async fn serve_feed() -> String {
+		let (addr, _) = serve(|request| {
+			if request.starts_with("GET /good ") {
+				ok(&feed("Good"))
+			} else {
+				empty("404 Not Found")
+			}
+		})
+		.await;
+		format!("http://{addr}")
+	}
+
+	#[tokio::test]
+	async fn refreshes_and_saves_every_included_feed() {
+		let server = serve_feed().await;
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let mut db = Db::default();
+		for name in ["good", "gone", "paused"] {
+			let feed = Feed {
+				refresh_enabled: name != "paused",
+				..Default::default()
+			};
+			db.feeds.insert(format!("{server}/{name}"), feed);
+		}
+		FsStore::open(&path, true).unwrap().replace(&db).unwrap();
+
+		let results = refresh_all::<FsStore>(&path, &FetchClient::default())
+			.await
+			.unwrap();
+		assert_eq!(results.len(), 2);
+		assert!(results[&format!("{server}/good")].is_ok());
+		assert!(results[&format!("{server}/gone")].is_err());
+
+		let saved = FsStore::open(&path, false).unwrap().load().unwrap().db;
+		let feed = |name: &str| &saved.feeds[&format!("{server}/{name}")];
+		assert_eq!(feed("good").feed.title, "Good");
+		assert!(feed("good").last_refreshed.is_some());
+		assert!(feed("gone").last_refreshed.is_none());
+		assert!(feed("paused").last_refreshed.is_none());
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn feeds_are_added_listed_and_removed() {
+		let server = serve_feed().await;
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		FsStore::open(&path, true).unwrap();
+		let good = format!("{server}/good");
+		let client = FetchClient::default();
+		assert!(
+			subscribe::<FsStore>(&path, &client, &format!("{server}/gone"))
+				.await
+				.is_err()
+		);
+		assert_eq!(
+			subscribe::<FsStore>(&path, &client, &good).await.unwrap(),
+			"Good"
+		);
+		assert_eq!(
+			list_feeds::<FsStore>(&path).unwrap(),
+			[(good.clone(), "Good".to_string(), 0)]
+		);
+		assert_eq!(unsubscribe::<FsStore>(&path, &good).await.unwrap(), "Good");
+		assert!(unsubscribe::<FsStore>(&path, &good).await.is_err());
+		assert!(list_feeds::<FsStore>(&path).unwrap().is_empty());
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn url_lists_add_new_feeds_and_report_the_rest() {
+		let server = serve_feed().await;
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let mut db = Db::default();
+		db.feeds.insert(format!("{server}/old"), Feed::default());
+		FsStore::open(&path, true).unwrap().replace(&db).unwrap();
+
+		let list = format!("# Feeds\n{server}/good\n{server}/old\n\n{server}/gone\n");
+		let results = import_urls::<FsStore>(&path, &FetchClient::default(), &list)
+			.await
+			.unwrap();
+		let urls: Vec<_> = results.iter().map(|(url, _)| url.clone()).collect();
+		assert_eq!(
+			urls,
+			[
+				format!("{server}/good"),
+				format!("{server}/old"),
+				format!("{server}/gone")
+			]
+		);
+		assert!(matches!(&results[0].1, UrlImport::Added(title) if title == "Good"));
+		assert!(matches!(results[1].1, UrlImport::AlreadySubscribed));
+		assert!(matches!(results[2].1, UrlImport::Failed(_)));
+
+		let saved = FsStore::open(&path, false).unwrap().load().unwrap().db;
+		assert_eq!(saved.feeds.len(), 2);
+		assert_eq!(saved.feeds[&format!("{server}/good")].feed.title, "Good");
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+}
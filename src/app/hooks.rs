@@ -0,0 +1,202 @@
+use std::{process::Stdio, sync::Arc, time::Duration};
+use tokio::{
+	process::Command,
+	sync::{
+		mpsc::{self, Receiver, Sender},
+		Semaphore,
+	},
+};
+
+/// The most hooks running at once, so a refresh full of new articles doesn't fork hundreds.
+const MAX_RUNNING: usize = 4;
+
+/// How long a hook may run before it's killed.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An article a refresh brought in, for the hook to be told about.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct NewArticle {
+	pub feed_title: String,
+	pub feed_url: String,
+	pub title: String,
+	pub link: String,
+}
+
+/// Runs `command` for each article sent to the returned channel, with its placeholders filled
+/// in. See [`fill`].
+pub fn spawn(command: Vec<String>) -> Sender<NewArticle> {
+	let (send, recv) = mpsc::channel(1024);
+	tokio::spawn(run_all(command, recv));
+	send
+}
+
+async fn run_all(command: Vec<String>, mut recv: Receiver<NewArticle>) {
+	let running = Arc::new(Semaphore::new(MAX_RUNNING));
+	while let Some(article) = recv.recv().await {
+		let permit = running
+			.clone()
+			.acquire_owned()
+			.await
+			.expect("hook semaphore closed");
+		let args = fill(&command, &article);
+		tokio::spawn(async move {
+			run(&args).await;
+			drop(permit);
+		});
+	}
+}
+
+/// Replaces `{feed_title}`, `{feed_url}`, `{article_title}` and `{link}` in each argument. The
+/// command isn't run through a shell, so whatever an article's title says stays one argument.
+/// Each argument is filled in one pass, so a placeholder inside a title is left as written.
+pub fn fill(command: &[String], article: &NewArticle) -> Vec<String> {
+	let placeholders = [
+		("{feed_title}", &article.feed_title),
+		("{feed_url}", &article.feed_url),
+		("{article_title}", &article.title),
+		("{link}", &article.link),
+	];
+	command
+		.iter()
+		.map(|arg| {
+			let mut filled = String::with_capacity(arg.len());
+			let mut rest = arg.as_str();
+			while let Some(start) = rest.find('{') {
+				filled.push_str(&rest[..start]);
+				rest = &rest[start..];
+				match placeholders
+					.iter()
+					.find(|(placeholder, _)| rest.starts_with(placeholder))
+				{
+					Some((placeholder, value)) => {
+						filled.push_str(value);
+						rest = &rest[placeholder.len()..];
+					}
+					None => {
+						filled.push('{');
+						rest = &rest[1..];
+					}
+				}
+			}
+			filled.push_str(rest);
+			filled
+		})
+		.collect()
+}
+
+async fn run(args: &[String]) {
+	let Some((program, args)) = args.split_first() else {
+		return;
+	};
+	let child = Command::new(program)
+		.args(args)
+		.stdin(Stdio::null())
+		.kill_on_drop(true)
+		.spawn();
+	let mut child = match child {
+		Ok(child) => child,
+		Err(e) => {
+			tracing::warn!(program, error = %e, "Running the new article hook failed");
+			return;
+		}
+	};
+	match tokio::time::timeout(TIMEOUT, child.wait()).await {
+		Ok(Ok(status)) if status.success() => {}
+		Ok(Ok(status)) => tracing::warn!(program, %status, "The new article hook failed"),
+		Ok(Err(e)) => {
+			tracing::warn!(program, error = %e, "Waiting for the new article hook failed")
+		}
+		Err(_) => tracing::warn!(
+			program,
+			"The new article hook took too long, so it was killed"
+		),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{fill, spawn, NewArticle};
+	use std::time::Duration;
+	use uuid::Uuid;
+
+	#[test]
+	fn placeholders_fill_whole_arguments() {
+		let article = NewArticle {
+			feed_title: "Cast".to_string(),
+			feed_url: "https://example.com/feed".to_string(),
+			title: "Episode 1; rm -rf ~".to_string(),
+			link: "https://example.com/1".to_string(),
+		};
+		let command = [
+			"notify",
+			"{feed_title}: {article_title}",
+			"--url={link}",
+			"{feed_url}",
+		]
+		.map(str::to_string);
+		assert_eq!(
+			fill(&command, &article),
+			[
+				"notify",
+				"Cast: Episode 1; rm -rf ~",
+				"--url=https://example.com/1",
+				"https://example.com/feed"
+			]
+		);
+	}
+
+	#[test]
+	fn placeholders_in_articles_stay_as_written() {
+		let article = NewArticle {
+			feed_title: "{link}".to_string(),
+			title: "{article_title} {".to_string(),
+			link: "https://example.com/1".to_string(),
+			..Default::default()
+		};
+		let command = ["{feed_title} {article_title}", "{link}"].map(str::to_string);
+		assert_eq!(
+			fill(&command, &article),
+			["{link} {article_title} {", "https://example.com/1"]
+		);
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn runs_for_each_article() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		std::fs::create_dir_all(&path).unwrap();
+		let send = spawn(
+			[
+				"sh",
+				"-c",
+				"printf %s \"$1\" > \"$0/$2\"",
+				&path.display().to_string(),
+				"{article_title}",
+				"{link}",
+			]
+			.map(str::to_string)
+			.to_vec(),
+		);
+		for n in 0..3 {
+			let article = NewArticle {
+				title: format!("Article {n}"),
+				link: n.to_string(),
+				..Default::default()
+			};
+			send.send(article).await.unwrap();
+		}
+		for n in 0..3 {
+			let file = path.join(n.to_string());
+			let mut written = String::new();
+			for _ in 0..100 {
+				written = std::fs::read_to_string(&file).unwrap_or_default();
+				if !written.is_empty() {
+					break;
+				}
+				tokio::time::sleep(Duration::from_millis(50)).await;
+			}
+			assert_eq!(written, format!("Article {n}"));
+		}
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+}
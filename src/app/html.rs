@@ -0,0 +1,239 @@
+/// Turns an HTML description into plain text: tags are dropped (block-level ones become line
+/// breaks), entities decoded, and runs of whitespace collapsed. Text with no markup or entities
+/// is returned as-is.
+pub fn html_to_text(html: &str) -> String {
+	if !html.contains(['<', '&']) {
+		return html.to_string();
+	}
+	let mut text = Text::default();
+	let mut rest = html;
+	while let Some(c) = rest.chars().next() {
+		match c {
+			'<' => {
+				let Some(end) = rest.find('>') else {
+					// Not a tag after all.
+					text.push('<');
+					rest = &rest[1..];
+					continue;
+				};
+				let tag = rest[1..end].to_ascii_lowercase();
+				let closing = tag.starts_with('/');
+				let name = tag
+					.trim_start_matches('/')
+					.split(|c: char| c.is_whitespace() || c == '/')
+					.next()
+					.unwrap_or_default()
+					.to_string();
+				rest = &rest[end + 1..];
+				match name.as_str() {
+					"script" | "style" if !closing => {
+						let skip = rest
+							.to_ascii_lowercase()
+							.find(&format!("</{name}"))
+							.unwrap_or(rest.len());
+						rest = &rest[skip..];
+					}
+					"br" | "li" | "tr" => text.line_break(1),
+					"p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "blockquote" | "ul"
+					| "ol" | "pre" | "table" | "hr" => text.line_break(2),
+					_ => {}
+				}
+			}
+			'&' => match rest
+				.find(';')
+				.filter(|&end| end <= 10)
+				.and_then(|end| Some((decode_entity(&rest[1..end])?, end)))
+			{
+				Some((c, end)) => {
+					text.push(if c == '\u{a0}' { ' ' } else { c });
+					rest = &rest[end + 1..];
+				}
+				None => {
+					text.push('&');
+					rest = &rest[1..];
+				}
+			},
+			c => {
+				if c.is_whitespace() {
+					text.space();
+				} else {
+					text.push(c);
+				}
+				rest = &rest[c.len_utf8()..];
+			}
+		}
+	}
+	text.text
+}
+
+/// The `href`s of a page's `<link rel="alternate">` tags that point to RSS, Atom, or JSON feeds,
+/// in the order the page lists them.
+pub fn feed_links(html: &str) -> Vec<String> {
+	let lower = html.to_ascii_lowercase();
+	let mut links = Vec::new();
+	let mut from = 0;
+	while let Some(start) = lower[from..].find("<link").map(|i| i + from) {
+		let Some(end) = lower[start..].find('>').map(|i| i + start) else {
+			break;
+		};
+		from = end;
+		let attributes = attributes(&html[start + "<link".len()..end]);
+		let attribute = |name: &str| {
+			attributes
+				.iter()
+				.find(|(key, _)| key.eq_ignore_ascii_case(name))
+				.map(|(_, value)| value.to_ascii_lowercase())
+		};
+		let alternate = attribute("rel")
+			.is_some_and(|rel| rel.split_whitespace().any(|rel| rel == "alternate"));
+		let feed_type = attribute("type").is_some_and(|t| {
+			[
+				"application/rss+xml",
+				"application/atom+xml",
+				"application/feed+json",
+			]
+			.contains(&t.trim())
+		});
+		let href = attributes
+			.iter()
+			.find(|(key, _)| key.eq_ignore_ascii_case("href"))
+			.map(|(_, href)| html_to_text(href.trim()));
+		if let Some(href) = href.filter(|_| alternate && feed_type) {
+			if !links.contains(&href) {
+				links.push(href);
+			}
+		}
+	}
+	links
+}
+
+/// Splits the inside of a tag into `name=value` pairs, quoted or not. Values are left undecoded.
+fn attributes(tag: &str) -> Vec<(&str, &str)> {
+	let mut attributes = Vec::new();
+	let mut rest = tag.trim_start_matches('/');
+	loop {
+		rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+		let name_end = rest
+			.find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+			.unwrap_or(rest.len());
+		if name_end == 0 {
+			return attributes;
+		}
+		let name = &rest[..name_end];
+		rest = rest[name_end..].trim_start();
+		let Some(value) = rest.strip_prefix('=') else {
+			attributes.push((name, ""));
+			continue;
+		};
+		let value = value.trim_start();
+		let (value, after) = match value.chars().next() {
+			Some(quote @ ('"' | '\'')) => {
+				let value = &value[1..];
+				let end = value.find(quote).unwrap_or(value.len());
+				(&value[..end], value.get(end + 1..).unwrap_or_default())
+			}
+			_ => value.split_at(value.find(char::is_whitespace).unwrap_or(value.len())),
+		};
+		attributes.push((name, value));
+		rest = after;
+	}
+}
+
+/// Collects text, deferring whitespace so a run of it (or of line breaks) comes out as one, and
+/// none is left at either end.
+#[derive(Default)]
+struct Text {
+	text: String,
+	space: bool,
+	breaks: usize,
+}
+
+impl Text {
+	fn push(&mut self, c: char) {
+		if !self.text.is_empty() {
+			match self.breaks {
+				0 if self.space => self.text.push(' '),
+				0 => {}
+				1 => self.text.push('\n'),
+				_ => self.text.push_str("\n\n"),
+			}
+		}
+		self.space = false;
+		self.breaks = 0;
+		self.text.push(c);
+	}
+
+	fn space(&mut self) {
+		self.space = true;
+	}
+
+	fn line_break(&mut self, lines: usize) {
+		self.breaks = self.breaks.max(lines);
+	}
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+	if let Some(number) = entity.strip_prefix('#') {
+		let code = match number.strip_prefix(['x', 'X']) {
+			Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+			None => number.parse().ok()?,
+		};
+		return char::from_u32(code);
+	}
+	Some(match entity {
+		"amp" => '&',
+		"lt" => '<',
+		"gt" => '>',
+		"quot" => '"',
+		"apos" => '\'',
+		"nbsp" => '\u{a0}',
+		"hellip" => '…',
+		"mdash" => '—',
+		"ndash" => '–',
+		"lsquo" => '‘',
+		"rsquo" => '’',
+		"ldquo" => '“',
+		"rdquo" => '”',
+		"copy" => '©',
+		_ => return None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{feed_links, html_to_text};
+
+	#[test]
+	fn strips_markup_and_decodes_entities() {
+		assert_eq!(
+			html_to_text(
+				"<p>Hello, <b>world</b> &amp; friends&#33;</p>\n\n<p>Second&nbsp;para<br/>line</p>"
+			),
+			"Hello, world & friends!\n\nSecond para\nline"
+		);
+		assert_eq!(
+			html_to_text("<style>p { color: red }</style>Text<script>alert(1)</script>"),
+			"Text"
+		);
+		assert_eq!(html_to_text("1 < 2 &unknown; 3"), "1 < 2 &unknown; 3");
+		// Plain text is left alone, whitespace and all.
+		assert_eq!(html_to_text("Just  text\nhere"), "Just  text\nhere");
+	}
+
+	#[test]
+	fn finds_feed_links() {
+		let page = r#"<!DOCTYPE html><html><head>
+			<link rel="stylesheet" href="/style.css">
+			<LINK REL="alternate" TYPE="application/rss+xml" title="Posts" HREF="/feed.xml">
+			<link type='application/atom+xml' rel='alternate' href='https://example.com/atom?a=1&amp;b=2' />
+			<link rel=alternate type=application/feed+json href=feed.json>
+			<link rel="alternate" type="text/html" hreflang="fr" href="/fr/">
+			<link rel="alternate" type="application/rss+xml" href="/feed.xml">
+		</head><body><a href="/other.xml">Not a link tag</a></body></html>"#;
+		assert_eq!(
+			feed_links(page),
+			["/feed.xml", "https://example.com/atom?a=1&b=2", "feed.json"]
+		);
+		assert!(feed_links("<html><link rel=\"alternate\"").is_empty());
+	}
+}
@@ -0,0 +1,206 @@
+use serde_json::{Map, Value};
+use std::{
+	io::{self, ErrorKind},
+	path::{Path, PathBuf},
+};
+use tokio::io::AsyncWriteExt;
+
+/// Holds the batches of changes on their way to disk, one JSON merge patch per line. It's written
+/// before the tree is and cleared after, so a crash halfway through a save can be finished on the
+/// next launch.
+const JOURNAL_FILE: &str = ".winter2_journal";
+
+fn journal_path(path: &Path) -> PathBuf {
+	path.join(JOURNAL_FILE)
+}
+
+/// What changed from `old` to `new`, as a JSON merge patch (RFC 7386): objects list only the
+/// keys that changed, `null` removes a key, and anything else replaces what was there. `None` if
+/// nothing changed.
+pub fn merge_patch(old: &Value, new: &Value) -> Option<Value> {
+	match (old, new) {
+		_ if old == new => None,
+		(Value::Object(old), Value::Object(new)) => {
+			let mut patch = Map::new();
+			for (key, value) in new {
+				let changed = match old.get(key) {
+					Some(old) => merge_patch(old, value),
+					None => Some(value.clone()),
+				};
+				patch.extend(changed.map(|change| (key.clone(), change)));
+			}
+			for key in old.keys().filter(|key| !new.contains_key(*key)) {
+				patch.insert(key.clone(), Value::Null);
+			}
+			Some(Value::Object(patch))
+		}
+		_ => Some(new.clone()),
+	}
+}
+
+/// Applies a patch from [`merge_patch`].
+pub fn apply_patch(target: &mut Value, patch: &Value) {
+	let Value::Object(patch) = patch else {
+		*target = patch.clone();
+		return;
+	};
+	if !target.is_object() {
+		*target = Value::Object(Map::new());
+	}
+	let Value::Object(target) = target else {
+		unreachable!()
+	};
+	for (key, value) in patch {
+		if value.is_null() {
+			target.remove(key);
+		} else {
+			apply_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+		}
+	}
+}
+
+/// Records a batch of changes, and makes sure it's on disk before returning.
+pub async fn append(path: &Path, patch: &Value) -> io::Result<()> {
+	let mut line = serde_json::to_vec(patch)?;
+	line.push(b'\n');
+	let mut journal = tokio::fs::OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(journal_path(path))
+		.await?;
+	journal.write_all(&line).await?;
+	journal.sync_all().await
+}
+
+/// Forgets every batch, once they're all in the tree.
+pub fn clear(path: &Path) -> io::Result<()> {
+	match std::fs::remove_file(journal_path(path)) {
+		Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+		_ => Ok(()),
+	}
+}
+
+pub async fn clear_async(path: &Path) -> io::Result<()> {
+	match tokio::fs::remove_file(journal_path(path)).await {
+		Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+		_ => Ok(()),
+	}
+}
+
+/// The batches that didn't make it into the tree. A batch that was cut off while being recorded
+/// never started being saved, so it's left out.
+fn read(path: &Path) -> io::Result<Vec<Value>> {
+	let journal = match std::fs::read_to_string(journal_path(path)) {
+		Ok(journal) => journal,
+		Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => return Err(e),
+	};
+	Ok(journal
+		.lines()
+		.map_while(|line| serde_json::from_str(line).ok())
+		.collect())
+}
+
+/// Applies the batches a crash kept out of the tree to `value`, the tree as it was read,
+/// returning how many there were. They stay journaled until the result is saved.
+pub fn recover(path: &Path, value: &mut Value) -> io::Result<usize> {
+	let patches = read(path)?;
+	for patch in &patches {
+		apply_patch(value, patch);
+	}
+	Ok(patches.len())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{append, apply_patch, journal_path, merge_patch, recover};
+	use crate::{
+		app::{
+			migrations::{load_db, save_db, DB_VERSION},
+			store::{FsStore, Store},
+			Db,
+		},
+		db_fmt::value_to_fs,
+	};
+	use rss::Channel;
+	use serde_json::json;
+	use uuid::Uuid;
+
+	#[test]
+	fn patches_turn_old_into_new() {
+		let old = json!({"a": 1, "b": {"c": [1, 2], "d": "x"}, "e": true});
+		let new = json!({"a": 1, "b": {"c": [1], "f": null}, "g": {"h": 2}});
+		let patch = merge_patch(&old, &new).unwrap();
+		assert_eq!(
+			patch,
+			json!({"b": {"c": [1], "d": null, "f": null}, "e": null, "g": {"h": 2}})
+		);
+		let mut patched = old.clone();
+		apply_patch(&mut patched, &patch);
+		// A merge patch can't tell a key set to null from a removed one.
+		assert_eq!(patched, json!({"a": 1, "b": {"c": [1]}, "g": {"h": 2}}));
+		assert_eq!(merge_patch(&new, &new), None);
+	}
+
+	#[tokio::test]
+	async fn interrupted_saves_are_recovered() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		std::fs::create_dir_all(&path).unwrap();
+		let db = Db::default();
+		save_db(&path, &db).unwrap();
+		let mut next = db.clone();
+		next.settings.auto_refresh = false;
+		let patch = |old: &Db, new: &Db| {
+			let old = serde_json::to_value(old).unwrap();
+			merge_patch(&old, &serde_json::to_value(new).unwrap()).unwrap()
+		};
+		append(&path, &patch(&db, &next)).await.unwrap();
+		let mut last = next.clone();
+		last.queue.push(("feed".to_string(), "article".to_string()));
+		append(&path, &patch(&next, &last)).await.unwrap();
+		// The crash came while the third batch was being recorded.
+		let mut journal = std::fs::read(journal_path(&path)).unwrap();
+		journal.extend_from_slice(b"{\"queue\": [");
+		std::fs::write(journal_path(&path), journal).unwrap();
+
+		let mut value = serde_json::to_value(&db).unwrap();
+		assert_eq!(recover(&path, &mut value).unwrap(), 2);
+		assert!(serde_json::from_value::<Db>(value).unwrap() == last);
+		// Loading saves what was recovered, which clears the journal.
+		let loaded = load_db(&path).unwrap();
+		assert_eq!(loaded.recovered, 2);
+		assert!(loaded.db == last);
+		assert!(!journal_path(&path).exists());
+		let again = load_db(&path).unwrap();
+		assert_eq!(again.recovered, 0);
+		assert!(again.db == last);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn saves_cut_short_before_a_migration_are_recovered() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let v1 = json!({"feeds": {"https://example.com/feed": {
+			"feed": Channel::default(),
+			"read_articles": {"done": 1.0},
+		}}});
+		value_to_fs(&path, &v1).unwrap();
+		std::fs::write(path.join(".winter2_version"), "1").unwrap();
+		// Journaled by a v1 build, which crashed before the tree was written.
+		let patch =
+			json!({"feeds": {"https://example.com/feed": {"read_articles": {"half": 0.5}}}});
+		append(&path, &patch).await.unwrap();
+
+		let loaded = FsStore::open(&path, false).unwrap().load().unwrap();
+		assert_eq!(loaded.recovered, 1);
+		let read = &loaded.db.feeds["https://example.com/feed"].read_articles;
+		assert_eq!(read["done"].fraction, 1.0);
+		assert_eq!(read["half"].fraction, 0.5);
+		assert!(!journal_path(&path).exists());
+		assert_eq!(
+			std::fs::read_to_string(path.join(".winter2_version")).unwrap(),
+			DB_VERSION.to_string()
+		);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+}
@@ -0,0 +1,156 @@
+use chrono::DateTime;
+use rss::{extension::itunes::ITunesItemExtension, Channel, Enclosure, Guid, Item};
+use serde::Deserialize;
+
+/// The parts of a [JSON Feed](https://jsonfeed.org/version/1.1) the app has a use for.
+#[derive(Deserialize)]
+struct JsonFeed {
+	title: String,
+	home_page_url: Option<String>,
+	description: Option<String>,
+	#[serde(default)]
+	items: Vec<JsonItem>,
+}
+
+#[derive(Deserialize)]
+struct JsonItem {
+	id: String,
+	url: Option<String>,
+	title: Option<String>,
+	content_html: Option<String>,
+	content_text: Option<String>,
+	summary: Option<String>,
+	date_published: Option<String>,
+	date_modified: Option<String>,
+	/// Version 1.1's list of authors.
+	#[serde(default)]
+	authors: Vec<Author>,
+	/// Version 1.0's single author.
+	author: Option<Author>,
+	#[serde(default)]
+	attachments: Vec<Attachment>,
+}
+
+#[derive(Deserialize)]
+struct Author {
+	name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Attachment {
+	url: String,
+	mime_type: String,
+	size_in_bytes: Option<u64>,
+	duration_in_seconds: Option<f64>,
+}
+
+/// Parses a JSON Feed into the RSS `Channel` the rest of the app stores.
+pub fn to_channel(json: &[u8]) -> Result<Channel, serde_json::Error> {
+	let feed: JsonFeed = serde_json::from_slice(json)?;
+	Ok(Channel {
+		title: feed.title,
+		link: feed.home_page_url.unwrap_or_default(),
+		description: feed.description.unwrap_or_default(),
+		items: feed.items.into_iter().map(to_item).collect(),
+		..Default::default()
+	})
+}
+
+fn to_item(item: JsonItem) -> Item {
+	let attachment = item.attachments.into_iter().next();
+	let duration = attachment
+		.as_ref()
+		.and_then(|a| a.duration_in_seconds)
+		.map(|seconds| ITunesItemExtension {
+			duration: Some(seconds.round().to_string()),
+			..Default::default()
+		});
+	Item {
+		title: item.title,
+		link: item.url,
+		description: item.content_html.or(item.content_text).or(item.summary),
+		author: item
+			.authors
+			.into_iter()
+			.chain(item.author)
+			.find_map(|a| a.name),
+		guid: Some(Guid {
+			value: item.id,
+			permalink: false,
+		}),
+		// JSON Feed dates are RFC 3339, but articles are dated in RFC 2822 like RSS.
+		pub_date: item
+			.date_published
+			.or(item.date_modified)
+			.map(|d| DateTime::parse_from_rfc3339(&d).map_or(d, |d| d.to_rfc2822())),
+		enclosure: attachment.map(|a| Enclosure {
+			url: a.url,
+			length: a.size_in_bytes.unwrap_or(0).to_string(),
+			mime_type: a.mime_type,
+		}),
+		itunes_ext: duration,
+		..Default::default()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::to_channel;
+
+	#[test]
+	fn json_feed_items_become_items() {
+		let json = r#"{
+			"version": "https://jsonfeed.org/version/1.1",
+			"title": "My Podcast",
+			"home_page_url": "https://example.org/",
+			"feed_url": "https://example.org/feed.json",
+			"items": [
+				{
+					"id": "2",
+					"url": "https://example.org/2",
+					"title": "Episode 2",
+					"content_html": "<p>Second</p>",
+					"date_published": "2024-01-02T10:00:00-05:00",
+					"authors": [{"name": "Host"}],
+					"attachments": [{
+						"url": "https://example.org/2.mp3",
+						"mime_type": "audio/mpeg",
+						"size_in_bytes": 1234,
+						"duration_in_seconds": 61.4
+					}]
+				},
+				{"id": "1", "content_text": "First", "author": {"name": "Old Host"}}
+			]
+		}"#;
+		let channel = to_channel(json.as_bytes()).unwrap();
+		assert_eq!(channel.title, "My Podcast");
+		assert_eq!(channel.link, "https://example.org/");
+		let [second, first] = &channel.items[..] else {
+			panic!("expected two items, got {:?}", channel.items);
+		};
+		assert_eq!(second.guid.as_ref().unwrap().value, "2");
+		assert_eq!(second.link.as_deref(), Some("https://example.org/2"));
+		assert_eq!(second.description.as_deref(), Some("<p>Second</p>"));
+		assert_eq!(second.author.as_deref(), Some("Host"));
+		assert_eq!(
+			second.pub_date.as_deref(),
+			Some("Tue, 2 Jan 2024 10:00:00 -0500")
+		);
+		let enclosure = second.enclosure.as_ref().unwrap();
+		assert_eq!(enclosure.url, "https://example.org/2.mp3");
+		assert_eq!(enclosure.length, "1234");
+		assert_eq!(enclosure.mime_type, "audio/mpeg");
+		assert_eq!(
+			second
+				.itunes_ext
+				.as_ref()
+				.and_then(|i| i.duration.as_deref()),
+			Some("61")
+		);
+
+		assert_eq!(first.description.as_deref(), Some("First"));
+		assert_eq!(first.author.as_deref(), Some("Old Host"));
+		assert_eq!(first.enclosure, None);
+		assert!(to_channel(b"{\"items\": []}").is_err());
+	}
+}
@@ -0,0 +1,270 @@
+use super::{journal, store::Loaded, Db, Feed, MkAppError, ReadState};
+use crate::db_fmt::{
+	fs_to_value_report, value_to_fs, value_to_fs_async, LoadMode, ReadOptions, ValueToFsError,
+	WriteStats,
+};
+use rss::Item;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Each entry upgrades the on-disk `Db` by one version, so `MIGRATIONS[n]` takes v`n` to
+/// v`n + 1`. Databases written before versioning existed count as v0.
+const MIGRATIONS: &[fn(&mut Value)] = &[v0_to_v1, v1_to_v2];
+
+pub const DB_VERSION: u32 = MIGRATIONS.len() as u32;
+
+const VERSION_FILE: &str = ".winter2_version";
+
+/// v0 databases could contain feeds without `read_articles`.
+fn v0_to_v1(db: &mut Value) {
+	let Some(feeds) = db.get_mut("feeds").and_then(Value::as_object_mut) else {
+		return;
+	};
+	for feed in feeds.values_mut().filter_map(Value::as_object_mut) {
+		feed.entry("read_articles")
+			.or_insert_with(|| Value::Object(Default::default()));
+	}
+}
+
+/// v1 stored each read article as its bare fraction, before reads were timestamped.
+fn v1_to_v2(db: &mut Value) {
+	let Some(feeds) = db.get_mut("feeds").and_then(Value::as_object_mut) else {
+		return;
+	};
+	let read_articles = feeds
+		.values_mut()
+		.filter_map(|feed| feed.get_mut("read_articles")?.as_object_mut());
+	for read in read_articles.flat_map(|read| read.values_mut()) {
+		if read.is_number() {
+			*read = json!({ "fraction": read.take() });
+		}
+	}
+}
+
+/// Brings a DB of unknown version, like an export, up to date. Every migration leaves what's
+/// already in its new shape alone, so they can all be run.
+pub fn migrate_unversioned(db: &mut Value) {
+	for migration in MIGRATIONS {
+		migration(db);
+	}
+}
+
+fn read_version(path: &Path) -> Result<u32, MkAppError> {
+	match std::fs::read_to_string(path.join(VERSION_FILE)) {
+		Ok(version) => version
+			.trim()
+			.parse()
+			.map_err(|_| MkAppError::BadVersion(version)),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Loads the `Db`, finishing any save a crash cut short and migrating it to [`DB_VERSION`] if
+/// it's older, and saving the result if either happened. Corrupt leaves are skipped and
+/// directories missing their `.type` are repaired rather than failing the load; the report says
+/// which so the user can be told, and the repaired DB is saved over them.
+pub fn load_db(path: &Path) -> Result<Loaded, MkAppError> {
+	let version = read_version(path)?;
+	if version > DB_VERSION {
+		return Err(MkAppError::NewerDb(version));
+	}
+	let options = ReadOptions {
+		mode: LoadMode::Lenient,
+		..Default::default()
+	};
+	let (mut value, report): (Value, _) = fs_to_value_report(path, &options)?;
+	// The journal is cleared whenever the tree is saved, so it's in the tree's version. Saving
+	// the migrated DB before replaying it would throw it away.
+	let recovered = journal::recover(path, &mut value)?;
+	for migration in &MIGRATIONS[version as usize..] {
+		migration(&mut value);
+	}
+	if !report.skipped.is_empty() {
+		salvage(&mut value);
+	}
+	let db = serde_json::from_value(value)?;
+	if version < DB_VERSION || !report.is_empty() || recovered > 0 {
+		save_db(path, &db)?;
+	}
+	Ok(Loaded {
+		db,
+		report,
+		recovered,
+	})
+}
+
+/// Lenient reads leave `null` where a leaf was corrupt, which only fits the optional fields.
+/// Elsewhere the field's default is used if it has one, and otherwise the article, read state,
+/// queue entry or feed holding it is dropped, so the rest of the DB still loads.
+fn salvage(db: &mut Value) {
+	fill_nulls(db, &json!(Db::default()));
+	if let Some(queue) = db.get_mut("queue").and_then(Value::as_array_mut) {
+		queue.retain(fits::<(String, String)>);
+	}
+	let Some(feeds) = db.get_mut("feeds").and_then(Value::as_object_mut) else {
+		return;
+	};
+	let (feed_default, item_default) = (json!(Feed::default()), json!(Item::default()));
+	feeds.retain(|url, feed| {
+		fill_nulls(feed, &feed_default);
+		if let Some(items) = feed
+			.pointer_mut("/feed/items")
+			.and_then(Value::as_array_mut)
+		{
+			for item in items.iter_mut() {
+				fill_nulls(item, &item_default);
+			}
+			items.retain(fits::<Item>);
+		}
+		if let Some(read) = feed.get_mut("read_articles").and_then(Value::as_object_mut) {
+			read.retain(|_, state| fits::<ReadState>(state));
+		}
+		let fits = fits::<Feed>(feed);
+		if !fits {
+			tracing::warn!(url, "Dropped a feed that was too damaged to load");
+		}
+		fits
+	});
+}
+
+/// Replaces each `null` in `value` with what's in the same place in `default`, the JSON of a
+/// default value, where that has anything.
+fn fill_nulls(value: &mut Value, default: &Value) {
+	let (Value::Object(fields), Value::Object(defaults)) = (value, default) else {
+		return;
+	};
+	for (key, field) in fields {
+		match defaults.get(key) {
+			Some(default) if field.is_null() => *field = default.clone(),
+			Some(default) => fill_nulls(field, default),
+			None => {}
+		}
+	}
+}
+
+fn fits<T: DeserializeOwned>(value: &Value) -> bool {
+	T::deserialize(value).is_ok()
+}
+
+/// Writes the `Db` along with the schema version it was written with.
+pub fn save_db(path: &Path, db: &Db) -> Result<WriteStats, ValueToFsError> {
+	let stats = value_to_fs(path, db)?;
+	std::fs::write(path.join(VERSION_FILE), DB_VERSION.to_string())?;
+	journal::clear(path)?;
+	Ok(stats)
+}
+
+pub async fn save_db_async(path: &Path, db: &Db) -> Result<WriteStats, ValueToFsError> {
+	let stats = value_to_fs_async(path, db).await?;
+	tokio::fs::write(path.join(VERSION_FILE), DB_VERSION.to_string()).await?;
+	journal::clear_async(path).await?;
+	Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{load_db, read_version, save_db, DB_VERSION};
+	use crate::{
+		app::{Db, Feed, MkAppError, ReadState},
+		db_fmt::value_to_fs,
+	};
+	use base64::{engine::general_purpose::STANDARD, Engine};
+	use rss::Channel;
+	use serde_json::json;
+	use uuid::Uuid;
+
+	#[test]
+	fn migrates_v0_and_rejects_newer() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let v0 = json!({"feeds": {"https://example.com/feed": {"feed": Channel::default()}}});
+		value_to_fs(&path, &v0).unwrap();
+		let db = load_db(&path).unwrap().db;
+		assert!(db.feeds["https://example.com/feed"]
+			.read_articles
+			.is_empty());
+		assert_eq!(read_version(&path).unwrap(), DB_VERSION);
+
+		std::fs::write(path.join(".winter2_version"), (DB_VERSION + 1).to_string()).unwrap();
+		assert!(matches!(load_db(&path), Err(MkAppError::NewerDb(_))));
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn v1_read_fractions_become_read_states() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let v1 = json!({"feeds": {"https://example.com/feed": {
+			"feed": Channel::default(),
+			"read_articles": {"done": 1.0, "half": 0.5},
+		}}});
+		value_to_fs(&path, &v1).unwrap();
+		std::fs::write(path.join(".winter2_version"), "1").unwrap();
+		let db = load_db(&path).unwrap().db;
+		let read = &db.feeds["https://example.com/feed"].read_articles;
+		assert_eq!(
+			read["done"],
+			ReadState {
+				fraction: 1.0,
+				read_at: None
+			}
+		);
+		assert_eq!(read["half"].fraction, 0.5);
+		assert_eq!(read_version(&path).unwrap(), DB_VERSION);
+		// It was saved in the new shape, so loading it again doesn't need to migrate.
+		let again = load_db(&path).unwrap().db;
+		assert!(again == db);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn corrupt_leaves_fall_back_to_defaults_or_drop_their_entry() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let mut db = Db::default();
+		let read = |fraction| ReadState {
+			fraction,
+			read_at: None,
+		};
+		db.feeds.insert(
+			"a".to_string(),
+			Feed {
+				feed: Channel {
+					title: "A".to_string(),
+					..Default::default()
+				},
+				read_articles: [
+					("done".to_string(), read(1.0)),
+					("half".to_string(), read(0.5)),
+				]
+				.into(),
+				..Default::default()
+			},
+		);
+		save_db(&path, &db).unwrap();
+		let leaf = |keys: &[&str]| {
+			keys.iter().fold(path.clone(), |leaf, key| {
+				leaf.join(format!("key_{}", STANDARD.encode(key)))
+			})
+		};
+		// Writes cut off partway through.
+		std::fs::write(leaf(&["feeds", "a", "feed", "title"]), "\"A").unwrap();
+		std::fs::write(
+			leaf(&["feeds", "a", "read_articles", "half", "fraction"]),
+			"0.",
+		)
+		.unwrap();
+
+		let loaded = load_db(&path).unwrap();
+		assert_eq!(loaded.report.skipped.len(), 2);
+		let feed = &loaded.db.feeds["a"];
+		// A channel can do without its title.
+		assert_eq!(feed.feed.title, "");
+		// A read state without its fraction says nothing.
+		assert_eq!(feed.read_articles.keys().collect::<Vec<_>>(), ["done"]);
+		// What was salvaged was saved, so the next load is clean.
+		let again = load_db(&path).unwrap();
+		assert!(again.report.is_empty());
+		assert!(again.db == loaded.db);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+}
@@ -1,61 +1,285 @@
-use crate::db_fmt::{fs_to_value, value_to_fs, FsToValueError};
-use eframe::egui::{CentralPanel, CollapsingHeader, ScrollArea, SidePanel, TopBottomPanel, Vec2b};
+use crate::db_fmt::{FsToValueError, ValueToFsError, WriteStats};
+use chrono::{DateTime, Datelike, FixedOffset, Local, Utc};
+use eframe::egui::{
+	Align2, Button, CentralPanel, CollapsingHeader, ComboBox, DragValue, Key, Modifiers, RichText,
+	ScrollArea, SidePanel, Slider, TextEdit, TopBottomPanel, Ui, Vec2, Vec2b, ViewportCommand,
+	Window,
+};
 use egui_notify::{Toast, ToastLevel, Toasts};
-use rss::{Channel, Guid};
+use reqwest::header::{HeaderName, HeaderValue};
+use rss::{Channel, Item};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
-	collections::HashMap,
-	convert::Infallible,
-	io::BufReader,
+	any::Any,
+	borrow::Cow,
+	collections::{BTreeMap, HashMap, HashSet, VecDeque},
+	future::Future,
+	mem,
 	ops::Mul,
-	path::PathBuf,
+	panic::{self, AssertUnwindSafe},
+	path::{Path, PathBuf},
 	sync::{
 		atomic::{AtomicUsize, Ordering},
-		Arc, OnceLock,
+		Arc, Mutex, OnceLock, Weak,
 	},
+	time::{Duration, Instant},
 };
+use thiserror::Error;
 use tokio::{
-	sync::mpsc::{Receiver, Sender},
+	sync::{
+		mpsc::{Receiver, Sender},
+		oneshot, Semaphore,
+	},
 	task::JoinHandle,
 };
+use tracing::Instrument;
+
+/// The most mutations applied before saving.
+const BATCH_LIMIT: usize = 1024;
+
+/// How long the backend waits for more mutations before saving a batch.
+const SAVE_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// How long to wait for another program to finish editing the DB before reloading it.
+const EXTERNAL_CHANGE_SETTLE: Duration = Duration::from_millis(250);
+
+/// Where the open feed and article are kept between launches, in eframe's storage.
+const SELECTED_FEED_KEY: &str = "selected_feed";
+
+/// Where the article list's sort order and "Unread only" are kept, beside the open feed.
+const LIST_VIEW_KEY: &str = "list_view";
+
+/// How many global search results are shown at once, so huge feeds don't stall the frame.
+const MAX_SEARCH_RESULTS: usize = 100;
+
+/// How many past states "Undo" can step back through. Each is a whole `Db`, so this bounds memory.
+const UNDO_DEPTH: usize = 20;
+
+/// The speeds offered in the now-playing panel.
+const PLAYBACK_RATES: [f64; 9] = [0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0, 2.25, 2.5];
+
+/// How many of an article's categories are shown in the article list, the rest going on hover.
+const ROW_CATEGORIES: usize = 3;
 
 type Mutation =
 	Box<dyn FnOnce(&mut Db, &Sender<(ToastLevel, String)>) -> eyre::Result<()> + Send + Sync>;
 
-pub fn mk_app(path: PathBuf, init: bool) -> Result<(Gui, Backend), FsToValueError> {
-	let db = if init {
-		let db = Db::default();
-		value_to_fs(&path, &db).unwrap();
-		db
-	} else {
-		fs_to_value(&path)?
+#[derive(Error, Debug)]
+pub enum MkAppError {
+	#[error("Loading DB")]
+	Load(#[from] FsToValueError),
+	#[error("Saving DB")]
+	Save(#[from] ValueToFsError),
+	#[error("Migrated DB doesn't match the current schema")]
+	Schema(#[from] serde_json::Error),
+	#[error("IO")]
+	IO(#[from] std::io::Error),
+	#[error("Another Winter2 instance is already using this database")]
+	Locked,
+	#[error("Unreadable DB version {0:?}")]
+	BadVersion(String),
+	#[error("DB is from a newer Winter2 (schema v{0}, this build supports up to v{DB_VERSION})")]
+	NewerDb(u32),
+	#[error("SQLite")]
+	Sqlite(#[from] rusqlite::Error),
+	#[error("Config")]
+	Config(#[from] ConfigError),
+}
+
+#[cfg(feature = "serve")]
+mod api;
+mod atom;
+mod config;
+mod download;
+mod export;
+mod fetch;
+mod headless;
+mod hooks;
+mod html;
+mod journal;
+mod json_feed;
+mod migrations;
+#[cfg(all(feature = "mpris", target_os = "linux"))]
+mod mpris;
+mod notifications;
+mod opml;
+mod player;
+mod settings;
+mod sqlite;
+mod stats;
+mod store;
+#[cfg(test)]
+mod test_server;
+#[cfg(feature = "tray")]
+mod tray;
+mod url_list;
+mod watch;
+
+#[cfg(feature = "serve")]
+pub use api::Api;
+pub use config::{Config, ConfigError, Storage};
+use download::{download, local_path, partial_path, Download, Progress};
+pub use export::{export_json, import_json};
+pub use fetch::FetchClient;
+use fetch::{fetch_channel, with_retries, FetchOptions, Fetched, BACKOFF};
+pub use fetch::{FeedAuth, FetchError};
+pub use headless::{import_urls, list_feeds, refresh_all, subscribe, unsubscribe};
+use hooks::NewArticle;
+pub use html::html_to_text;
+pub use migrations::DB_VERSION;
+use notifications::NewArticles;
+pub use opml::{export_opml, to_opml};
+use player::{clock, Episode, Playback, PlayerCommand};
+pub use settings::{Settings, Theme};
+pub use sqlite::SqliteStore;
+use stats::Stats;
+pub use store::{FsStore, Store};
+pub use url_list::UrlImport;
+use url_list::{fetch_url_list, parse_url_list};
+use watch::DbWatcher;
+
+/// Opens the DB at `config.db`, or creates it there if `init`, and sets up the GUI and the backend
+/// that saves its changes.
+pub fn mk_app<S: Store>(config: &Config, init: bool) -> Result<(Gui, Backend<S>), MkAppError> {
+	let client = config.client()?;
+	let mut store = S::open(&config.db, init)?;
+	let store::Loaded {
+		mut db,
+		report,
+		recovered,
+	} = store.load()?;
+	if init {
+		db.settings = config.settings.clone();
+		store.replace(&db)?;
+	}
+	let mut toasts = Toasts::new();
+	if recovered > 0 {
+		toasts.warning(format!(
+			"Finished saving {recovered} batches of changes that were cut short last time"
+		));
+	}
+	let list = |paths: &[PathBuf]| -> String {
+		let paths: Vec<_> = paths.iter().map(|p| p.display().to_string()).collect();
+		paths.join(", ")
+	};
+	if !report.skipped.is_empty() {
+		toasts.add(Toast::custom(
+			format!(
+				"Dropped {} corrupted DB entries: {}",
+				report.skipped.len(),
+				list(&report.skipped)
+			),
+			ToastLevel::Warning,
+		));
+	}
+	if !report.repaired.is_empty() {
+		toasts.add(Toast::custom(
+			format!(
+				"Repaired {} DB directories with a missing type: {}",
+				report.repaired.len(),
+				list(&report.repaired)
+			),
+			ToastLevel::Warning,
+		));
+	}
+	let watcher = match DbWatcher::new(store.path()) {
+		Ok(watcher) => Some(watcher),
+		Err(e) => {
+			toasts.warning(format!(
+				"Changes other programs make to the DB won't be noticed, since watching it failed with {e}"
+			));
+			None
+		}
 	};
 	let (send_mutations, recv_mutations) = tokio::sync::mpsc::channel(1024);
 	let (send_db, recv_db) = tokio::sync::mpsc::channel(1024);
 	let (send_toast, recv_toast) = tokio::sync::mpsc::channel(1024);
+	let (send_shutdown, recv_shutdown) = oneshot::channel();
+	let window = Arc::default();
+	let new_articles = config
+		.notify_new_articles
+		.then(|| notifications::spawn(Arc::clone(&window)));
+	let article_hook = (!config.new_article_hook.is_empty())
+		.then(|| hooks::spawn(config.new_article_hook.clone()));
+	#[cfg(all(feature = "mpris", target_os = "linux"))]
+	let mpris = mpris::spawn(Arc::clone(&window));
+	#[cfg(not(feature = "tray"))]
+	if config.tray {
+		tracing::warn!("There's no tray icon, since this build doesn't have the tray feature");
+	}
 	let queued = Arc::new(AtomicUsize::new(0));
+	let last_save = Arc::new(Mutex::new(WriteStats::default()));
+	let history = Arc::new(Mutex::new(UndoHistory::default()));
 	let db = Arc::new(db);
 	Ok((
 		Gui {
 			mutations: send_mutations,
 			new_state: recv_db,
 			queued: queued.clone(),
+			last_save: last_save.clone(),
+			history: history.clone(),
 			db: db.clone(),
 			playing: None,
+			seek_drag: None,
+			resume_after_seek: false,
+			volume: 1.0,
 			staged_feed: None,
+			opml_export: None,
+			url_import: None,
+			settings_draft: None,
+			last_refresh: Instant::now(),
+			fetch_permits: Arc::new(Semaphore::new(db.settings.max_concurrent_fetches)),
+			client,
+			fetch_limit: db.settings.max_concurrent_fetches,
+			article_query: String::new(),
+			unread_only: false,
+			article_category: None,
+			raw_html: false,
+			sort_order: SortOrder::default(),
+			global_query: None,
+			global_results: None,
+			starred_only: false,
+			search_starred_only: false,
+			stats_open: false,
+			stats: None,
+			renaming_feed: None,
+			categorizing_feed: None,
+			editing_url: None,
+			editing_auth: None,
+			editing_headers: None,
+			confirming: None,
 			selected_feed: None,
+			article_cursor: None,
+			title_unread: None,
+			backend_stopped: false,
+			shutdown: Some(send_shutdown),
+			window,
+			new_articles,
+			article_hook,
+			#[cfg(all(feature = "mpris", target_os = "linux"))]
+			mpris,
+			#[cfg(feature = "tray")]
+			tray: config.tray.then(tray::Tray::default),
 			jobs: vec![],
+			downloads: vec![],
+			download_dir: config.download_dir.clone(),
 			send_toast: send_toast.clone(),
 			recv_toast,
-			toasts: Toasts::new(),
+			toasts,
 		},
 		Backend {
 			mutations: recv_mutations,
 			new_db: send_db,
+			latest: tokio::sync::watch::channel(db.clone()).0,
 			queued,
-			path,
+			last_save,
+			history,
+			store,
 			db,
 			toast: send_toast,
+			watcher,
+			shutdown: recv_shutdown,
 		},
 	))
 }
@@ -66,182 +290,1041 @@ pub struct Gui {
 	recv_toast: Receiver<(ToastLevel, String)>,
 	send_toast: Sender<(ToastLevel, String)>,
 	queued: Arc<AtomicUsize>,
+	last_save: Arc<Mutex<WriteStats>>,
+	history: Arc<Mutex<UndoHistory>>,
 	db: Arc<Db>,
-	playing: Option<JoinHandle<()>>,
+	playing: Option<Playback>,
+	/// Where the seek slider is while it's being dragged.
+	seek_drag: Option<f64>,
+	/// Whether dragging the seek slider paused playback, so letting go should resume it.
+	resume_after_seek: bool,
+	/// The player's volume, where 1 is 100%.
+	volume: f64,
 	jobs: Vec<JoinHandle<()>>,
+	/// Enclosures being downloaded, shown beside the jobs.
+	downloads: Vec<Download>,
+	download_dir: PathBuf,
 	#[allow(clippy::type_complexity)]
-	staged_feed: Option<(String, JoinHandle<()>, Arc<OnceLock<eyre::Result<Feed>>>)>,
-	selected_feed: Option<(String, Option<Guid>)>,
+	staged_feed: Option<(
+		String,
+		FeedAuth,
+		JoinHandle<()>,
+		Arc<OnceLock<eyre::Result<Feed>>>,
+	)>,
+	/// The settings being edited, while the settings window is open.
+	settings_draft: Option<Settings>,
+	/// When feeds were last refreshed, for the automatic refresh timer.
+	last_refresh: Instant,
+	/// Shared by every feed download, so refreshing many feeds doesn't open a connection to each
+	/// at once.
+	fetch_permits: Arc<Semaphore>,
+	/// Every download goes through this, so connections are reused and carry the config's
+	/// User-Agent and headers.
+	client: FetchClient,
+	/// How many permits `fetch_permits` was made with.
+	fetch_limit: usize,
+	/// Where the "Export OPML" dialog will write to, while it's open.
+	opml_export: Option<String>,
+	url_import: Option<UrlImportDialog>,
+	/// Filters the open feed's article list.
+	article_query: String,
+	/// Hides finished articles from the open feed's list.
+	unread_only: bool,
+	/// Shows only the open feed's articles in this category.
+	article_category: Option<String>,
+	sort_order: SortOrder,
+	/// Shows descriptions as the feed sent them, markup and all, for debugging.
+	raw_html: bool,
+	/// The search across every feed, while that view is open.
+	global_query: Option<String>,
+	global_results: Option<SearchResults>,
+	/// Whether to show only the open feed's starred articles.
+	starred_only: bool,
+	/// Whether the search of all feeds shows only starred articles, as the Starred view does.
+	search_starred_only: bool,
+	/// Whether the statistics window is open.
+	stats_open: bool,
+	/// What the statistics window shows, worked out again only once the DB changes.
+	stats: Option<(Weak<Db>, Stats)>,
+	/// The feed being renamed, and the title typed so far.
+	renaming_feed: Option<(String, String)>,
+	/// The feed whose category is being changed, and the category typed so far.
+	categorizing_feed: Option<(String, String)>,
+	/// The feed being moved to a new URL, and the URL typed so far.
+	editing_url: Option<(String, String)>,
+	/// The feed whose login is being changed, and the login entered so far.
+	editing_auth: Option<(String, FeedAuth)>,
+	/// The feed whose extra headers are being changed, and the text typed so far, one
+	/// `Name: value` per line.
+	editing_headers: Option<(String, String)>,
+	/// A destructive change waiting on the user to confirm it, and the question to ask.
+	confirming: Option<(String, Mutation)>,
+	/// The open feed's URL, and the [`article_key`] of the open article.
+	selected_feed: Option<(String, Option<String>)>,
+	/// Which row of the (filtered) article list the keyboard is on.
+	article_cursor: Option<usize>,
+	/// The unread total last put in the window title.
+	title_unread: Option<usize>,
+	/// Whether the backend has been seen to stop, so nothing sent to it will be saved.
+	backend_stopped: bool,
+	/// Tells the backend to save what's queued and stop. Dropping it does the same.
+	shutdown: Option<oneshot::Sender<()>>,
+	/// The window's context, once it's open, so clicking a notification can bring it forward.
+	window: Arc<OnceLock<eframe::egui::Context>>,
+	/// Where to report new articles, if notifications are on.
+	new_articles: Option<Sender<NewArticles>>,
+	/// Runs the config's new article hook, if it has one.
+	article_hook: Option<Sender<NewArticle>>,
+	/// The desktop's media controls.
+	#[cfg(all(feature = "mpris", target_os = "linux"))]
+	mpris: mpris::Mpris,
+	/// The tray icon, if the config asks for one.
+	#[cfg(feature = "tray")]
+	tray: Option<tray::Tray>,
 	toasts: Toasts,
 }
 
+/// The article list's keys, as pressed this frame.
+#[derive(Default)]
+struct NavKeys {
+	down: bool,
+	up: bool,
+	open: bool,
+	back: bool,
+	toggle_read: bool,
+}
+
+impl NavKeys {
+	/// Reads j/k or the arrows to move, Enter to open, Esc to go back, and R to toggle read. Keys
+	/// typed into a text box don't count.
+	fn read(ctx: &eframe::egui::Context) -> Self {
+		if ctx.wants_keyboard_input() {
+			return Self::default();
+		}
+		ctx.input(|i| Self {
+			down: i.key_pressed(Key::J) || i.key_pressed(Key::ArrowDown),
+			up: i.key_pressed(Key::K) || i.key_pressed(Key::ArrowUp),
+			open: i.key_pressed(Key::Enter),
+			back: i.key_pressed(Key::Escape),
+			toggle_read: i.key_pressed(Key::R),
+		})
+	}
+}
+
+/// The "Import URLs" dialog, while it's open.
+#[derive(Default)]
+struct UrlImportDialog {
+	/// The URLs, pasted in or loaded from `path`.
+	list: String,
+	path: String,
+	/// How each URL went, once the import started from the dialog finishes.
+	#[allow(clippy::type_complexity)]
+	results: Option<Arc<OnceLock<Vec<(String, UrlImport)>>>>,
+}
+
+/// The last search of all feeds, so it's only run again once the query or the DB changes rather
+/// than on every frame.
+struct SearchResults {
+	query: String,
+	starred_only: bool,
+	db: Weak<Db>,
+	/// Each article found, with its feed's URL.
+	found: Vec<(String, Item)>,
+}
+
+/// The DB as it was before each of the last few changes, newest last.
+#[derive(Default)]
+struct UndoHistory {
+	states: VecDeque<Arc<Db>>,
+	/// Set by an undo, so that putting the old state back isn't itself recorded as a change.
+	restoring: bool,
+}
+
+impl UndoHistory {
+	/// Remembers `before` if a batch of mutations changed it into `after`.
+	fn record(&mut self, before: Arc<Db>, after: &Db) {
+		if std::mem::take(&mut self.restoring) || *before == *after {
+			return;
+		}
+		self.states.push_back(before);
+		if self.states.len() > UNDO_DEPTH {
+			self.states.pop_front();
+		}
+	}
+
+	/// Takes the newest state to go back to, if there is one.
+	fn undo(&mut self) -> Option<Arc<Db>> {
+		let state = self.states.pop_back()?;
+		self.restoring = true;
+		Some(state)
+	}
+}
+
+/// The order the open feed's articles are listed in.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+enum SortOrder {
+	/// Whatever order the feed lists them in.
+	#[default]
+	Feed,
+	NewestFirst,
+	OldestFirst,
+}
+
 #[derive(Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct Db {
 	pub feeds: HashMap<String, Feed>,
+	/// Episodes to play next, as (feed URL, [`article_key`]).
+	#[serde(default)]
+	pub queue: Vec<(String, String)>,
+	#[serde(default)]
+	pub settings: Settings,
 }
 
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Feed {
 	pub feed: Channel,
-	/// Table mapping articles to the read fraction. Media articles might be partially read.
-	pub read_articles: HashMap<String, f64>,
+	/// Table mapping articles to how far they were read.
+	pub read_articles: HashMap<String, ReadState>,
+	/// The user's own name for the feed, shown instead of the channel's title.
+	#[serde(default)]
+	pub custom_title: Option<String>,
+	/// When the feed was last downloaded successfully.
+	#[serde(default)]
+	pub last_refreshed: Option<DateTime<Utc>>,
+	/// Validators from the last download, for conditional requests.
+	#[serde(default)]
+	pub etag: Option<String>,
+	#[serde(default)]
+	pub last_modified: Option<String>,
+	/// The folder the feed is listed under, if the user put it in one.
+	#[serde(default)]
+	pub category: Option<String>,
+	/// Whether "Refresh" and automatic refreshes include this feed. Its own refresh button always
+	/// works.
+	#[serde(default = "enabled")]
+	pub refresh_enabled: bool,
+	/// When each read article that's no longer in the feed was first seen to be gone, so its read
+	/// state can be dropped once it has been gone a while.
+	#[serde(default)]
+	pub missing_since: HashMap<String, DateTime<Utc>>,
+	/// What to log in with, for private feeds. Stored as plain text; see [`FeedAuth`].
+	#[serde(default)]
+	pub auth: FeedAuth,
+	/// Headers this feed's host needs, replacing any with the same name from the config.
+	#[serde(default)]
+	pub headers: BTreeMap<String, String>,
+	/// Where articles' enclosures were downloaded to, to play offline.
+	#[serde(default)]
+	pub downloads: HashMap<String, PathBuf>,
+	/// The articles the user starred, to find again later.
+	#[serde(default)]
+	pub starred: HashSet<String>,
+}
+
+fn enabled() -> bool {
+	true
+}
+
+/// How far an article was read.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct ReadState {
+	/// From 0 (untouched) to 1 (done). Media articles might be partially read.
+	pub fraction: f64,
+	/// When it was finished. Missing for articles finished before this was recorded.
+	#[serde(default)]
+	pub read_at: Option<DateTime<Utc>>,
+}
+
+impl ReadState {
+	/// Read to `fraction`, at `now` if that's the whole thing.
+	pub fn at(fraction: f64, now: DateTime<Utc>) -> Self {
+		Self {
+			fraction,
+			read_at: (fraction >= 1.0).then_some(now),
+		}
+	}
+}
+
+impl Default for Feed {
+	fn default() -> Self {
+		Self {
+			feed: Channel::default(),
+			read_articles: HashMap::new(),
+			custom_title: None,
+			last_refreshed: None,
+			etag: None,
+			last_modified: None,
+			category: None,
+			refresh_enabled: true,
+			missing_since: HashMap::new(),
+			downloads: HashMap::new(),
+			starred: HashSet::new(),
+			auth: FeedAuth::None,
+			headers: BTreeMap::new(),
+		}
+	}
+}
+
+impl Feed {
+	pub fn title(&self) -> &str {
+		self.custom_title.as_deref().unwrap_or(&self.feed.title)
+	}
+
+	/// How many articles haven't been read to the end.
+	pub fn unread(&self) -> usize {
+		self.feed
+			.items
+			.iter()
+			.filter(|i| completion(self, &article_key(i)) < 1.0)
+			.count()
+	}
+
+	/// How many of the feed's articles are starred.
+	pub fn starred_count(&self) -> usize {
+		self.feed
+			.items
+			.iter()
+			.filter(|i| self.starred.contains(article_key(i).as_ref()))
+			.count()
+	}
+
+	/// Records the article as read to `fraction`. Finishing it again keeps when it was first
+	/// finished.
+	pub fn set_read(&mut self, guid: String, fraction: f64, now: DateTime<Utc>) {
+		let read = self.read_articles.entry(guid).or_default();
+		if fraction < 1.0 || read.fraction < 1.0 {
+			*read = ReadState::at(fraction, now);
+		}
+	}
+
+	/// Takes in what refreshing the feed downloaded, keeping its read state and title.
+	pub(crate) fn refreshed(
+		&mut self,
+		fetched: Fetched,
+		now: DateTime<Utc>,
+		grace: chrono::Duration,
+	) {
+		if let Fetched::Changed {
+			channel,
+			etag,
+			last_modified,
+		} = fetched
+		{
+			self.feed = *channel;
+			self.etag = etag;
+			self.last_modified = last_modified;
+		}
+		self.last_refreshed = Some(now);
+		self.prune_read_articles(now, grace);
+	}
+
+	/// Forgets the read state of articles that have been gone from the feed for longer than
+	/// `grace`. Feeds only list their latest articles, so without this it would pile up forever.
+	pub fn prune_read_articles(&mut self, now: DateTime<Utc>, grace: chrono::Duration) {
+		let current: HashSet<_> = self.feed.items.iter().map(article_key).collect();
+		let Feed {
+			read_articles,
+			missing_since,
+			..
+		} = self;
+		// Articles that came back keep their read state.
+		missing_since.retain(|key, _| !current.contains(key.as_str()));
+		for key in read_articles.keys() {
+			if !current.contains(key.as_str()) {
+				missing_since.entry(key.clone()).or_insert(now);
+			}
+		}
+		missing_since.retain(|key, since| {
+			let expired = now - *since > grace;
+			if expired {
+				read_articles.remove(key);
+			}
+			!expired && read_articles.contains_key(key)
+		});
+	}
 }
 
 impl Gui {
+	/// Reopens the feed and article that were open when the app last closed, if they're still
+	/// there, and lists articles the way they were. Missing or unreadable saved state just leaves
+	/// the feed list open.
+	pub fn restore(&mut self, storage: Option<&dyn eframe::Storage>) {
+		let view: Option<(SortOrder, bool)> =
+			storage.and_then(|storage| eframe::get_value(storage, LIST_VIEW_KEY));
+		if let Some((sort_order, unread_only)) = view {
+			self.sort_order = sort_order;
+			self.unread_only = unread_only;
+		}
+		// Saved as an `Option` itself, since no feed being open is worth remembering too.
+		let selected: Option<Option<(String, Option<String>)>> =
+			storage.and_then(|storage| eframe::get_value(storage, SELECTED_FEED_KEY));
+		self.selected_feed = selected.flatten().and_then(|(url, article)| {
+			let feed = self.db.feeds.get(&url)?;
+			let article =
+				article.filter(|key| feed.feed.items.iter().any(|a| article_key(a) == *key));
+			Some((url, article))
+		});
+	}
+
+	/// Queues a change for the backend. If the backend has stopped, the change is dropped; `update`
+	/// tells the user once rather than every send failing loudly.
 	fn send_mutation(&self, mutation: Mutation) {
 		let mutations = self.mutations.clone();
 		tokio::spawn(async move {
-			mutations.send(mutation).await.unwrap();
+			let _ = mutations.send(mutation).await;
 		});
 	}
 
 	fn status_line(&mut self, ctx: &eframe::egui::Context) {
+		let unread: usize = self.db.feeds.values().map(Feed::unread).sum();
+		if self.title_unread != Some(unread) {
+			self.title_unread = Some(unread);
+			ctx.send_viewport_cmd(ViewportCommand::Title(match unread {
+				0 => "Winter2".to_string(),
+				n => format!("Winter2 ({n})"),
+			}));
+		}
 		TopBottomPanel::top("status").show(ctx, |ui| {
 			ui.horizontal(|ui| {
+				ui.label(format!("{unread} unread"));
+				ui.separator();
 				ui.label(format!(
 					"M: {}; J: {}",
 					self.queued.load(Ordering::Relaxed),
 					self.jobs.len()
 				));
+				for download in &self.downloads {
+					ui.label(format!(
+						"D: {} ({})",
+						download.episode.title,
+						download.progress.describe()
+					))
+					.on_hover_text("Downloading");
+				}
+				if self.backend_stopped {
+					ui.colored_label(ui.visuals().error_fg_color, "Not saving");
+				}
+				let last_save = *self.last_save.lock().unwrap();
+				ui.label(format!(
+					"W: {}+{}d-{} ({} B)",
+					last_save.files_written,
+					last_save.dirs_created,
+					last_save.files_removed,
+					last_save.bytes
+				))
+				.on_hover_text("Files written, dirs created, and entries removed by the last save");
 				ui.separator();
 				if ui.button("New Feed").clicked() {
-					self.staged_feed =
-						Some((String::new(), tokio::spawn(async {}), Default::default()));
+					self.staged_feed = Some((
+						String::new(),
+						FeedAuth::None,
+						tokio::spawn(async {}),
+						Default::default(),
+					));
 				}
 				if ui.button("Refresh").clicked() {
 					self.refresh();
 				}
-				if let Some(jh) = &self.playing {
-					if ui.button("STOP").clicked() {
-						jh.abort();
-						self.playing = None;
-					}
+				let can_undo = !self.history.lock().unwrap().states.is_empty();
+				if ui
+					.add_enabled(can_undo, Button::new("Undo"))
+					.on_hover_text("Ctrl+Z")
+					.clicked()
+				{
+					self.undo();
+				}
+				if ui.button("Settings").clicked() {
+					self.settings_draft = Some(self.db.settings.clone());
+				}
+				if ui.button("Search").clicked() {
+					self.global_query = Some(String::new());
+					self.search_starred_only = false;
+				}
+				if ui.button("Starred").clicked() {
+					self.global_query = Some(String::new());
+					self.search_starred_only = true;
+				}
+				if ui.button("Stats").clicked() {
+					self.stats_open = !self.stats_open;
+				}
+				if ui.button("Import URLs").clicked() {
+					self.url_import = Some(UrlImportDialog::default());
+				}
+				if ui.button("Export OPML").clicked() {
+					self.opml_export = Some("subscriptions.opml".to_string());
 				}
 			});
 		});
 	}
 
-	fn refresh(&mut self) {
-		for (url, feed) in self.db.feeds.iter() {
-			let url = url.clone();
-			let read_articles = feed.read_articles.clone();
-			let send_toast = self.send_toast.clone();
-			let send_mutation = self.mutations.clone();
-			self.jobs.push(tokio::spawn(async move {
-				let response = match reqwest::get(&url).await {
-					Err(e) => {
-						send_toast
-							.send((
-								ToastLevel::Error,
-								format!("Downloading feed {url} failed with {e}"),
-							))
-							.await
-							.unwrap();
-						return;
+	/// Starts playing the episode in VLC, closing whatever was playing before. It's taken off the
+	/// queue if it was on there. A downloaded copy is played instead of streaming it.
+	fn play(&mut self, mut episode: Episode) {
+		if let Some(previous) = self.playing.take() {
+			previous.stop();
+		}
+		let local = self
+			.db
+			.feeds
+			.get(&episode.feed_url)
+			.and_then(|feed| downloaded(feed, &episode.guid));
+		if let Some(path) = local {
+			episode.url = path.display().to_string();
+		}
+		let entry = (episode.feed_url.clone(), episode.guid.clone());
+		self.send_mutation(Box::new(move |db, _| {
+			db.queue.retain(|queued| *queued != entry);
+			Ok(())
+		}));
+		self.playing = Some(Playback::start(
+			episode,
+			self.volume,
+			self.db.settings.playback_rate,
+			self.send_toast.clone(),
+			self.mutations.clone(),
+		));
+	}
+
+	/// Downloads the episode's enclosure so it can be played offline, picking up where an earlier
+	/// attempt left off.
+	fn download(&mut self, episode: Episode) {
+		let running = self.downloads.iter().any(|download| {
+			download.episode.feed_url == episode.feed_url && download.episode.guid == episode.guid
+		});
+		if running {
+			return;
+		}
+		let path = local_path(
+			&self.download_dir,
+			&episode.feed_url,
+			&episode.guid,
+			&episode.url,
+		);
+		let auth = self
+			.db
+			.feeds
+			.get(&episode.feed_url)
+			.map(|feed| feed.auth.clone())
+			.unwrap_or_default();
+		let progress = Arc::new(Progress::default());
+		let client = self.client.clone();
+		let send_toast = self.send_toast.clone();
+		let send_mutation = self.mutations.clone();
+		let span = tracing::info_span!("download", url = %episode.url);
+		let job = {
+			let episode = episode.clone();
+			let progress = progress.clone();
+			async move {
+				if let Err(e) = download(&client, &episode.url, &auth, &path, &progress).await {
+					tracing::warn!(error = %e, "Downloading failed");
+					let _ = send_toast
+						.send((
+							ToastLevel::Error,
+							format!(
+								"Downloading {} failed: {e}. What it got is kept to resume from.",
+								episode.title
+							),
+						))
+						.await;
+					return;
+				}
+				let Episode {
+					feed_url,
+					guid,
+					title,
+					..
+				} = episode;
+				let _ = send_mutation
+					.send(Box::new(move |db, toast| {
+						if let Some(feed) = db.feeds.get_mut(&feed_url) {
+							feed.downloads.insert(guid, path);
+						}
+						let _ =
+							toast.try_send((ToastLevel::Success, format!("Downloaded {title}")));
+						Ok(())
+					}))
+					.await;
+			}
+		};
+		self.downloads.push(Download {
+			episode,
+			progress,
+			task: tokio::spawn(job.instrument(span)),
+		});
+	}
+
+	/// Deletes the episode's downloaded enclosure, or what a failed download left of it.
+	fn discard_download(&mut self, episode: Episode) {
+		let path = local_path(
+			&self.download_dir,
+			&episode.feed_url,
+			&episode.guid,
+			&episode.url,
+		);
+		// It may have been downloaded before the download directory moved.
+		let recorded = self
+			.db
+			.feeds
+			.get(&episode.feed_url)
+			.and_then(|feed| feed.downloads.get(&episode.guid))
+			.cloned();
+		for file in [partial_path(&path), path].into_iter().chain(recorded) {
+			match std::fs::remove_file(&file) {
+				Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+					self.toasts
+						.error(format!("Couldn't delete {}: {e}", file.display()));
+				}
+				_ => {}
+			}
+		}
+		let Episode { feed_url, guid, .. } = episode;
+		self.send_mutation(Box::new(move |db, _| {
+			if let Some(feed) = db.feeds.get_mut(&feed_url) {
+				feed.downloads.remove(&guid);
+			}
+			Ok(())
+		}));
+	}
+
+	/// Plays the first queued episode that still exists.
+	fn play_next(&mut self) {
+		let next = self
+			.db
+			.queue
+			.iter()
+			.find_map(|(feed_url, guid)| episode(&self.db, feed_url, guid));
+		if let Some(next) = next {
+			self.play(next);
+		}
+	}
+
+	/// Does what was picked from the tray icon's menu, and sends the window to the tray rather than
+	/// closing it.
+	#[cfg(feature = "tray")]
+	fn tray(&mut self, ctx: &eframe::egui::Context) {
+		use tray::TrayCommand;
+		let Some(tray) = &mut self.tray else {
+			return;
+		};
+		let unread = self.db.feeds.values().map(Feed::unread).sum();
+		let mut refresh = false;
+		let mut play_pause = false;
+		for command in tray.update(ctx, unread) {
+			match command {
+				TrayCommand::Refresh => refresh = true,
+				TrayCommand::PlayPause => play_pause = !play_pause,
+				TrayCommand::ToggleWindow if tray::Tray::can_hide() => {
+					tray.hidden = !tray.hidden;
+					ctx.send_viewport_cmd(ViewportCommand::Visible(!tray.hidden));
+					if !tray.hidden {
+						ctx.send_viewport_cmd(ViewportCommand::Focus);
 					}
-					Ok(v) => v,
-				};
-				let bytes = match response.bytes().await {
-					Err(e) => {
-						send_toast
-							.send((
-								ToastLevel::Error,
-								format!("Reading feed {url} failed with {e}"),
-							))
-							.await
-							.unwrap();
-						return;
+				}
+				TrayCommand::ToggleWindow => ctx.send_viewport_cmd(ViewportCommand::Focus),
+				TrayCommand::Quit => {
+					tray.quitting = true;
+					ctx.send_viewport_cmd(ViewportCommand::Close);
+				}
+			}
+		}
+		// Without an icon to click there'd be no getting the window back.
+		if ctx.input(|i| i.viewport().close_requested())
+			&& !tray.quitting
+			&& tray.is_up()
+			&& tray::Tray::can_hide()
+		{
+			ctx.send_viewport_cmd(ViewportCommand::CancelClose);
+			ctx.send_viewport_cmd(ViewportCommand::Visible(false));
+			tray.hidden = true;
+		}
+		if refresh && self.jobs.is_empty() {
+			self.refresh();
+		}
+		if play_pause {
+			match &self.playing {
+				Some(playback) => playback.send(PlayerCommand::TogglePause),
+				None => self.play_next(),
+			}
+		}
+	}
+
+	/// Does what the desktop's media controls asked, and shows them what's playing now.
+	#[cfg(all(feature = "mpris", target_os = "linux"))]
+	fn media_controls(&mut self) {
+		use mpris::{MprisCommand, NowPlaying};
+		while let Ok(command) = self.mpris.commands.try_recv() {
+			let Some(playback) = &self.playing else {
+				if matches!(command, MprisCommand::Play | MprisCommand::Next) {
+					self.play_next();
+				}
+				continue;
+			};
+			let status = playback.status();
+			match command {
+				MprisCommand::PlayPause => playback.send(PlayerCommand::TogglePause),
+				MprisCommand::Play if status.paused => playback.send(PlayerCommand::TogglePause),
+				MprisCommand::Pause if !status.paused => playback.send(PlayerCommand::TogglePause),
+				MprisCommand::Play | MprisCommand::Pause => {}
+				MprisCommand::Stop => playback.stop(),
+				MprisCommand::Next => self.play_next(),
+				// The queue only holds what's still to come, so back means from the top.
+				MprisCommand::Previous => playback.send(PlayerCommand::Seek(0.0)),
+				MprisCommand::Jump(seconds) => playback.send(PlayerCommand::Jump(seconds)),
+				MprisCommand::SetPosition(seconds) if status.length > 0.0 => {
+					playback.send(PlayerCommand::Seek(seconds / status.length))
+				}
+				MprisCommand::SetPosition(_) => {}
+			}
+		}
+		let now = self.playing.as_ref().map(|playback| {
+			let status = playback.status();
+			let playing = &playback.episode;
+			NowPlaying {
+				title: playing.title.clone(),
+				feed: self
+					.db
+					.feeds
+					.get(&playing.feed_url)
+					.map(|feed| feed.feed.title.clone())
+					.unwrap_or_default(),
+				url: playing.url.clone(),
+				time: status.time,
+				length: status.length,
+				paused: status.paused,
+				rate: self.db.settings.playback_rate,
+				volume: self.volume,
+				can_go_next: self
+					.db
+					.queue
+					.iter()
+					.any(|(feed_url, guid)| episode(&self.db, feed_url, guid).is_some()),
+			}
+		});
+		self.mpris.update(now);
+	}
+
+	fn now_playing(&mut self, ctx: &eframe::egui::Context) {
+		let Some(playback) = &self.playing else {
+			return;
+		};
+		let status = playback.status();
+		let position = match self.seek_drag {
+			Some(fraction) => fraction,
+			None if status.length > 0.0 => status.time / status.length,
+			None => 0.0,
+		};
+		let mut new_rate = None;
+		TopBottomPanel::bottom("now_playing").show(ctx, |ui| {
+			ui.horizontal(|ui| {
+				ui.label(RichText::new(&playback.episode.title).strong());
+				ui.label(format!(
+					"{} / {}",
+					clock(position * status.length),
+					clock(status.length)
+				));
+				ui.separator();
+				let mut percent = self.volume * 100.0;
+				let volume = ui.add(
+					Slider::new(&mut percent, 0.0..=100.0)
+						.text("Volume")
+						.suffix("%")
+						.fixed_decimals(0),
+				);
+				if volume.changed() {
+					self.volume = percent / 100.0;
+					playback.send(PlayerCommand::SetVolume(self.volume));
+				}
+				let mut rate = self.db.settings.playback_rate;
+				ComboBox::from_label("Speed")
+					.selected_text(format!("{rate}x"))
+					.show_ui(ui, |ui| {
+						for option in PLAYBACK_RATES {
+							ui.selectable_value(&mut rate, option, format!("{option}x"));
+						}
+					});
+				if rate != self.db.settings.playback_rate {
+					playback.send(PlayerCommand::SetRate(rate));
+					new_rate = Some(rate);
+				}
+			});
+			ui.horizontal(|ui| {
+				if ui.button("-15s").clicked() {
+					playback.send(PlayerCommand::Jump(-15.0));
+				}
+				if ui
+					.button(if status.paused { "Play" } else { "Pause" })
+					.clicked()
+				{
+					playback.send(PlayerCommand::TogglePause);
+				}
+				if ui.button("+30s").clicked() {
+					playback.send(PlayerCommand::Jump(30.0));
+				}
+				if ui.button("STOP").clicked() {
+					playback.stop();
+				}
+				let mut fraction = position;
+				ui.spacing_mut().slider_width = ui.available_width();
+				let slider = ui.add_enabled(
+					status.length > 0.0,
+					Slider::new(&mut fraction, 0.0..=1.0).show_value(false),
+				);
+				if slider.drag_started() && !status.paused {
+					playback.send(PlayerCommand::TogglePause);
+					self.resume_after_seek = true;
+				}
+				if slider.changed() {
+					playback.send(PlayerCommand::Seek(fraction));
+					if slider.dragged() {
+						self.seek_drag = Some(fraction);
 					}
-					Ok(v) => v,
-				};
-				let feed = match rss::Channel::read_from(&bytes[..]) {
-					Err(e) => {
-						send_toast
-							.send((
-								ToastLevel::Error,
-								format!("Parsing feed {url} failed with {e}"),
-							))
-							.await
-							.unwrap();
-						return;
+				}
+				if slider.drag_released() {
+					self.seek_drag = None;
+					if std::mem::take(&mut self.resume_after_seek) {
+						playback.send(PlayerCommand::TogglePause);
 					}
-					Ok(v) => v,
-				};
-				let feed = Feed {
-					feed,
-					read_articles,
-				};
-				send_mutation
-					.send(Box::new(move |db, _| {
-						db.feeds.insert(url, feed);
-						Ok(())
-					}))
-					.await
-					.unwrap();
-			}))
+				}
+			});
+		});
+		if let Some(rate) = new_rate {
+			self.send_mutation(Box::new(move |db, _| {
+				db.settings.playback_rate = rate;
+				Ok(())
+			}));
+		}
+		ctx.request_repaint_after(Duration::from_millis(500));
+	}
+
+	fn queue_panel(&mut self, ctx: &eframe::egui::Context) {
+		if self.db.queue.is_empty() && self.playing.is_none() {
+			return;
+		}
+		let db = self.db.clone();
+		let mut play_next = false;
+		SidePanel::right("queue").show(ctx, |ui| {
+			ui.heading("Queue");
+			if let Some(playback) = &self.playing {
+				ui.label(RichText::new(format!("▶ {}", playback.episode.title)).strong());
+			}
+			ScrollArea::vertical().show(ui, |ui| {
+				for (n, (feed_url, guid)) in db.queue.iter().enumerate() {
+					let title = episode(&db, feed_url, guid)
+						.map_or_else(|| "(no longer in the feed)".to_string(), |e| e.title);
+					ui.horizontal(|ui| {
+						ui.label(title);
+						let entry = (feed_url.clone(), guid.clone());
+						if n > 0 && ui.button("^").clicked() {
+							move_in_queue(&self.mutations, entry.clone(), -1);
+						}
+						if n + 1 < db.queue.len() && ui.button("v").clicked() {
+							move_in_queue(&self.mutations, entry.clone(), 1);
+						}
+						if ui.button("x").clicked() {
+							self.send_mutation(Box::new(move |db, _| {
+								db.queue.retain(|queued| *queued != entry);
+								Ok(())
+							}));
+						}
+					});
+				}
+			});
+			play_next = self.playing.is_none() && ui.button("Play queue").clicked();
+		});
+		if play_next {
+			self.play_next();
+		}
+	}
+
+	fn copy(&mut self, ctx: &eframe::egui::Context, text: String) {
+		self.toasts.info(format!("Copied {text}"));
+		ctx.copy_text(text);
+	}
+
+	/// Opens `url` in the default browser, toasting if there isn't one.
+	fn open_link(&mut self, url: String) {
+		let send_toast = self.send_toast.clone();
+		tokio::task::spawn_blocking(move || {
+			if let Err(e) = webbrowser::open(&url) {
+				let _ = send_toast
+					.blocking_send((ToastLevel::Error, format!("Opening {url} failed with {e}")));
+			}
+		});
+	}
+
+	fn refresh(&mut self) {
+		self.last_refresh = Instant::now();
+		let urls: Vec<_> = self
+			.db
+			.feeds
+			.iter()
+			.filter(|(_, feed)| feed.refresh_enabled)
+			.map(|(url, _)| url.clone())
+			.collect();
+		tracing::info!(feeds = urls.len(), "Refreshing all feeds");
+		for url in urls {
+			self.fetch_feed(url, false);
+		}
+	}
+
+	/// Re-downloads just the one feed, and says so once it's done.
+	fn refresh_feed(&mut self, url: &str) {
+		self.fetch_feed(url.to_string(), true);
+	}
+
+	/// Downloads `url` and replaces the stored channel, keeping the feed's read state and title.
+	/// Failures are always toasted; success only if `announce`, so a full refresh isn't noisy.
+	fn fetch_feed(&mut self, url: String, announce: bool) {
+		let options = self.fetch_options(&url);
+		self.fetch_feed_with(url, options, announce);
+	}
+
+	/// How the stored feed at `url` is downloaded.
+	fn fetch_options(&self, url: &str) -> FetchOptions {
+		self.db
+			.feeds
+			.get(url)
+			.map(FetchOptions::for_feed)
+			.unwrap_or_default()
+	}
+
+	/// Like [`Self::fetch_feed`], but with `options` rather than the stored feed's, for trying out
+	/// a change to them before it's been saved.
+	fn fetch_feed_with(&mut self, url: String, options: FetchOptions, announce: bool) {
+		let limit = self.db.settings.max_concurrent_fetches.max(1);
+		if limit != self.fetch_limit {
+			// Downloads already running keep their permits from the old semaphore.
+			self.fetch_permits = Arc::new(Semaphore::new(limit));
+			self.fetch_limit = limit;
 		}
+		let permits = self.fetch_permits.clone();
+		let client = self.client.clone();
+		let send_toast = self.send_toast.clone();
+		let send_mutation = self.mutations.clone();
+		let new_articles = self.new_articles.clone();
+		let article_hook = self.article_hook.clone();
+		let span = tracing::info_span!("fetch", %url);
+		let job = async move {
+			let attempt = || async {
+				// Held per attempt, so a feed waiting to retry doesn't hold up the others.
+				let _permit = permits.acquire().await.expect("fetch semaphore closed");
+				fetch_channel(&client, &url, &options).await
+			};
+			let fetched = match with_retries(BACKOFF, attempt).await {
+				Ok(v) => v,
+				Err((e, attempts)) => {
+					tracing::warn!(attempts, error = %e, "Refreshing failed");
+					let _ = send_toast
+						.send((
+							ToastLevel::Error,
+							format!("Refreshing feed {url} failed after {attempts} attempts: {e}"),
+						))
+						.await;
+					return;
+				}
+			};
+			let _ = send_mutation
+				.send(Box::new(move |db, toast| {
+					let grace = db.settings.read_state_grace();
+					// The feed may have been deleted while it was downloading.
+					if let Some(feed) = db.feeds.get_mut(&url) {
+						let known: HashSet<_> = feed
+							.feed
+							.items
+							.iter()
+							.map(|item| article_key(item).into_owned())
+							.collect();
+						feed.refreshed(fetched, Utc::now(), grace);
+						let new: Vec<_> = feed
+							.feed
+							.items
+							.iter()
+							.filter(|item| !known.contains(article_key(item).as_ref()))
+							.collect();
+						// A feed that was empty is most likely new, and everything in it would
+						// count.
+						let announce_new = !new.is_empty() && !known.is_empty();
+						if let Some(send) = new_articles.filter(|_| announce_new) {
+							let _ = send.try_send((feed.title().to_string(), new.len()));
+						}
+						if let Some(send) = article_hook.as_ref().filter(|_| announce_new) {
+							for item in &new {
+								let _ = send.try_send(NewArticle {
+									feed_title: feed.title().to_string(),
+									feed_url: url.clone(),
+									title: item.title().unwrap_or_default().to_string(),
+									link: item.link().unwrap_or_default().to_string(),
+								});
+							}
+						}
+						if announce {
+							let _ = toast.try_send((
+								ToastLevel::Success,
+								format!("Refreshed {}", feed.title()),
+							));
+						}
+					}
+					Ok(())
+				}))
+				.await;
+		};
+		self.jobs.push(tokio::spawn(job.instrument(span)));
 	}
 
 	fn new_feed_editor(&mut self, ctx: &eframe::egui::Context) {
-		if let Some((url, jh, info)) = &mut self.staged_feed {
+		if let Some((url, auth, jh, info)) = &mut self.staged_feed {
 			let mut clear_feed = false;
 			let mut commit = false;
 			SidePanel::left("new_feed").show(ctx, |ui| {
 				ui.heading("New Feed");
-				if ui.text_edit_singleline(url).changed() {
+				let mut edited = ui.text_edit_singleline(url).changed();
+				edited |= auth_editor(ui, auth);
+				match info.get() {
+					Some(Ok(f)) => {
+						ui.label(format!(
+							"Feed {} retrieved OK, {} articles.",
+							f.feed.title,
+							f.feed.items.len()
+						));
+						if self.db.feeds.contains_key(url.as_str()) {
+							ui.label("You're already subscribed to this feed.");
+							commit = ui.button("Replace (keep read state)").clicked();
+						} else {
+							commit = ui.button("Commit").clicked();
+						}
+					}
+					Some(Err(e)) => {
+						ui.label(format!("Feed NOT OK, {e}"));
+						if let Some(FetchError::NotAFeed(feeds)) = e.downcast_ref() {
+							if !feeds.is_empty() {
+								ui.label("It links to these feeds:");
+							}
+							for feed in feeds {
+								if ui.button(feed).clicked() {
+									*url = feed.clone();
+									edited = true;
+								}
+							}
+						}
+					}
+					None => {
+						ui.spinner();
+					}
+				}
+				if edited {
 					jh.abort();
 					*info = Default::default();
 					*jh = tokio::spawn({
 						let url = url.clone();
+						let auth = auth.clone();
 						let info = info.clone();
+						let client = self.client.clone();
 						async move {
-							let response = match reqwest::get(url).await {
-								Ok(v) => v,
-								Err(e) => {
-									info.get_or_init(move || Err(e.into()));
-									return;
-								}
-							};
-							let bytes = match response.bytes().await {
-								Ok(v) => v,
-								Err(e) => {
-									info.get_or_init(move || Err(e.into()));
-									return;
-								}
-							};
-							let channel = match Channel::read_from(BufReader::new(&bytes[..])) {
-								Ok(v) => v,
-								Err(e) => {
-									info.get_or_init(move || Err(e.into()));
-									return;
-								}
-							};
-							info.get_or_init(move || {
-								Ok(Feed {
-									feed: channel,
-									read_articles: HashMap::default(),
-								})
-							});
+							let result = fetch_new_feed(&client, &url, auth).await;
+							info.get_or_init(move || result);
 						}
 					});
 				}
-				if let Some(feed) = info.get() {
-					match feed {
-						Ok(f) => {
-							ui.label(format!(
-								"Feed {} retrieved OK, {} articles.",
-								f.feed.title,
-								f.feed.items.len()
-							));
-							commit = ui.button("Commit").clicked();
-						}
-						Err(e) => {
-							ui.label(format!("Feed NOT OK, {e}"));
-						}
-					}
-				} else {
-					ui.spinner();
-				}
 				clear_feed = ui.button("Cancel").clicked();
 			});
 			if commit {
@@ -251,7 +1334,7 @@ impl Gui {
 				};
 				let feed = feed.clone();
 				self.send_mutation(Box::new(move |state, _| {
-					state.feeds.insert(url, feed);
+					add_feed(state, url, feed);
 					Ok(())
 				}));
 			}
@@ -261,53 +1344,737 @@ impl Gui {
 		}
 	}
 
-	fn feed_picker(&mut self, ui: &mut eframe::egui::Ui) {
-		for (url, feed) in self.db.feeds.iter() {
+	/// Refreshes once the interval has passed, unless the last refresh is still running. Returns how
+	/// long until the next check is due.
+	fn auto_refresh(&mut self) -> Option<Duration> {
+		let settings = &self.db.settings;
+		if !settings.auto_refresh {
+			return None;
+		}
+		let interval = settings.refresh_interval();
+		let elapsed = self.last_refresh.elapsed();
+		if elapsed < interval {
+			return Some(interval - elapsed);
+		}
+		if self.jobs.is_empty() {
+			self.refresh();
+		}
+		Some(interval)
+	}
+
+	fn settings_editor(&mut self, ctx: &eframe::egui::Context) {
+		let Some(settings) = &mut self.settings_draft else {
+			return;
+		};
+		let mut close = false;
+		let mut save = false;
+		Window::new("Settings").collapsible(false).show(ctx, |ui| {
+			ui.checkbox(&mut settings.auto_refresh, "Refresh feeds automatically");
 			ui.horizontal(|ui| {
-				ui.heading(&feed.feed.title);
-				let total = feed.feed.items.len();
-				let completed = feed
-					.feed
-					.items
-					.iter()
-					.filter(|i| {
-						feed.read_articles
-							.get(i.guid().map(|g| g.value()).unwrap_or("???"))
-							.copied()
-							.unwrap_or(0.0) >= 1.0
-					})
-					.count();
-				ui.label(format!("{completed}/{total}"));
-				if ui.button(">").clicked() {
-					self.selected_feed = Some((url.clone(), None));
+				ui.label("Every");
+				ui.add(
+					DragValue::new(&mut settings.refresh_interval_mins)
+						.clamp_range(1..=24 * 60)
+						.suffix(" min"),
+				);
+			});
+			ui.horizontal(|ui| {
+				ui.label("Download at most");
+				ui.add(DragValue::new(&mut settings.max_concurrent_fetches).clamp_range(1..=64));
+				ui.label("feeds at once");
+			});
+			ui.horizontal(|ui| {
+				ui.label("Remember read articles for");
+				ui.add(
+					DragValue::new(&mut settings.read_state_grace_days)
+						.clamp_range(1..=3650)
+						.suffix(" days"),
+				)
+				.on_hover_text("After they drop out of their feed");
+			});
+			ui.horizontal(|ui| {
+				ui.label("Theme");
+				for (theme, label) in [
+					(Theme::System, "Follow system"),
+					(Theme::Dark, "Dark"),
+					(Theme::Light, "Light"),
+				] {
+					ui.selectable_value(&mut settings.theme, theme, label);
 				}
 			});
-			CollapsingHeader::new("Description")
-				.id_source(url)
-				.show(ui, |ui| {
-					ui.label(feed.feed.description());
-				});
-			ui.separator();
+			ui.horizontal(|ui| {
+				save = ui.button("Save").clicked();
+				close = ui.button("Cancel").clicked();
+			});
+		});
+		if save {
+			let settings = settings.clone();
+			self.send_mutation(Box::new(move |db, _| {
+				db.settings = settings;
+				Ok(())
+			}));
+		}
+		if save || close {
+			self.settings_draft = None;
 		}
 	}
-}
 
-impl eframe::App for Gui {
-	fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
-		while let Ok(new_db) = self.new_state.try_recv() {
-			self.db = new_db;
+	fn stats_window(&mut self, ctx: &eframe::egui::Context) {
+		if !self.stats_open {
+			self.stats = None;
+			return;
+		}
+		let db = Arc::downgrade(&self.db);
+		if !self.stats.as_ref().is_some_and(|(of, _)| of.ptr_eq(&db)) {
+			self.stats = Some((db, Stats::of(&self.db, Utc::now())));
+		}
+		let (_, stats) = self.stats.as_ref().unwrap();
+		Window::new("Statistics")
+			.open(&mut self.stats_open)
+			.vscroll(true)
+			.show(ctx, |ui| stats.show(ui));
+	}
+
+	fn url_importer(&mut self, ctx: &eframe::egui::Context) {
+		let Some(dialog) = &mut self.url_import else {
+			return;
+		};
+		let mut close = false;
+		let mut import = false;
+		Window::new("Import URLs")
+			.collapsible(false)
+			.show(ctx, |ui| {
+				ui.horizontal(|ui| {
+					ui.label("File");
+					ui.text_edit_singleline(&mut dialog.path);
+					if ui.button("Load").clicked() {
+						match std::fs::read_to_string(&dialog.path) {
+							Ok(list) => dialog.list = list,
+							Err(e) => {
+								self.toasts
+									.error(format!("Reading {} failed with {e}", dialog.path));
+							}
+						}
+					}
+				});
+				ui.add(
+					TextEdit::multiline(&mut dialog.list)
+						.hint_text("One feed URL per line, # for comments"),
+				);
+				let running = match dialog.results.as_ref().map(|results| results.get()) {
+					Some(None) => {
+						ui.spinner();
+						true
+					}
+					Some(Some(results)) => {
+						ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+							for (url, result) in results {
+								ui.label(format!("{url}: {result}"));
+							}
+						});
+						false
+					}
+					None => false,
+				};
+				ui.horizontal(|ui| {
+					let urls = parse_url_list(&dialog.list).len();
+					import = ui
+						.add_enabled(
+							!running && urls > 0,
+							Button::new(format!("Import {urls} feeds")),
+						)
+						.clicked();
+					close = ui.button("Close").clicked();
+				});
+			});
+		if import {
+			let results = Arc::new(OnceLock::new());
+			dialog.results = Some(results.clone());
+			let urls = parse_url_list(&dialog.list);
+			let db = self.db.clone();
+			let client = self.client.clone();
+			let send_mutation = self.mutations.clone();
+			let send_toast = self.send_toast.clone();
+			self.jobs.push(tokio::spawn(async move {
+				let limit = db.settings.max_concurrent_fetches;
+				let fetched =
+					fetch_url_list(&client, urls, |url| db.feeds.contains_key(url), limit).await;
+				let mut feeds = vec![];
+				let mut report = vec![];
+				for (url, result, feed) in fetched {
+					if let Some(feed) = feed {
+						feeds.push((url.clone(), feed));
+					}
+					report.push((url, result));
+				}
+				let added = feeds.len();
+				let failed = report
+					.iter()
+					.filter(|(_, result)| matches!(result, UrlImport::Failed(_)))
+					.count();
+				// Added the way the new feed editor adds one, all in one change.
+				let _ = send_mutation
+					.send(Box::new(move |db, _| {
+						for (url, feed) in feeds {
+							add_feed(db, url, feed);
+						}
+						Ok(())
+					}))
+					.await;
+				let level = if failed > 0 {
+					ToastLevel::Warning
+				} else {
+					ToastLevel::Success
+				};
+				let _ = send_toast
+					.send((
+						level,
+						format!("Imported {added} feeds from the list, {failed} failed"),
+					))
+					.await;
+				results.get_or_init(|| report);
+			}));
+		}
+		if close {
+			self.url_import = None;
+		}
+	}
+
+	fn opml_exporter(&mut self, ctx: &eframe::egui::Context) {
+		let Some(path) = &mut self.opml_export else {
+			return;
+		};
+		let mut close = false;
+		Window::new("Export OPML")
+			.collapsible(false)
+			.show(ctx, |ui| {
+				ui.horizontal(|ui| {
+					ui.label("Path");
+					ui.text_edit_singleline(path);
+				});
+				ui.horizontal(|ui| {
+					if ui.button("Save").clicked() {
+						close = true;
+						let toast = match std::fs::write(&*path, to_opml(&self.db)) {
+							Ok(()) => Toast::success(format!(
+								"Exported {} feeds to {path}",
+								self.db.feeds.len()
+							)),
+							Err(e) => {
+								Toast::error(format!("Exporting OPML to {path} failed with {e}"))
+							}
+						};
+						self.toasts.add(toast);
+					}
+					close |= ui.button("Cancel").clicked();
+				});
+			});
+		if close {
+			self.opml_export = None;
+		}
+	}
+
+	fn feed_picker(&mut self, ui: &mut eframe::egui::Ui) {
+		let db = self.db.clone();
+		let mut action = None;
+		for (category, feeds) in feed_groups(&db) {
+			let unread: usize = feeds.iter().map(|(_, feed)| feed.unread()).sum();
+			let name = category.unwrap_or("Uncategorized");
+			CollapsingHeader::new(format!("{name} ({unread} unread)"))
+				.id_source(("category", category))
+				.default_open(true)
+				.show(ui, |ui| {
+					for (url, feed) in feeds {
+						if let Some(feed_action) = self.feed_row(ui, url, feed) {
+							action = Some(feed_action);
+						}
+					}
+				});
+		}
+		match action {
+			Some(FeedAction::Delete(url)) => self.delete_feed(url),
+			Some(FeedAction::Refresh(url)) => self.refresh_feed(&url),
+			Some(FeedAction::Copy(url)) => self.copy(ui.ctx(), url),
+			Some(FeedAction::Rename(url, title)) => {
+				self.renaming_feed = None;
+				self.send_mutation(Box::new(move |db, _| {
+					if let Some(feed) = db.feeds.get_mut(&url) {
+						// Clearing the name, or setting it back to the channel's, follows
+						// upstream again.
+						feed.custom_title =
+							Some(title).filter(|t| !t.is_empty() && *t != feed.feed.title);
+					}
+					Ok(())
+				}));
+			}
+			Some(FeedAction::Categorize(url, category)) => {
+				self.categorizing_feed = None;
+				self.send_mutation(Box::new(move |db, _| {
+					if let Some(feed) = db.feeds.get_mut(&url) {
+						feed.category = Some(category).filter(|c| !c.is_empty());
+					}
+					Ok(())
+				}));
+			}
+			Some(FeedAction::SetRefreshEnabled(url, enabled)) => {
+				self.send_mutation(Box::new(move |db, _| {
+					if let Some(feed) = db.feeds.get_mut(&url) {
+						feed.refresh_enabled = enabled;
+					}
+					Ok(())
+				}));
+			}
+			Some(FeedAction::MoveUrl(from, to)) => {
+				self.editing_url = None;
+				if self.db.feeds.contains_key(&to) {
+					// Including when the URL wasn't changed at all.
+					if to != from {
+						self.toasts
+							.error(format!("You're already subscribed to {to}"));
+					}
+				} else if !to.is_empty() {
+					if let Some((selected, _)) = &mut self.selected_feed {
+						if *selected == from {
+							*selected = to.clone();
+						}
+					}
+					// Taken before the move, while the login and headers are still under `from`.
+					let options = self.fetch_options(&from);
+					let target = to.clone();
+					self.send_mutation(Box::new(move |db, _| move_feed(db, &from, target)));
+					self.fetch_feed_with(to, options, true);
+				}
+			}
+			Some(FeedAction::SetAuth(url, auth)) => {
+				self.editing_auth = None;
+				// Tries the new login out straight away, without waiting for it to be saved.
+				let mut options = self.fetch_options(&url);
+				options.auth = auth.clone();
+				let target = url.clone();
+				self.send_mutation(Box::new(move |db, _| {
+					if let Some(feed) = db.feeds.get_mut(&target) {
+						feed.auth = auth;
+					}
+					Ok(())
+				}));
+				self.fetch_feed_with(url, options, true);
+			}
+			Some(FeedAction::SetHeaders(url, text)) => match parse_headers(&text) {
+				Ok(headers) => {
+					self.editing_headers = None;
+					let mut options = self.fetch_options(&url);
+					options.headers = headers.clone();
+					let target = url.clone();
+					self.send_mutation(Box::new(move |db, _| {
+						if let Some(feed) = db.feeds.get_mut(&target) {
+							feed.headers = headers;
+						}
+						Ok(())
+					}));
+					self.fetch_feed_with(url, options, true);
+				}
+				// Left open, so the typo can be fixed.
+				Err(e) => {
+					self.toasts.error(e);
+				}
+			},
+			None => {}
+		}
+	}
+
+	/// One feed in the picker, with its buttons.
+	fn feed_row(&mut self, ui: &mut Ui, url: &String, feed: &Feed) -> Option<FeedAction> {
+		let mut action = None;
+		ui.horizontal(|ui| {
+			if let Some((renaming, title)) = self
+				.renaming_feed
+				.as_mut()
+				.filter(|(renaming, _)| renaming == url)
+			{
+				ui.text_edit_singleline(title);
+				if ui.button("Save").clicked() {
+					action = Some(FeedAction::Rename(
+						renaming.clone(),
+						title.trim().to_string(),
+					));
+				}
+				if ui.button("Cancel").clicked() {
+					self.renaming_feed = None;
+				}
+			} else {
+				ui.heading(feed.title());
+				if ui.button("Rename").clicked() {
+					self.renaming_feed = Some((url.clone(), feed.title().to_string()));
+				}
+				if ui.button("Category").clicked() {
+					self.categorizing_feed =
+						Some((url.clone(), feed.category.clone().unwrap_or_default()));
+				}
+				if ui
+					.button("Edit URL")
+					.on_hover_text("For when the feed moves, keeping which articles you've read")
+					.clicked()
+				{
+					self.editing_url = Some((url.clone(), url.clone()));
+				}
+				if ui
+					.button("Login")
+					.on_hover_text("For private feeds that need a password or token")
+					.clicked()
+				{
+					self.editing_auth = Some((url.clone(), feed.auth.clone()));
+				}
+				if ui
+					.button("Headers")
+					.on_hover_text("Extra HTTP headers this feed's host needs")
+					.clicked()
+				{
+					let text = feed
+						.headers
+						.iter()
+						.map(|(name, value)| format!("{name}: {value}\n"))
+						.collect();
+					self.editing_headers = Some((url.clone(), text));
+				}
+			}
+			ui.label(format!("updated {}", ago(feed.last_refreshed, Utc::now())));
+			if let Some(date) = feed.feed.last_build_date().or(feed.feed.pub_date()) {
+				ui.label("published");
+				date_label(ui, date);
+			}
+			let total = feed.feed.items.len();
+			let unread = feed.unread();
+			ui.label(format!("{}/{total}", total - unread));
+			unread_badge(ui, unread);
+			let starred = feed.starred_count();
+			if starred > 0 {
+				ui.label(format!("★{starred}"))
+					.on_hover_text(format!("{starred} starred"));
+			}
+			if ui.button(">").clicked() {
+				self.selected_feed = Some((url.clone(), None));
+				self.article_query.clear();
+				self.article_category = None;
+				self.article_cursor = None;
+			}
+			if ui.button("Refresh").clicked() {
+				action = Some(FeedAction::Refresh(url.clone()));
+			}
+			let mut refresh_enabled = feed.refresh_enabled;
+			if ui
+				.checkbox(&mut refresh_enabled, "Auto")
+				.on_hover_text("Include this feed when refreshing all feeds")
+				.changed()
+			{
+				action = Some(FeedAction::SetRefreshEnabled(url.clone(), refresh_enabled));
+			}
+			if ui.button("Copy URL").clicked() {
+				action = Some(FeedAction::Copy(url.clone()));
+			}
+			if ui.button("Delete").clicked() {
+				action = Some(FeedAction::Delete(url.clone()));
+			}
+		});
+		let mut cancel = false;
+		if let Some((_, category)) = self
+			.categorizing_feed
+			.as_mut()
+			.filter(|(categorizing, _)| categorizing == url)
+		{
+			ui.horizontal(|ui| {
+				ui.label("Category");
+				ui.text_edit_singleline(category);
+				if ui.button("Save").clicked() {
+					action = Some(FeedAction::Categorize(
+						url.clone(),
+						category.trim().to_string(),
+					));
+				}
+				cancel = ui.button("Cancel").clicked();
+			});
+		}
+		if cancel {
+			self.categorizing_feed = None;
+		}
+		let mut cancel = false;
+		if let Some((_, new_url)) = self
+			.editing_url
+			.as_mut()
+			.filter(|(editing, _)| editing == url)
+		{
+			ui.horizontal(|ui| {
+				ui.label("URL");
+				ui.text_edit_singleline(new_url);
+				if ui.button("Save").clicked() {
+					action = Some(FeedAction::MoveUrl(url.clone(), new_url.trim().to_string()));
+				}
+				cancel = ui.button("Cancel").clicked();
+			});
+		}
+		if cancel {
+			self.editing_url = None;
+		}
+		let mut cancel = false;
+		if let Some((_, auth)) = self
+			.editing_auth
+			.as_mut()
+			.filter(|(editing, _)| editing == url)
+		{
+			auth_editor(ui, auth);
+			ui.horizontal(|ui| {
+				if ui.button("Save").clicked() {
+					action = Some(FeedAction::SetAuth(url.clone(), auth.clone()));
+				}
+				cancel = ui.button("Cancel").clicked();
+			});
+		}
+		if cancel {
+			self.editing_auth = None;
+		}
+		let mut cancel = false;
+		if let Some((_, text)) = self
+			.editing_headers
+			.as_mut()
+			.filter(|(editing, _)| editing == url)
+		{
+			ui.label("Headers, one \"Name: value\" per line. These replace the config's.");
+			ui.text_edit_multiline(text);
+			ui.horizontal(|ui| {
+				if ui.button("Save").clicked() {
+					action = Some(FeedAction::SetHeaders(url.clone(), text.clone()));
+				}
+				cancel = ui.button("Cancel").clicked();
+			});
+		}
+		if cancel {
+			self.editing_headers = None;
+		}
+		CollapsingHeader::new("Description")
+			.id_source(url)
+			.show(ui, |ui| {
+				ui.label(feed.feed.description());
+			});
+		ui.separator();
+		action
+	}
+
+	fn global_search(&mut self, ui: &mut Ui) {
+		let Some(query) = &mut self.global_query else {
+			self.global_results = None;
+			return;
+		};
+		ui.horizontal(|ui| {
+			ui.label("Search all feeds");
+			ui.text_edit_singleline(query);
+			ui.checkbox(&mut self.search_starred_only, "Starred only");
+		});
+		// Every starred article is worth listing, but not every article.
+		if query.is_empty() && !self.search_starred_only {
+			return;
+		}
+		let fresh = self.global_results.as_ref().is_some_and(|results| {
+			results.query == *query
+				&& results.starred_only == self.search_starred_only
+				&& results.db.ptr_eq(&Arc::downgrade(&self.db))
+		});
+		if !fresh {
+			let found = search_all(
+				&self.db,
+				&query.to_lowercase(),
+				self.search_starred_only,
+				MAX_SEARCH_RESULTS + 1,
+			)
+			.into_iter()
+			.map(|(url, _, article)| (url.to_string(), article.clone()))
+			.collect();
+			self.global_results = Some(SearchResults {
+				query: query.clone(),
+				starred_only: self.search_starred_only,
+				db: Arc::downgrade(&self.db),
+				found,
+			});
+		}
+		let results = &self.global_results.as_ref().unwrap().found;
+		let mut open = None;
+		for (url, article) in results.iter().take(MAX_SEARCH_RESULTS) {
+			let Some(feed) = self.db.feeds.get(url) else {
+				continue;
+			};
+			ui.horizontal(|ui| {
+				let key = article_key(article);
+				star_button(ui, &self.mutations, url, feed, &key);
+				ui.heading(article.title().unwrap_or("???"));
+				ui.label(feed.title());
+				if ui.button(">").clicked() {
+					open = Some((url.to_string(), Some(article_key(article).into_owned())));
+				}
+			});
+		}
+		if results.len() > MAX_SEARCH_RESULTS {
+			ui.label(format!(
+				"Showing the first {MAX_SEARCH_RESULTS} results, refine the search to see more."
+			));
+		} else if results.is_empty() {
+			ui.label("No matching articles.");
+		}
+		if let Some(selection) = open {
+			self.selected_feed = Some(selection);
+			self.article_query.clear();
+			self.article_category = None;
+			self.article_cursor = None;
+			self.global_query = None;
+		}
+	}
+
+	/// Asks before deleting, since the feed's read state goes with it.
+	fn delete_feed(&mut self, url: String) {
+		let title = self.db.feeds.get(&url).map_or(url.as_str(), Feed::title);
+		let question = format!("Delete {title}? Which articles you've read will be forgotten.");
+		self.confirm(
+			question,
+			Box::new(move |db, toast| {
+				if let Some(feed) = db.feeds.remove(&url) {
+					let _ = toast.try_send((
+						ToastLevel::Success,
+						format!("Deleted feed {}", feed.title()),
+					));
+				}
+				Ok(())
+			}),
+		);
+	}
+
+	/// Holds `mutation` back until the user answers yes to `question`.
+	fn confirm(&mut self, question: String, mutation: Mutation) {
+		self.confirming = Some((question, mutation));
+	}
+
+	fn confirmation(&mut self, ctx: &eframe::egui::Context) {
+		let Some((question, _)) = &self.confirming else {
+			return;
+		};
+		let (mut yes, mut no) = (false, false);
+		Window::new("Are you sure?")
+			.collapsible(false)
+			.resizable(false)
+			.anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+			.show(ctx, |ui| {
+				ui.label(question);
+				ui.horizontal(|ui| {
+					yes = ui.button("Yes").clicked();
+					no = ui.button("No").clicked();
+				});
+			});
+		if yes {
+			if let Some((_, mutation)) = self.confirming.take() {
+				self.send_mutation(mutation);
+			}
+		}
+		if no {
+			self.confirming = None;
+		}
+	}
+
+	/// Puts the DB back how it was before the last change, whether the user or a refresh made it.
+	fn undo(&mut self) {
+		let history = self.history.clone();
+		self.send_mutation(Box::new(move |db, toast| {
+			if let Some(before) = history.lock().unwrap().undo() {
+				*db = (*before).clone();
+				let _ = toast.try_send((ToastLevel::Info, "Undid the last change".to_string()));
+			}
+			Ok(())
+		}));
+	}
+}
+
+impl eframe::App for Gui {
+	fn save(&mut self, storage: &mut dyn eframe::Storage) {
+		eframe::set_value(storage, SELECTED_FEED_KEY, &self.selected_feed);
+		eframe::set_value(storage, LIST_VIEW_KEY, &(self.sort_order, self.unread_only));
+	}
+
+	fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+		if let Some(shutdown) = self.shutdown.take() {
+			let _ = shutdown.send(());
+		}
+	}
+
+	fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
+		self.window.get_or_init(|| ctx.clone());
+		while let Ok(new_db) = self.new_state.try_recv() {
+			self.db = new_db;
 			ctx.request_repaint();
 		}
 		while let Ok((level, message)) = self.recv_toast.try_recv() {
 			self.toasts.add(Toast::custom(message, level));
 		}
+		if !self.backend_stopped && self.mutations.is_closed() {
+			self.backend_stopped = true;
+			self.toasts
+				.error("The backend stopped, so changes from now on won't be saved")
+				.set_duration(None);
+		}
 		if self.queued.load(Ordering::Relaxed) > 0 || !self.jobs.is_empty() {
 			ctx.request_repaint();
 		}
 		self.jobs.retain(|network| !network.is_finished());
+		self.downloads
+			.retain(|download| !download.task.is_finished());
+		if !self.downloads.is_empty() {
+			ctx.request_repaint_after(Duration::from_millis(250));
+		}
+		if self.playing.as_ref().is_some_and(Playback::is_finished) {
+			let played_through = self.playing.take().is_some_and(|p| p.played_through());
+			if played_through {
+				self.play_next();
+			}
+		}
+		#[cfg(all(feature = "mpris", target_os = "linux"))]
+		self.media_controls();
+		#[cfg(feature = "tray")]
+		self.tray(ctx);
+		if let Some(next) = self.auto_refresh() {
+			ctx.request_repaint_after(next);
+		}
+		// The settings window previews its theme before it's saved.
+		let theme = self
+			.settings_draft
+			.as_ref()
+			.unwrap_or(&self.db.settings)
+			.theme;
+		let visuals = theme.visuals(frame.info().system_theme);
+		if ctx.style().visuals.dark_mode != visuals.dark_mode {
+			ctx.set_visuals(visuals);
+		}
 		self.status_line(ctx);
 		self.new_feed_editor(ctx);
+		self.opml_exporter(ctx);
+		self.url_importer(ctx);
+		self.settings_editor(ctx);
+		self.stats_window(ctx);
+		self.confirmation(ctx);
+		self.now_playing(ctx);
+		self.queue_panel(ctx);
+		let db = self.db.clone();
+		let mut action = None;
+		let keys = NavKeys::read(ctx);
+		// Text boxes keep Ctrl+Z for undoing typing.
+		if !ctx.wants_keyboard_input()
+			&& ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::Z))
+		{
+			self.undo();
+		}
 		CentralPanel::default().show(ctx, |ui| {
+			if self.global_query.is_some() {
+				if ui.button("< Close search").clicked() {
+					self.global_query = None;
+				}
+				ScrollArea::vertical()
+					.auto_shrink(Vec2b::new(false, false))
+					.show(ui, |ui| self.global_search(ui));
+				return;
+			}
 			if self.selected_feed.is_some() && ui.button("< Select feed").clicked() {
 				self.selected_feed = None;
 			}
@@ -317,128 +2084,2052 @@ impl eframe::App for Gui {
 					let send_mutation = self.mutations.clone();
 					if let Some((feed_url, feed, selected_article)) =
 						self.selected_feed.as_mut().and_then(|(feed_url, art)| {
-							self.db
-								.feeds
+							db.feeds
 								.get(feed_url)
 								.map(|feed| (feed_url.clone(), feed, art))
 						}) {
+						ui.horizontal(|ui| {
+							ui.heading(feed.title());
+							if ui.button("Mark all read").clicked() {
+								action = Some(ArticleAction::Confirm(
+									format!("Mark every article in {} read?", feed.title()),
+									set_all_read(feed_url.clone(), 1.0),
+								));
+							}
+							if ui.button("Mark all unread").clicked() {
+								action = Some(ArticleAction::Confirm(
+									format!(
+										"Mark every article in {} unread? Playback positions are lost too.",
+										feed.title()
+									),
+									set_all_read(feed_url.clone(), 0.0),
+								));
+							}
+						});
 						if selected_article.is_some() && ui.button("< Select article").clicked() {
 							*selected_article = None;
 						}
+						if keys.back {
+							*selected_article = None;
+						}
 						if let Some(article) = selected_article
-							.as_ref()
-							.and_then(|art| feed.feed.items.iter().find(|a| a.guid() == Some(art)))
+							.as_deref()
+							.and_then(|key| feed.feed.items.iter().find(|a| article_key(a) == key))
 						{
+							action = article_detail(
+								ui,
+								&feed_url,
+								feed,
+								article,
+								download_state(
+									&self.downloads,
+									&self.download_dir,
+									&feed_url,
+									feed,
+									article,
+								),
+								selected_article,
+								&mut self.raw_html,
+								&send_mutation,
+							);
 						} else {
-							for article in &feed.feed.items {
-								let guid = article.guid().map(|g| g.value()).unwrap_or("???");
-								let completion = feed
-									.read_articles
-									.get(guid)
-									.copied()
-									.unwrap_or(0.0)
-									.clamp(0.0, 1.0)
-									.mul(100.0)
-									.round();
+							ui.horizontal(|ui| {
+								ui.label("Search");
+								ui.text_edit_singleline(&mut self.article_query);
+								ui.checkbox(&mut self.unread_only, "Unread only");
+								ui.checkbox(&mut self.starred_only, "Starred only");
+								category_picker(ui, feed, &mut self.article_category);
+								ui.checkbox(&mut self.raw_html, "Raw HTML");
+								for (order, label) in [
+									(SortOrder::Feed, "Feed order"),
+									(SortOrder::NewestFirst, "Newest first"),
+									(SortOrder::OldestFirst, "Oldest first"),
+								] {
+									ui.selectable_value(&mut self.sort_order, order, label);
+								}
+							});
+							let query = self.article_query.to_lowercase();
+							let mut articles: Vec<_> = feed
+								.feed
+								.items
+								.iter()
+								.filter(|a| {
+									let key = article_key(a);
+									matches_query(a, &query)
+										&& !(self.unread_only && completion(feed, &key) >= 1.0)
+										&& (!self.starred_only
+											|| feed.starred.contains(key.as_ref()))
+										&& self
+											.article_category
+											.as_deref()
+											.is_none_or(|category| in_category(a, category))
+								})
+								.collect();
+							sort_articles(&mut articles, self.sort_order);
+							self.article_cursor =
+								move_cursor(self.article_cursor, articles.len(), &keys);
+							// What's shown, in the order it's shown, for "mark everything above".
+							let shown: Vec<String> = articles
+								.iter()
+								.map(|article| article_key(article).into_owned())
+								.collect();
+							for (n, article) in articles.into_iter().enumerate() {
+								let key = article_key(article);
+								let guid = key.as_ref();
+								let percent = completion(feed, guid).mul(100.0).round();
+								let current = self.article_cursor == Some(n);
+								if current && keys.open {
+									*selected_article = Some(guid.to_string());
+								}
+								if current && keys.toggle_read {
+									set_read(
+										&send_mutation,
+										&feed_url,
+										guid,
+										if percent > 0.0 { 0.0 } else { 1.0 },
+									);
+								}
 								ui.horizontal(|ui| {
+									if current {
+										let marker = ui.label("▶");
+										if keys.up || keys.down {
+											marker.scroll_to_me(None);
+										}
+									}
+									star_button(ui, &send_mutation, &feed_url, feed, guid);
 									ui.heading(article.title().unwrap_or("???"));
-									ui.label(format!("{completion}%"));
+									if let Some(category) = category_labels(ui, article) {
+										self.article_category = Some(category);
+									}
+									ui.label(format!("{percent}%"));
+									if let Some(date) = article.pub_date() {
+										date_label(ui, date);
+									}
+									if let Some(summary) = enclosure_summary(article) {
+										ui.label(summary);
+									}
 									if ui.button(">").clicked() {
-										*selected_article = article.guid.clone();
+										*selected_article = Some(guid.to_string());
+										self.article_cursor = Some(n);
+									}
+									if let Some(open) = link_buttons(ui, article) {
+										action = Some(open);
+									}
+									if ui.button(if percent > 0.0 { "x" } else { "r" }).clicked() {
+										set_read(
+											&send_mutation,
+											&feed_url,
+											guid,
+											if percent > 0.0 { 0.0 } else { 1.0 },
+										);
 									}
-									if ui
-										.button(if completion > 0.0 { "x" } else { "r" })
-										.clicked()
+									if n > 0
+										&& ui
+											.button("^r")
+											.on_hover_text("Mark everything above as read")
+											.clicked()
 									{
-										let guid = guid.to_string();
-										let feed_url = feed_url.clone();
-										let send_mutation = send_mutation.clone();
-										tokio::spawn(async move {
-											send_mutation
-												.clone()
-												.send(Box::new(move |db, _| {
-													if let Some(feed) =
-														db.feeds.get_mut(feed_url.as_str())
-													{
-														feed.read_articles.insert(
-															guid,
-															if completion > 0.0 {
-																0.0
-															} else {
-																1.0
-															},
-														);
-													}
-													Ok(())
-												}))
-												.await
-										});
+										mark_read(&send_mutation, &feed_url, shown[..n].to_vec());
 									}
 								});
 								if let Some(desc) = article.description() {
 									CollapsingHeader::new("Description").id_source(guid).show(
 										ui,
 										|ui| {
-											ui.label(desc);
+											ui.label(display_html(desc, self.raw_html));
 										},
 									);
 								}
 							}
 						}
-						if let Some(article_id) = selected_article.clone() {
-							if ui.button("< Done").clicked() {
-								*selected_article = None;
-								self.send_mutation(Box::new(move |db, _| {
-									if let Some(feed) = db.feeds.get_mut(feed_url.as_str()) {
-										feed.read_articles.insert(article_id.value, 1.0);
-									}
-									Ok(())
-								}));
-							}
-						}
 					} else {
 						self.feed_picker(ui);
 					}
 				});
 		});
+		match action {
+			Some(ArticleAction::Play(episode)) => self.play(episode),
+			Some(ArticleAction::Download(episode)) => self.download(episode),
+			Some(ArticleAction::DiscardDownload(episode)) => self.discard_download(episode),
+			Some(ArticleAction::Enqueue(episode)) => {
+				let entry = (episode.feed_url, episode.guid);
+				self.send_mutation(Box::new(move |db, _| {
+					if !db.queue.contains(&entry) {
+						db.queue.push(entry);
+					}
+					Ok(())
+				}));
+			}
+			Some(ArticleAction::Open(url)) => self.open_link(url),
+			Some(ArticleAction::Copy(text)) => self.copy(ctx, text),
+			Some(ArticleAction::Confirm(question, mutation)) => self.confirm(question, mutation),
+			None => {}
+		}
+	}
+}
+
+/// Lets the user pick how a feed logs in and enter the credentials. Returns whether anything
+/// changed.
+fn auth_editor(ui: &mut Ui, auth: &mut FeedAuth) -> bool {
+	let mut changed = false;
+	ui.horizontal(|ui| {
+		ui.label("Login");
+		let kinds = [
+			("None", FeedAuth::None),
+			(
+				"Password",
+				FeedAuth::Basic {
+					user: String::new(),
+					password: String::new(),
+				},
+			),
+			(
+				"Token",
+				FeedAuth::Bearer {
+					token: String::new(),
+				},
+			),
+		];
+		for (name, kind) in kinds {
+			let selected = mem::discriminant(auth) == mem::discriminant(&kind);
+			if ui.selectable_label(selected, name).clicked() && !selected {
+				*auth = kind;
+				changed = true;
+			}
+		}
+	});
+	match auth {
+		FeedAuth::None => {}
+		FeedAuth::Basic { user, password } => {
+			ui.horizontal(|ui| {
+				ui.label("User");
+				changed |= ui.text_edit_singleline(user).changed();
+				ui.label("Password");
+				changed |= ui
+					.add(TextEdit::singleline(password).password(true))
+					.changed();
+			});
+		}
+		FeedAuth::Bearer { token } => {
+			ui.horizontal(|ui| {
+				ui.label("Token");
+				changed |= ui.add(TextEdit::singleline(token).password(true)).changed();
+			});
+		}
+	}
+	changed
+}
+
+/// Reads headers typed one `Name: value` per line, skipping blank lines.
+fn parse_headers(text: &str) -> Result<BTreeMap<String, String>, String> {
+	text.lines()
+		.filter(|line| !line.trim().is_empty())
+		.map(|line| {
+			let (name, value) = line
+				.split_once(':')
+				.ok_or_else(|| format!("\"{line}\" needs a colon between the name and value"))?;
+			let (name, value) = (name.trim(), value.trim());
+			if HeaderName::try_from(name).is_err() || HeaderValue::try_from(value).is_err() {
+				return Err(format!("\"{line}\" isn't a valid HTTP header"));
+			}
+			Ok((name.to_string(), value.to_string()))
+		})
+		.collect()
+}
+
+/// Downloads a feed that isn't in the DB yet.
+async fn fetch_new_feed(client: &FetchClient, url: &str, auth: FeedAuth) -> eyre::Result<Feed> {
+	let options = FetchOptions {
+		auth: auth.clone(),
+		..Default::default()
+	};
+	match fetch_channel(client, url, &options).await? {
+		Fetched::Changed {
+			channel,
+			etag,
+			last_modified,
+		} => Ok(Feed {
+			feed: *channel,
+			last_refreshed: Some(Utc::now()),
+			etag,
+			last_modified,
+			auth,
+			..Default::default()
+		}),
+		Fetched::NotModified => Err(eyre::eyre!("Server sent 304 to an unconditional request")),
+	}
+}
+
+/// Subscribes to `feed`. Re-adding a feed replaces the channel but keeps what the user did with it.
+fn add_feed(db: &mut Db, url: String, mut feed: Feed) {
+	if let Some(existing) = db.feeds.remove(&url) {
+		feed.read_articles = existing.read_articles;
+		feed.custom_title = existing.custom_title;
+		feed.category = existing.category;
+		feed.refresh_enabled = existing.refresh_enabled;
+		feed.missing_since = existing.missing_since;
+		feed.downloads = existing.downloads;
+		feed.starred = existing.starred;
+		feed.headers = existing.headers;
+	}
+	db.feeds.insert(url, feed);
+}
+
+/// Moves the feed at `from` to `to`, keeping its read state, and repoints its queued articles.
+/// Refuses to replace a feed that's already at `to`.
+fn move_feed(db: &mut Db, from: &str, to: String) -> eyre::Result<()> {
+	if db.feeds.contains_key(&to) {
+		eyre::bail!("There's already a feed at {to}");
+	}
+	let Some(mut feed) = db.feeds.remove(from) else {
+		return Ok(());
+	};
+	// They came from the old server, so the new one can't use them.
+	feed.etag = None;
+	feed.last_modified = None;
+	for (feed_url, _) in &mut db.queue {
+		if feed_url == from {
+			feed_url.clone_from(&to);
+		}
+	}
+	db.feeds.insert(to, feed);
+	Ok(())
+}
+
+/// The feeds grouped by category, categories in alphabetical order with uncategorized feeds last,
+/// and feeds by title within each.
+#[allow(clippy::type_complexity)]
+fn feed_groups(db: &Db) -> Vec<(Option<&str>, Vec<(&String, &Feed)>)> {
+	let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+	for (url, feed) in &db.feeds {
+		let category = feed.category.as_deref();
+		groups
+			.entry((category.is_none(), category))
+			.or_default()
+			.push((url, feed));
+	}
+	groups
+		.into_iter()
+		.map(|((_, category), mut feeds)| {
+			feeds.sort_by(|(a_url, a), (b_url, b)| (a.title(), a_url).cmp(&(b.title(), b_url)));
+			(category, feeds)
+		})
+		.collect()
+}
+
+/// What identifies an article in `read_articles` and the selection: its guid, or for articles
+/// without one, a hash of its title, link, and date, which stays the same across refreshes.
+pub fn article_key(article: &Item) -> Cow<'_, str> {
+	if let Some(guid) = article.guid() {
+		return Cow::Borrowed(guid.value());
+	}
+	let mut hasher = Sha256::new();
+	for part in [article.title(), article.link(), article.pub_date()] {
+		hasher.update(part.unwrap_or_default());
+		hasher.update([0]);
+	}
+	Cow::Owned(format!("winter2:{:x}", hasher.finalize()))
+}
+
+/// Shows how many articles are unread as a highlighted count, or nothing when they're all read.
+fn unread_badge(ui: &mut Ui, unread: usize) {
+	if unread == 0 {
+		return;
+	}
+	let visuals = ui.visuals();
+	let badge = RichText::new(format!(" {unread} "))
+		.strong()
+		.color(visuals.strong_text_color())
+		.background_color(visuals.selection.bg_fill);
+	ui.label(badge).on_hover_text(format!("{unread} unread"));
+}
+
+/// Moves the article list's cursor for the keys pressed, keeping it on the list as filters
+/// shrink it.
+fn move_cursor(cursor: Option<usize>, len: usize, keys: &NavKeys) -> Option<usize> {
+	if len == 0 {
+		return None;
+	}
+	let cursor = match (cursor, keys.down, keys.up) {
+		(None, true, _) | (None, _, true) => 0,
+		(None, _, _) => return None,
+		(Some(n), true, false) => n + 1,
+		(Some(n), false, true) => n.saturating_sub(1),
+		(Some(n), _, _) => n,
+	};
+	Some(cursor.min(len - 1))
+}
+
+/// Whether the article is tagged with `category`.
+fn in_category(article: &Item, category: &str) -> bool {
+	article
+		.categories()
+		.iter()
+		.any(|c| c.name().trim() == category)
+}
+
+/// The distinct categories the feed's articles are in, with how many are in each, most used
+/// first.
+fn feed_categories(feed: &Feed) -> Vec<(&str, usize)> {
+	let mut counts: HashMap<&str, usize> = HashMap::new();
+	for article in &feed.feed.items {
+		let names: HashSet<_> = article
+			.categories()
+			.iter()
+			.map(|c| c.name().trim())
+			.filter(|name| !name.is_empty())
+			.collect();
+		for name in names {
+			*counts.entry(name).or_default() += 1;
+		}
+	}
+	let mut categories: Vec<_> = counts.into_iter().collect();
+	categories.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+	categories
+}
+
+/// A dropdown of the feed's categories, for showing only one of them. Left out for feeds that
+/// don't use categories.
+fn category_picker(ui: &mut Ui, feed: &Feed, selected: &mut Option<String>) {
+	let categories = feed_categories(feed);
+	if categories.is_empty() && selected.is_none() {
+		return;
+	}
+	ComboBox::from_id_source("article_category")
+		.selected_text(selected.as_deref().unwrap_or("All categories"))
+		.show_ui(ui, |ui| {
+			ui.selectable_value(selected, None, "All categories");
+			for (name, count) in categories {
+				ui.selectable_value(
+					selected,
+					Some(name.to_string()),
+					format!("{name} ({count})"),
+				);
+			}
+		});
+}
+
+/// The article's first few categories, as small buttons. Returns the one clicked, to filter by.
+fn category_labels(ui: &mut Ui, article: &Item) -> Option<String> {
+	let names: Vec<_> = article
+		.categories()
+		.iter()
+		.map(|c| c.name().trim())
+		.filter(|name| !name.is_empty())
+		.collect();
+	let mut clicked = None;
+	for name in names.iter().take(ROW_CATEGORIES) {
+		if ui
+			.small_button(*name)
+			.on_hover_text("Show only this category")
+			.clicked()
+		{
+			clicked = Some(name.to_string());
+		}
+	}
+	if names.len() > ROW_CATEGORIES {
+		ui.small(format!("+{}", names.len() - ROW_CATEGORIES))
+			.on_hover_text(names[ROW_CATEGORIES..].join(", "));
+	}
+	clicked
+}
+
+/// Whether `query` (already lowercased) appears in the article's title or description.
+fn matches_query(article: &Item, query: &str) -> bool {
+	[article.title(), article.description()]
+		.into_iter()
+		.flatten()
+		.any(|text| text.to_lowercase().contains(query))
+}
+
+/// Articles from every feed matching `query` (already lowercased), at most `limit` of them, with
+/// their feed's URL. Feeds are scanned in title order so results don't jump around between frames.
+fn search_all<'a>(
+	db: &'a Db,
+	query: &str,
+	starred_only: bool,
+	limit: usize,
+) -> Vec<(&'a str, &'a Feed, &'a Item)> {
+	let mut feeds: Vec<_> = db.feeds.iter().collect();
+	feeds.sort_by(|(a_url, a), (b_url, b)| (a.title(), a_url).cmp(&(b.title(), b_url)));
+	feeds
+		.into_iter()
+		.flat_map(|(url, feed)| {
+			feed.feed
+				.items
+				.iter()
+				.map(move |article| (url.as_str(), feed, article))
+		})
+		.filter(|(_, feed, article)| {
+			matches_query(article, query)
+				&& (!starred_only || feed.starred.contains(article_key(article).as_ref()))
+		})
+		.take(limit)
+		.collect()
+}
+
+/// The article's `pubDate`, if it has one that parses as RFC 2822.
+fn pub_date(article: &Item) -> Option<DateTime<FixedOffset>> {
+	DateTime::parse_from_rfc2822(article.pub_date()?.trim()).ok()
+}
+
+/// Sorts by date for the date orders. Undated articles go last either way, in feed order.
+fn sort_articles(articles: &mut [&Item], order: SortOrder) {
+	let newest_first = match order {
+		SortOrder::Feed => return,
+		SortOrder::NewestFirst => true,
+		SortOrder::OldestFirst => false,
+	};
+	articles.sort_by(|a, b| match (pub_date(a), pub_date(b)) {
+		(Some(a), Some(b)) if newest_first => b.cmp(&a),
+		(Some(a), Some(b)) => a.cmp(&b),
+		(a, b) => b.is_some().cmp(&a.is_some()),
+	});
+}
+
+/// A rough "5m ago" rendering of `time` relative to `now`.
+fn ago(time: Option<DateTime<Utc>>, now: DateTime<Utc>) -> String {
+	let Some(time) = time else {
+		return "never".to_string();
+	};
+	let secs = (now - time).num_seconds().max(0);
+	match secs {
+		0..60 => "just now".to_string(),
+		60..3600 => format!("{}m ago", secs / 60),
+		3600..86400 => format!("{}h ago", secs / 3600),
+		_ => format!("{}d ago", secs / 86400),
+	}
+}
+
+/// Renders an RFC 2822 date the way a feed reader does: "3h ago" for today, "yesterday", then
+/// "Jan 5", and the year too once it's not this one. Dates that don't parse are shown as sent.
+fn relative_date(date: &str, now: DateTime<Utc>) -> String {
+	let Ok(date) = DateTime::parse_from_rfc2822(date.trim()) else {
+		return date.trim().to_string();
+	};
+	let date = date.with_timezone(&Utc);
+	match (now - date).num_seconds() {
+		..86400 => ago(Some(date), now),
+		86400..172800 => "yesterday".to_string(),
+		_ if date.year() == now.year() => date.format("%b %-d").to_string(),
+		_ => date.format("%b %-d, %Y").to_string(),
+	}
+}
+
+/// A [`relative_date`], with the full local time on hover.
+fn date_label(ui: &mut Ui, date: &str) {
+	let absolute = DateTime::parse_from_rfc2822(date.trim()).map_or_else(
+		|_| date.to_string(),
+		|d| d.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string(),
+	);
+	ui.label(relative_date(date, Utc::now()))
+		.on_hover_text(absolute);
+}
+
+/// How much of the article has been read, from 0 (untouched) to 1 (done).
+fn completion(feed: &Feed, guid: &str) -> f64 {
+	feed.read_articles
+		.get(guid)
+		.map_or(0.0, |read| read.fraction)
+		.clamp(0.0, 1.0)
+}
+
+fn set_read(send_mutation: &Sender<Mutation>, feed_url: &str, guid: &str, fraction: f64) {
+	let feed_url = feed_url.to_string();
+	let guid = guid.to_string();
+	let send_mutation = send_mutation.clone();
+	tokio::spawn(async move {
+		send_mutation
+			.send(Box::new(move |db, _| {
+				if let Some(feed) = db.feeds.get_mut(feed_url.as_str()) {
+					feed.set_read(guid, fraction, Utc::now());
+				}
+				Ok(())
+			}))
+			.await
+	});
+}
+
+/// A star that stars or unstars the article when clicked.
+fn star_button(
+	ui: &mut Ui,
+	send_mutation: &Sender<Mutation>,
+	feed_url: &str,
+	feed: &Feed,
+	guid: &str,
+) {
+	let starred = feed.starred.contains(guid);
+	let star = ui
+		.button(if starred { "★" } else { "☆" })
+		.on_hover_text(if starred { "Unstar" } else { "Star" });
+	if !star.clicked() {
+		return;
+	}
+	let feed_url = feed_url.to_string();
+	let guid = guid.to_string();
+	let send_mutation = send_mutation.clone();
+	tokio::spawn(async move {
+		send_mutation
+			.send(Box::new(move |db, _| {
+				if let Some(feed) = db.feeds.get_mut(feed_url.as_str()) {
+					if starred {
+						feed.starred.remove(&guid);
+					} else {
+						feed.starred.insert(guid);
+					}
+				}
+				Ok(())
+			}))
+			.await
+	});
+}
+
+/// Marks the articles read, as one mutation.
+fn mark_read(send_mutation: &Sender<Mutation>, feed_url: &str, guids: Vec<String>) {
+	let feed_url = feed_url.to_string();
+	let send_mutation = send_mutation.clone();
+	tokio::spawn(async move {
+		send_mutation
+			.send(Box::new(move |db, _| {
+				if let Some(feed) = db.feeds.get_mut(feed_url.as_str()) {
+					let now = Utc::now();
+					for guid in guids {
+						feed.set_read(guid, 1.0, now);
+					}
+				}
+				Ok(())
+			}))
+			.await
+	});
+}
+
+/// Sets every article in the feed to `fraction`, as one mutation.
+fn set_all_read(feed_url: String, fraction: f64) -> Mutation {
+	Box::new(move |db, _| {
+		if let Some(feed) = db.feeds.get_mut(&feed_url) {
+			let now = Utc::now();
+			let keys: Vec<_> = feed
+				.feed
+				.items
+				.iter()
+				.map(|article| article_key(article).into_owned())
+				.collect();
+			for key in keys {
+				feed.set_read(key, fraction, now);
+			}
+		}
+		Ok(())
+	})
+}
+
+fn display_html(html: &str, raw: bool) -> Cow<'_, str> {
+	if raw {
+		Cow::Borrowed(html)
+	} else {
+		Cow::Owned(html_to_text(html))
+	}
+}
+
+/// The playable enclosure of the article `guid` in `feed_url`, if both still exist.
+fn episode(db: &Db, feed_url: &str, guid: &str) -> Option<Episode> {
+	let article = db
+		.feeds
+		.get(feed_url)?
+		.feed
+		.items
+		.iter()
+		.find(|a| article_key(a) == guid)?;
+	Some(Episode {
+		feed_url: feed_url.to_string(),
+		guid: guid.to_string(),
+		title: article.title().unwrap_or("???").to_string(),
+		url: article.enclosure()?.url().to_string(),
+	})
+}
+
+/// A one-line summary of the article's enclosure, like "34.2 MB, audio/mpeg, 1:02:03", leaving
+/// out whatever the feed didn't say.
+fn enclosure_summary(article: &Item) -> Option<String> {
+	let enclosure = article.enclosure()?;
+	let size = enclosure
+		.length()
+		.parse()
+		.ok()
+		.filter(|&bytes| bytes > 0)
+		.map(human_size);
+	let mime_type = Some(enclosure.mime_type().to_string()).filter(|t| !t.is_empty());
+	let duration = article
+		.itunes_ext()
+		.and_then(|i| i.duration())
+		.map(|d| parse_duration(d).map_or_else(|| d.trim().to_string(), clock));
+	let parts: Vec<_> = [size, mime_type, duration].into_iter().flatten().collect();
+	(!parts.is_empty()).then(|| parts.join(", "))
+}
+
+fn human_size(bytes: u64) -> String {
+	let mut size = bytes as f64;
+	for unit in ["B", "kB", "MB", "GB"] {
+		if size < 1000.0 {
+			return if unit == "B" {
+				format!("{bytes} B")
+			} else {
+				format!("{size:.1} {unit}")
+			};
+		}
+		size /= 1000.0;
+	}
+	format!("{size:.1} TB")
+}
+
+/// Reads an `itunes:duration`, which is either plain seconds or `[[h:]m:]s`.
+fn parse_duration(duration: &str) -> Option<f64> {
+	duration
+		.trim()
+		.split(':')
+		.try_fold(0.0, |total, part| {
+			Some(total * 60.0 + part.parse::<f64>().ok()?)
+		})
+		.filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+}
+
+/// Moves a queue entry `delta` places towards the back (or front, if negative).
+fn move_in_queue(send_mutation: &Sender<Mutation>, entry: (String, String), delta: isize) {
+	let send_mutation = send_mutation.clone();
+	tokio::spawn(async move {
+		send_mutation
+			.send(Box::new(move |db, _| {
+				move_entry(&mut db.queue, &entry, delta);
+				Ok(())
+			}))
+			.await
+	});
+}
+
+fn move_entry<T: PartialEq>(list: &mut [T], entry: &T, delta: isize) {
+	let Some(from) = list.iter().position(|e| e == entry) else {
+		return;
+	};
+	let to = from.saturating_add_signed(delta).min(list.len() - 1);
+	if from < to {
+		list[from..=to].rotate_left(1);
+	} else {
+		list[to..=from].rotate_right(1);
+	}
+}
+
+/// Something the user asked to do with a feed in the picker, by its URL.
+enum FeedAction {
+	Delete(String),
+	Refresh(String),
+	Copy(String),
+	Rename(String, String),
+	Categorize(String, String),
+	SetRefreshEnabled(String, bool),
+	/// Move the feed from the first URL to the second.
+	MoveUrl(String, String),
+	SetAuth(String, FeedAuth),
+	SetHeaders(String, String),
+}
+
+/// Something the user asked to do with an article, which needs the whole `Gui` to carry out.
+enum ArticleAction {
+	Play(Episode),
+	Enqueue(Episode),
+	Download(Episode),
+	DiscardDownload(Episode),
+	Open(String),
+	Copy(String),
+	/// Make a destructive change, once the user says yes to the question.
+	Confirm(String, Mutation),
+}
+
+/// How far an article's enclosure is from being playable offline.
+enum DownloadState {
+	None,
+	/// An earlier download failed partway, and can be resumed.
+	Partial,
+	/// Being downloaded, this far along.
+	Running(String),
+	Done,
+}
+
+fn download_state(
+	downloads: &[Download],
+	dir: &Path,
+	feed_url: &str,
+	feed: &Feed,
+	article: &Item,
+) -> DownloadState {
+	let guid = article_key(article);
+	let running = downloads
+		.iter()
+		.find(|download| download.episode.feed_url == feed_url && download.episode.guid == guid);
+	if let Some(download) = running {
+		return DownloadState::Running(download.progress.describe());
+	}
+	if downloaded(feed, &guid).is_some() {
+		return DownloadState::Done;
+	}
+	let partial = article
+		.enclosure()
+		.map(|enclosure| partial_path(&local_path(dir, feed_url, &guid, enclosure.url())));
+	if partial.is_some_and(|partial| partial.exists()) {
+		DownloadState::Partial
+	} else {
+		DownloadState::None
+	}
+}
+
+/// The downloaded copy of the article's enclosure, if it's still there.
+fn downloaded<'a>(feed: &'a Feed, guid: &str) -> Option<&'a Path> {
+	feed.downloads
+		.get(guid)
+		.map(PathBuf::as_path)
+		.filter(|path| path.is_file())
+}
+
+/// "Open" and "Copy link" buttons for the article's link, greyed out if it has none.
+fn link_buttons(ui: &mut Ui, article: &Item) -> Option<ArticleAction> {
+	let link = article.link();
+	let mut button = |label| {
+		ui.add_enabled(link.is_some(), Button::new(label))
+			.on_disabled_hover_text("This article has no link")
+			.clicked()
+	};
+	let (open, copy) = (button("Open"), button("Copy link"));
+	let link = link?.to_string();
+	match (open, copy) {
+		(true, _) => Some(ArticleAction::Open(link)),
+		(_, true) => Some(ArticleAction::Copy(link)),
+		_ => None,
+	}
+}
+
+/// Shows everything about one article.
+#[allow(clippy::too_many_arguments)]
+fn article_detail(
+	ui: &mut Ui,
+	feed_url: &str,
+	feed: &Feed,
+	article: &Item,
+	download: DownloadState,
+	selected_article: &mut Option<String>,
+	raw_html: &mut bool,
+	send_mutation: &Sender<Mutation>,
+) -> Option<ArticleAction> {
+	let key = article_key(article);
+	let guid = key.as_ref();
+	let read = completion(feed, guid);
+	let mut action = None;
+	ui.horizontal(|ui| {
+		star_button(ui, send_mutation, feed_url, feed, guid);
+		ui.heading(article.title().unwrap_or("???"));
+	});
+	let author = article
+		.author()
+		.or_else(|| article.itunes_ext().and_then(|i| i.author()))
+		.or_else(|| {
+			article
+				.dublin_core_ext()
+				.and_then(|dc| dc.creators().first().map(String::as_str))
+		});
+	if let Some(author) = author {
+		ui.label(format!("By {author}"));
+	}
+	let categories: Vec<_> = article.categories().iter().map(|c| c.name()).collect();
+	if !categories.is_empty() {
+		ui.label(format!("In {}", categories.join(", ")));
+	}
+	if let Some(date) = article.pub_date() {
+		date_label(ui, date);
+	}
+	if let Some(open) = link_buttons(ui, article) {
+		action = Some(open);
 	}
+	if let Some(enclosure) = article.enclosure() {
+		ui.horizontal(|ui| {
+			let episode = || Episode {
+				feed_url: feed_url.to_string(),
+				guid: guid.to_string(),
+				title: article.title().unwrap_or("???").to_string(),
+				url: enclosure.url().to_string(),
+			};
+			if ui.button("Play").clicked() {
+				action = Some(ArticleAction::Play(episode()));
+			}
+			if ui.button("Enqueue").clicked() {
+				action = Some(ArticleAction::Enqueue(episode()));
+			}
+			match &download {
+				DownloadState::None => {
+					if ui.button("Download").clicked() {
+						action = Some(ArticleAction::Download(episode()));
+					}
+				}
+				DownloadState::Partial => {
+					if ui.button("Resume download").clicked() {
+						action = Some(ArticleAction::Download(episode()));
+					}
+					if ui.button("Discard download").clicked() {
+						action = Some(ArticleAction::DiscardDownload(episode()));
+					}
+				}
+				DownloadState::Running(progress) => {
+					ui.label(format!("Downloading ({progress})"));
+				}
+				DownloadState::Done => {
+					if ui.button("Delete download").clicked() {
+						action = Some(ArticleAction::DiscardDownload(episode()));
+					}
+				}
+			}
+			ui.label(enclosure.url());
+		});
+		if let Some(summary) = enclosure_summary(article) {
+			ui.label(summary);
+		}
+	}
+	ui.horizontal(|ui| {
+		ui.label(format!("{}% read", read.mul(100.0).round()));
+		if read < 1.0 && ui.button("Mark read").clicked() {
+			set_read(send_mutation, feed_url, guid, 1.0);
+		}
+		if read > 0.0 && ui.button("Mark unread").clicked() {
+			set_read(send_mutation, feed_url, guid, 0.0);
+		}
+	});
+	ui.separator();
+	if let Some(body) = article.content().or(article.description()) {
+		ui.checkbox(raw_html, "Raw HTML");
+		ui.label(display_html(body, *raw_html));
+	}
+	ui.separator();
+	if ui.button("< Done").clicked() {
+		*selected_article = None;
+		set_read(send_mutation, feed_url, guid, 1.0);
+	}
+	action
 }
 
-pub struct Backend {
+pub struct Backend<S> {
 	mutations: Receiver<Mutation>,
 	queued: Arc<AtomicUsize>,
+	/// What the most recent save changed, shown in the status line to make I/O churn visible.
+	last_save: Arc<Mutex<WriteStats>>,
+	/// Filled with the DB from before each batch that changes it.
+	history: Arc<Mutex<UndoHistory>>,
 	new_db: Sender<Arc<Db>>,
+	/// The latest DB, for anything besides the GUI that wants to look at it.
+	latest: tokio::sync::watch::Sender<Arc<Db>>,
 	toast: Sender<(ToastLevel, String)>,
-	path: PathBuf,
+	/// Held for as long as the backend lives, so a second instance can't clobber the DB.
+	store: S,
 	db: Arc<Db>,
+	/// Notices other programs editing the DB, if watching it worked.
+	watcher: Option<DbWatcher>,
+	/// Fires, or is dropped, when the GUI closes.
+	shutdown: oneshot::Receiver<()>,
 }
 
-impl Backend {
-	pub async fn work(&mut self) -> eyre::Result<Infallible> {
+impl<S: Store> Backend<S> {
+	/// Applies mutations as they come until the GUI shuts down, then saves whatever was still
+	/// queued and returns.
+	pub async fn work(&mut self) -> eyre::Result<()> {
 		loop {
 			let mut mutations = vec![];
-			self.mutations.recv_many(&mut mutations, 128).await;
-			self.queued.fetch_add(mutations.len(), Ordering::Relaxed);
-			let mut new_db: Db = tokio::task::spawn_blocking({
-				let path = self.path.clone();
-				move || fs_to_value(&path)
-			})
-			.await??;
-			for mutation in mutations {
-				mutation(&mut new_db, &self.toast)?;
-				self.queued.fetch_sub(1, Ordering::Relaxed);
-			}
-			tokio::task::spawn_blocking({
-				let path = self.path.clone();
-				let new_db = new_db.clone();
-				move || value_to_fs(&path, &new_db)
-			})
-			.await??;
-			if new_db == *self.db {
-				continue;
+			tokio::select! {
+				_ = &mut self.shutdown => {
+					while let Ok(mutation) = self.mutations.try_recv() {
+						mutations.push(mutation);
+					}
+					tracing::info!(queued = mutations.len(), "Shutting down");
+					if !mutations.is_empty() {
+						self.apply(mutations).await?;
+					}
+					return Ok(());
+				}
+				received = self.mutations.recv_many(&mut mutations, BATCH_LIMIT) => {
+					if received == 0 {
+						eyre::bail!("The GUI closed");
+					}
+					self.gather(&mut mutations).await;
+					self.apply(mutations).await?;
+				}
+				() = DbWatcher::changed(&mut self.watcher) => {
+					// Let whatever is editing the DB finish, so it isn't read half-written.
+					tokio::time::sleep(EXTERNAL_CHANGE_SETTLE).await;
+					if let Some(watcher) = &mut self.watcher {
+						while watcher.changes.try_recv().is_ok() {}
+					}
+					self.reload().await?;
+				}
+			}
+		}
+	}
+
+	/// Keeps collecting mutations for a moment after the first, so a burst of them (like marking
+	/// article after article) is saved once rather than once per batch.
+	async fn gather(&mut self, mutations: &mut Vec<Mutation>) {
+		let deadline = tokio::time::Instant::now() + SAVE_DEBOUNCE;
+		while mutations.len() < BATCH_LIMIT {
+			let more = self
+				.mutations
+				.recv_many(mutations, BATCH_LIMIT - mutations.len());
+			match tokio::time::timeout_at(deadline, more).await {
+				Ok(received) if received > 0 => {}
+				_ => break,
+			}
+		}
+	}
+
+	/// Picks up changes another program (a `git pull`, a hand edit) made to the DB, so the next
+	/// save doesn't overwrite them. Changes that were waiting to be made are made to the edited
+	/// DB instead.
+	async fn reload(&mut self) -> eyre::Result<()> {
+		let db = match self.store.reload().await {
+			Ok(db) => db,
+			Err(e) => {
+				self.error(format!(
+					"The DB was changed by another program, but reading it failed with {e}, so the next change will overwrite it"
+				))
+				.await;
+				return Ok(());
+			}
+		};
+		// The watcher reports the backend's own saves too, which change nothing.
+		if db == *self.db {
+			return Ok(());
+		}
+		let mut waiting = vec![];
+		while let Ok(mutation) = self.mutations.try_recv() {
+			waiting.push(mutation);
+		}
+		tracing::info!(
+			waiting = waiting.len(),
+			"Reloading the DB after another program changed it"
+		);
+		// Undoing would go back to before the edit, as if it never happened.
+		self.history.lock().unwrap().states.clear();
+		self.publish(db).await?;
+		if waiting.is_empty() {
+			let _ = self
+				.toast
+				.send((
+					ToastLevel::Info,
+					"Reloaded the DB, which another program changed".to_string(),
+				))
+				.await;
+			return Ok(());
+		}
+		let _ = self
+			.toast
+			.send((
+				ToastLevel::Warning,
+				format!(
+					"Another program changed the DB while {} changes were waiting; they were made on top of its edits, which they may undo",
+					waiting.len()
+				),
+			))
+			.await;
+		self.apply(waiting).await
+	}
+
+	/// Applies a batch of mutations and saves the result. Anything that goes wrong along the way is
+	/// toasted rather than returned, so one bad mutation or a flaky disk doesn't stop the backend
+	/// for good; the only error is the GUI having gone away.
+	async fn apply(&mut self, mutations: Vec<Mutation>) -> eyre::Result<()> {
+		let batch = mutations.len();
+		tracing::debug!(batch, "Applying a batch of mutations");
+		self.queued.fetch_add(batch, Ordering::Relaxed);
+		// What's in memory is what's on disk, since `reload` catches other programs' edits, so
+		// there's no need to read the whole tree back in for every batch.
+		let mut new_db = (*self.db).clone();
+		// How many changes since `new_db` was last a copy of `self.db`.
+		let mut made = 0;
+		for mutation in mutations {
+			let toast = &self.toast;
+			let result = panic::catch_unwind(AssertUnwindSafe(|| mutation(&mut new_db, toast)));
+			self.queued.fetch_sub(1, Ordering::Relaxed);
+			match result {
+				Ok(Ok(())) => made += 1,
+				Ok(Err(e)) => self.error(format!("A change failed: {e}")).await,
+				Err(payload) => {
+					// There's no telling what it left half-done, and no copy from just before it.
+					new_db = (*self.db).clone();
+					let discarded = std::mem::take(&mut made);
+					self.error(format!(
+						"A change crashed with \"{}\", so it and the {discarded} before it in its batch were thrown away",
+						panic_message(&*payload)
+					))
+					.await;
+				}
+			}
+		}
+		let started = Instant::now();
+		let saved = self.store.save(&self.db, &new_db).await;
+		match saved {
+			Ok(stats) => {
+				tracing::info!(
+					batch,
+					made,
+					files_written = stats.files_written,
+					files_removed = stats.files_removed,
+					bytes = stats.bytes,
+					took = ?started.elapsed(),
+					"Saved the DB"
+				);
+				*self.last_save.lock().unwrap() = stats;
 			}
-			self.db = Arc::new(new_db);
-			self.new_db.send(self.db.clone()).await?;
+			Err(e) => {
+				self.error(format!(
+					"Saving the DB failed with {e}, so the last changes weren't kept"
+				))
+				.await;
+				return Ok(());
+			}
+		}
+		self.history
+			.lock()
+			.unwrap()
+			.record(self.db.clone(), &new_db);
+		if new_db == *self.db {
+			return Ok(());
+		}
+		self.publish(new_db).await
+	}
+
+	/// Makes `db` the current DB and sends it to the GUI.
+	async fn publish(&mut self, db: Db) -> eyre::Result<()> {
+		self.db = Arc::new(db);
+		self.latest.send_replace(self.db.clone());
+		self.new_db.send(self.db.clone()).await?;
+		Ok(())
+	}
+
+	/// Follows the DB as the backend saves changes to it.
+	pub fn subscribe(&self) -> tokio::sync::watch::Receiver<Arc<Db>> {
+		self.latest.subscribe()
+	}
+
+	/// Doesn't borrow the backend while sending, since the store needn't be `Sync`.
+	fn error(&self, message: String) -> impl Future<Output = ()> + Send {
+		tracing::error!("{message}");
+		let toast = self.toast.clone();
+		async move {
+			let _ = toast.send((ToastLevel::Error, message)).await;
+		}
+	}
+}
+
+/// What a caught panic said, if it said it with a string.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+	payload
+		.downcast_ref::<&str>()
+		.copied()
+		.or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+		.unwrap_or("no message")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		add_feed, ago, article_key, completion, enclosure_summary, feed_categories, feed_groups,
+		human_size, in_category, mark_read, matches_query, mk_app, move_cursor, move_entry,
+		move_feed, parse_duration, parse_headers, relative_date, search_all, sort_articles,
+		Backend, Config, Db, Feed, FeedAuth, FsStore, Gui, NavKeys, ReadState, SortOrder, Store,
+		UndoHistory, UNDO_DEPTH,
+	};
+	use crate::app::migrations::save_db;
+	use crate::db_fmt::{fs_to_value, value_to_fs};
+	use chrono::{TimeZone, Utc};
+	use egui_notify::ToastLevel;
+	use rss::{extension::itunes::ITunesItemExtension, Channel, Enclosure, Item};
+	use std::{
+		collections::HashMap,
+		path::{Path, PathBuf},
+		sync::Arc,
+		time::{Duration, Instant},
+	};
+	use uuid::Uuid;
+
+	/// Read to `fraction`, with no time it was finished.
+	fn read(fraction: f64) -> ReadState {
+		ReadState {
+			fraction,
+			read_at: None,
+		}
+	}
+
+	/// A new DB at `path`, with the default config.
+	fn test_app(path: &Path) -> (Gui, Backend<FsStore>) {
+		let config = Config {
+			db: path.to_path_buf(),
+			..Default::default()
+		};
+		mk_app(&config, true).unwrap()
+	}
+
+	#[test]
+	fn enclosures_are_summarised() {
+		assert_eq!(human_size(512), "512 B");
+		assert_eq!(human_size(34_200_000), "34.2 MB");
+		assert_eq!(parse_duration("3723"), Some(3723.0));
+		assert_eq!(parse_duration("1:02:03"), Some(3723.0));
+		assert_eq!(parse_duration("62:03"), Some(3723.0));
+		assert_eq!(parse_duration("an hour"), None);
+
+		let mut article = Item {
+			enclosure: Some(Enclosure {
+				url: "https://example.com/ep.mp3".to_string(),
+				length: "34200000".to_string(),
+				mime_type: "audio/mpeg".to_string(),
+			}),
+			itunes_ext: Some(ITunesItemExtension {
+				duration: Some("3723".to_string()),
+				..Default::default()
+			}),
+			..Default::default()
+		};
+		assert_eq!(
+			enclosure_summary(&article).as_deref(),
+			Some("34.2 MB, audio/mpeg, 1:02:03")
+		);
+		// Feeds often send a length of 0 when they don't know it.
+		article.enclosure.as_mut().unwrap().length = "0".to_string();
+		article.itunes_ext = None;
+		assert_eq!(enclosure_summary(&article).as_deref(), Some("audio/mpeg"));
+		assert_eq!(enclosure_summary(&Item::default()), None);
+	}
+
+	#[test]
+	fn guidless_articles_have_independent_read_state() {
+		let item = |title: &str| Item {
+			title: Some(title.to_string()),
+			link: Some(format!("https://example.com/{title}")),
+			..Default::default()
+		};
+		let mut feed = Feed {
+			feed: Channel {
+				items: vec![item("one"), item("two")],
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let [one, two] = [0, 1].map(|n| article_key(&feed.feed.items[n]).into_owned());
+		assert_ne!(one, two);
+		assert_eq!(article_key(&item("one")), one);
+
+		feed.read_articles.insert(one.clone(), read(1.0));
+		assert_eq!(feed.read_articles.get(&one), Some(&read(1.0)));
+		assert_eq!(feed.read_articles.get(&two), None);
+		assert_eq!(feed.unread(), 1);
+		// Partly read still counts as unread.
+		feed.read_articles.insert(two.clone(), read(0.5));
+		assert_eq!(feed.unread(), 1);
+	}
+
+	#[test]
+	fn query_matches_title_or_description() {
+		let article = Item {
+			title: Some("Rust 2.0 Released".to_string()),
+			description: Some("A <b>big</b> day".to_string()),
+			..Default::default()
+		};
+		assert!(matches_query(&article, ""));
+		assert!(matches_query(&article, "rust 2"));
+		assert!(matches_query(&article, "big"));
+		assert!(!matches_query(&article, "python"));
+		assert!(!matches_query(&Item::default(), "rust"));
+	}
+
+	#[test]
+	fn categories_are_counted_once_per_article() {
+		let item = |categories: &[&str]| Item {
+			categories: categories
+				.iter()
+				.map(|name| rss::Category {
+					name: name.to_string(),
+					domain: None,
+				})
+				.collect(),
+			..Default::default()
+		};
+		let feed = Feed {
+			feed: Channel {
+				items: vec![
+					item(&["Rust", " Rust ", "News"]),
+					item(&["News"]),
+					item(&[]),
+					item(&["", "Audio"]),
+				],
+				..Default::default()
+			},
+			..Default::default()
+		};
+		assert_eq!(
+			feed_categories(&feed),
+			[("News", 2), ("Audio", 1), ("Rust", 1)]
+		);
+		assert!(in_category(&feed.feed.items[0], "Rust"));
+		assert!(!in_category(&feed.feed.items[2], "Rust"));
+		assert!(feed_categories(&Feed::default()).is_empty());
+	}
+
+	#[test]
+	fn global_search_spans_feeds_and_caps_results() {
+		let feed = |title: &str, articles: &[&str]| Feed {
+			feed: Channel {
+				title: title.to_string(),
+				items: articles
+					.iter()
+					.map(|a| Item {
+						title: Some(a.to_string()),
+						..Default::default()
+					})
+					.collect(),
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let mut db = Db::default();
+		db.feeds
+			.insert("b".to_string(), feed("B", &["rust news", "go news"]));
+		db.feeds.insert("a".to_string(), feed("A", &["Rust tips"]));
+		let found: Vec<_> = search_all(&db, "rust", false, 10)
+			.into_iter()
+			.map(|(url, _, article)| (url, article.title().unwrap()))
+			.collect();
+		assert_eq!(found, [("a", "Rust tips"), ("b", "rust news")]);
+		assert_eq!(search_all(&db, "news", false, 1).len(), 1);
+	}
+
+	#[test]
+	fn the_starred_view_gathers_stars_from_every_feed() {
+		let feed = |articles: &[&str], starred: &[&str]| Feed {
+			feed: Channel {
+				items: articles
+					.iter()
+					.map(|a| Item {
+						title: Some(a.to_string()),
+						guid: Some(rss::Guid {
+							value: a.to_string(),
+							..Default::default()
+						}),
+						..Default::default()
+					})
+					.collect(),
+				..Default::default()
+			},
+			starred: starred.iter().map(|s| s.to_string()).collect(),
+			..Default::default()
+		};
+		let mut db = Db::default();
+		db.feeds
+			.insert("a".to_string(), feed(&["one", "two"], &["two"]));
+		db.feeds.insert(
+			"b".to_string(),
+			feed(&["three", "four"], &["three", "gone"]),
+		);
+		let found: Vec<_> = search_all(&db, "", true, 10)
+			.into_iter()
+			.map(|(url, _, article)| (url, article.title().unwrap()))
+			.collect();
+		assert_eq!(found, [("a", "two"), ("b", "three")]);
+		// Stars on articles that dropped out of the feed aren't counted.
+		assert_eq!(db.feeds["b"].starred_count(), 1);
+	}
+
+	#[tokio::test]
+	async fn marking_above_goes_by_the_shown_order() {
+		let item = |title: &str, date: &str| Item {
+			title: Some(title.to_string()),
+			pub_date: Some(date.to_string()),
+			..Default::default()
+		};
+		let mut db = Db::default();
+		db.feeds.insert(
+			"feed".to_string(),
+			Feed {
+				feed: Channel {
+					items: vec![
+						item("middle", "Tue, 02 Jan 2024 00:00:00 +0000"),
+						item("old", "Mon, 01 Jan 2024 00:00:00 +0000"),
+						item("new", "Wed, 03 Jan 2024 00:00:00 +0000"),
+					],
+					..Default::default()
+				},
+				..Default::default()
+			},
+		);
+		let mut articles: Vec<_> = db.feeds["feed"].feed.items.iter().collect();
+		sort_articles(&mut articles, SortOrder::NewestFirst);
+		let shown: Vec<_> = articles
+			.iter()
+			.map(|article| article_key(article).into_owned())
+			.collect();
+		let (send_mutation, mut mutations) = tokio::sync::mpsc::channel(1);
+		mark_read(&send_mutation, "feed", shown[..2].to_vec());
+		let (send_toast, _) = tokio::sync::mpsc::channel(1);
+		(mutations.recv().await.unwrap())(&mut db, &send_toast).unwrap();
+		let feed = &db.feeds["feed"];
+		let mut read: Vec<_> = feed
+			.feed
+			.items
+			.iter()
+			.filter(|article| completion(feed, &article_key(article)) == 1.0)
+			.map(|article| article.title().unwrap())
+			.collect();
+		read.sort();
+		assert_eq!(read, ["middle", "new"]);
+	}
+
+	#[test]
+	fn date_sort_puts_undated_articles_last() {
+		let item = |title: &str, date: Option<&str>| Item {
+			title: Some(title.to_string()),
+			pub_date: date.map(str::to_string),
+			..Default::default()
+		};
+		let items = [
+			item("undated", None),
+			item("old", Some("Mon, 01 Jan 2024 00:00:00 +0000")),
+			item("garbage", Some("yesterday")),
+			item("new", Some("Tue, 02 Jan 2024 09:00:00 +0200")),
+		];
+		let sorted = |order| {
+			let mut articles: Vec<_> = items.iter().collect();
+			sort_articles(&mut articles, order);
+			articles
+				.into_iter()
+				.map(|a| a.title().unwrap())
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			sorted(SortOrder::NewestFirst),
+			["new", "old", "undated", "garbage"]
+		);
+		assert_eq!(
+			sorted(SortOrder::OldestFirst),
+			["old", "new", "undated", "garbage"]
+		);
+		assert_eq!(
+			sorted(SortOrder::Feed),
+			["undated", "old", "garbage", "new"]
+		);
+	}
+
+	#[test]
+	fn readding_a_feed_keeps_read_state() {
+		let url = "https://example.com/feed".to_string();
+		let mut db = Db::default();
+		db.feeds.insert(
+			url.clone(),
+			Feed {
+				read_articles: [("guid".to_string(), read(1.0))].into(),
+				custom_title: Some("Mine".to_string()),
+				category: Some("News".to_string()),
+				refresh_enabled: false,
+				headers: [("User-Agent".to_string(), "Mine/1.0".to_string())].into(),
+				..Default::default()
+			},
+		);
+		let staged = Feed {
+			feed: Channel {
+				title: "New".to_string(),
+				..Default::default()
+			},
+			..Default::default()
+		};
+		add_feed(&mut db, url.clone(), staged);
+		let feed = &db.feeds[&url];
+		assert_eq!(feed.feed.title, "New");
+		assert_eq!(feed.read_articles.get("guid"), Some(&read(1.0)));
+		assert_eq!(feed.title(), "Mine");
+		assert_eq!(feed.category.as_deref(), Some("News"));
+		assert!(!feed.refresh_enabled);
+		assert_eq!(feed.headers["User-Agent"], "Mine/1.0");
+	}
+
+	#[test]
+	fn moving_a_feed_keeps_read_state_but_not_over_another_feed() {
+		let (old, new) = (
+			"https://old.example.com/feed",
+			"https://new.example.com/feed",
+		);
+		let mut db = Db::default();
+		db.feeds.insert(
+			old.to_string(),
+			Feed {
+				read_articles: [("guid".to_string(), read(1.0))].into(),
+				etag: Some("\"abc\"".to_string()),
+				..Default::default()
+			},
+		);
+		db.queue.push((old.to_string(), "guid".to_string()));
+		move_feed(&mut db, old, new.to_string()).unwrap();
+		assert!(!db.feeds.contains_key(old));
+		let feed = &db.feeds[new];
+		assert_eq!(feed.read_articles.get("guid"), Some(&read(1.0)));
+		assert_eq!(feed.etag, None);
+		assert_eq!(db.queue, [(new.to_string(), "guid".to_string())]);
+
+		db.feeds.insert(old.to_string(), Feed::default());
+		let before = db.clone();
+		assert!(move_feed(&mut db, old, new.to_string()).is_err());
+		assert!(db == before);
+	}
+
+	#[test]
+	fn typed_headers_are_checked() {
+		let headers =
+			parse_headers("X-Api-Key: abc\n\n  Accept : application/rss+xml  \n").unwrap();
+		assert_eq!(
+			headers,
+			[
+				("Accept".to_string(), "application/rss+xml".to_string()),
+				("X-Api-Key".to_string(), "abc".to_string()),
+			]
+			.into()
+		);
+		assert!(parse_headers("").unwrap().is_empty());
+		assert!(parse_headers("No colon").is_err());
+		assert!(parse_headers("Bad name: x").is_err());
+	}
+
+	#[test]
+	fn queue_entries_move_within_bounds() {
+		let mut queue = vec!['a', 'b', 'c', 'd'];
+		move_entry(&mut queue, &'a', 2);
+		assert_eq!(queue, ['b', 'c', 'a', 'd']);
+		move_entry(&mut queue, &'a', -1);
+		assert_eq!(queue, ['b', 'a', 'c', 'd']);
+		move_entry(&mut queue, &'d', 5);
+		assert_eq!(queue, ['b', 'a', 'c', 'd']);
+		move_entry(&mut queue, &'c', -5);
+		assert_eq!(queue, ['c', 'b', 'a', 'd']);
+		move_entry(&mut queue, &'z', 1);
+		assert_eq!(queue, ['c', 'b', 'a', 'd']);
+	}
+
+	#[test]
+	fn optional_feed_fields_round_trip() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let mut feed = Feed {
+			feed: Channel {
+				title: "Upstream".to_string(),
+				..Default::default()
+			},
+			..Default::default()
+		};
+		value_to_fs(&path, &feed).unwrap();
+		let loaded: Feed = fs_to_value(&path).unwrap();
+		assert_eq!(loaded.custom_title, None);
+		assert_eq!(loaded.title(), "Upstream");
+		assert_eq!(loaded.last_refreshed, None);
+
+		feed.custom_title = Some("Mine".to_string());
+		feed.last_refreshed = Some(Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap());
+		feed.category = Some("News".to_string());
+		feed.refresh_enabled = false;
+		feed.read_articles.insert("gone".to_string(), read(1.0));
+		feed.missing_since.insert(
+			"gone".to_string(),
+			Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+		);
+		feed.auth = FeedAuth::Basic {
+			user: "me".to_string(),
+			password: "hunter2".to_string(),
+		};
+		feed.downloads
+			.insert("gone".to_string(), PathBuf::from("/cache/episode.mp3"));
+		feed.starred.insert("gone".to_string());
+		value_to_fs(&path, &feed).unwrap();
+		let loaded: Feed = fs_to_value(&path).unwrap();
+		assert_eq!(loaded.title(), "Mine");
+		assert_eq!(loaded.last_refreshed, feed.last_refreshed);
+		assert_eq!(loaded.category.as_deref(), Some("News"));
+		assert!(!loaded.refresh_enabled);
+		assert_eq!(loaded.missing_since, feed.missing_since);
+		assert_eq!(loaded.auth, feed.auth);
+		assert_eq!(loaded.downloads, feed.downloads);
+		assert_eq!(loaded.starred, feed.starred);
+
+		// Feeds saved before these fields existed have no entries for them at all.
+		std::fs::remove_file(path.join("key_Y3VzdG9tX3RpdGxl")).unwrap();
+		std::fs::remove_file(path.join("key_bGFzdF9yZWZyZXNoZWQ=")).unwrap();
+		std::fs::remove_file(path.join("key_Y2F0ZWdvcnk=")).unwrap();
+		std::fs::remove_file(path.join("key_cmVmcmVzaF9lbmFibGVk")).unwrap();
+		std::fs::remove_dir_all(path.join("key_bWlzc2luZ19zaW5jZQ==")).unwrap();
+		std::fs::remove_dir_all(path.join("key_YXV0aA==")).unwrap();
+		std::fs::remove_dir_all(path.join("key_ZG93bmxvYWRz")).unwrap();
+		std::fs::remove_dir_all(path.join("key_c3RhcnJlZA==")).unwrap();
+		let loaded: Feed = fs_to_value(&path).unwrap();
+		assert_eq!(loaded.custom_title, None);
+		assert_eq!(loaded.last_refreshed, None);
+		assert_eq!(loaded.category, None);
+		assert!(loaded.refresh_enabled);
+		assert!(loaded.missing_since.is_empty());
+		assert_eq!(loaded.auth, FeedAuth::None);
+		assert!(loaded.downloads.is_empty());
+		assert!(loaded.starred.is_empty());
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn relative_times() {
+		let now = Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap();
+		assert_eq!(ago(None, now), "never");
+		assert_eq!(ago(Some(now - Duration::from_secs(5)), now), "just now");
+		assert_eq!(ago(Some(now - Duration::from_secs(5 * 60)), now), "5m ago");
+		assert_eq!(
+			ago(Some(now - Duration::from_secs(3 * 3600)), now),
+			"3h ago"
+		);
+		assert_eq!(
+			ago(Some(now - Duration::from_secs(2 * 86400)), now),
+			"2d ago"
+		);
+		// Clock skew shouldn't produce nonsense.
+		assert_eq!(
+			ago(Some(now + Duration::from_secs(5 * 60)), now),
+			"just now"
+		);
+	}
+
+	#[test]
+	fn relative_dates() {
+		let now = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+		let date = |d: &str| relative_date(d, now);
+		assert_eq!(date("Sun, 10 Mar 2024 09:00:00 +0000"), "3h ago");
+		assert_eq!(date("Sat, 09 Mar 2024 09:00:00 +0000"), "yesterday");
+		assert_eq!(date("Fri, 05 Jan 2024 09:00:00 +0000"), "Jan 5");
+		assert_eq!(date("Thu, 05 Jan 2023 09:00:00 +0000"), "Jan 5, 2023");
+		// Offsets count, so this is the same moment as noon UTC.
+		assert_eq!(date("Sun, 10 Mar 2024 07:00:00 -0500"), "just now");
+		assert_eq!(date(" sometime last week "), "sometime last week");
+	}
+
+	/// eframe's storage, kept in memory.
+	#[derive(Default)]
+	struct MemoryStorage(HashMap<String, String>);
+
+	impl eframe::Storage for MemoryStorage {
+		fn get_string(&self, key: &str) -> Option<String> {
+			self.0.get(key).cloned()
+		}
+
+		fn set_string(&mut self, key: &str, value: String) {
+			self.0.insert(key.to_string(), value);
+		}
+
+		fn flush(&mut self) {}
+	}
+
+	#[tokio::test]
+	async fn the_open_feed_and_list_view_come_back_unless_theyre_gone() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let (mut gui, _backend) = test_app(&path);
+		let feed = Feed {
+			feed: Channel {
+				items: vec![Item {
+					guid: Some(rss::Guid {
+						value: "a".to_string(),
+						..Default::default()
+					}),
+					..Default::default()
+				}],
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let mut db = Db::default();
+		db.feeds.insert("feed".to_string(), feed);
+		gui.db = Arc::new(db.clone());
+		let open = Some(("feed".to_string(), Some("a".to_string())));
+		gui.selected_feed = open.clone();
+		gui.sort_order = SortOrder::NewestFirst;
+		gui.unread_only = true;
+		let mut storage = MemoryStorage::default();
+		eframe::App::save(&mut gui, &mut storage);
+
+		gui.selected_feed = None;
+		gui.sort_order = SortOrder::Feed;
+		gui.unread_only = false;
+		gui.restore(Some(&storage));
+		assert_eq!(gui.selected_feed, open);
+		assert_eq!(gui.sort_order, SortOrder::NewestFirst);
+		assert!(gui.unread_only);
+
+		// The article dropped out of the feed, so the feed opens without it.
+		db.feeds.get_mut("feed").unwrap().feed.items.clear();
+		gui.db = Arc::new(db.clone());
+		gui.restore(Some(&storage));
+		assert_eq!(gui.selected_feed, Some(("feed".to_string(), None)));
+
+		// The feed was deleted, so the feed list opens.
+		db.feeds.clear();
+		gui.db = Arc::new(db);
+		gui.restore(Some(&storage));
+		assert_eq!(gui.selected_feed, None);
+		gui.restore(None);
+		assert_eq!(gui.selected_feed, None);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn cursor_stays_on_the_list() {
+		let down = NavKeys {
+			down: true,
+			..Default::default()
+		};
+		let up = NavKeys {
+			up: true,
+			..Default::default()
+		};
+		let none = NavKeys::default();
+		assert_eq!(move_cursor(None, 3, &none), None);
+		assert_eq!(move_cursor(None, 3, &down), Some(0));
+		assert_eq!(move_cursor(Some(0), 3, &down), Some(1));
+		assert_eq!(move_cursor(Some(2), 3, &down), Some(2));
+		assert_eq!(move_cursor(Some(0), 3, &up), Some(0));
+		// A filter that shrinks the list pulls the cursor back onto it.
+		assert_eq!(move_cursor(Some(5), 2, &none), Some(1));
+		assert_eq!(move_cursor(Some(0), 0, &down), None);
+	}
+
+	#[test]
+	fn feeds_group_by_category_with_uncategorized_last() {
+		let feed = |title: &str, category: Option<&str>| Feed {
+			feed: Channel {
+				title: title.to_string(),
+				..Default::default()
+			},
+			category: category.map(str::to_string),
+			..Default::default()
+		};
+		let mut db = Db::default();
+		for (url, title, category) in [
+			("a", "Loose", None),
+			("b", "Zed", Some("Tech")),
+			("c", "Alpha", Some("Tech")),
+			("d", "Daily", Some("News")),
+		] {
+			db.feeds.insert(url.to_string(), feed(title, category));
+		}
+		let groups: Vec<_> = feed_groups(&db)
+			.into_iter()
+			.map(|(category, feeds)| {
+				let titles: Vec<_> = feeds.iter().map(|(_, f)| f.title()).collect();
+				(category, titles)
+			})
+			.collect();
+		assert_eq!(
+			groups,
+			[
+				(Some("News"), vec!["Daily"]),
+				(Some("Tech"), vec!["Alpha", "Zed"]),
+				(None, vec!["Loose"]),
+			]
+		);
+	}
+
+	#[test]
+	fn undo_steps_back_through_changes() {
+		let state = |title: &str| {
+			let mut db = Db::default();
+			db.feeds.insert(
+				"feed".to_string(),
+				Feed {
+					custom_title: Some(title.to_string()),
+					..Default::default()
+				},
+			);
+			Arc::new(db)
+		};
+		let title = |db: &Db| db.feeds["feed"].title().to_string();
+		let mut history = UndoHistory::default();
+		history.record(state("a"), &state("b"));
+		// Batches that change nothing aren't worth undoing.
+		history.record(state("b"), &state("b"));
+		history.record(state("b"), &state("c"));
+
+		let undone = history.undo().unwrap();
+		assert_eq!(title(&undone), "b");
+		// Restoring "b" over "c" isn't recorded, so the next undo goes further back.
+		history.record(state("c"), &undone);
+		assert_eq!(title(&history.undo().unwrap()), "a");
+		history.record(state("b"), &state("a"));
+		assert!(history.undo().is_none());
+
+		for n in 0..UNDO_DEPTH + 5 {
+			history.record(state(&n.to_string()), &state("next"));
+		}
+		assert_eq!(history.states.len(), UNDO_DEPTH);
+		assert_eq!(title(&history.states[0]), "5");
+	}
+
+	#[test]
+	fn new_dbs_start_with_the_configs_settings() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let mut config = Config {
+			db: path.clone(),
+			..Default::default()
+		};
+		config.settings.refresh_interval_mins = 5;
+		let (gui, backend) = mk_app::<FsStore>(&config, true).unwrap();
+		assert_eq!(gui.db.settings.refresh_interval_mins, 5);
+		drop(backend);
+		// Once the DB exists, its own settings win.
+		config.settings.refresh_interval_mins = 60;
+		let (gui, _backend) = mk_app::<FsStore>(&config, false).unwrap();
+		assert_eq!(gui.db.settings.refresh_interval_mins, 5);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn failed_mutations_are_toasted_and_the_rest_still_apply() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let (mut gui, mut backend) = test_app(&path);
+		backend
+			.apply(vec![
+				Box::new(|_, _| Err(eyre::eyre!("boom"))),
+				Box::new(|db, _| {
+					db.settings.auto_refresh = false;
+					Ok(())
+				}),
+			])
+			.await
+			.unwrap();
+		let (level, message) = gui.recv_toast.try_recv().unwrap();
+		assert!(matches!(level, ToastLevel::Error));
+		assert!(message.contains("boom"), "{message}");
+		assert!(!gui.new_state.try_recv().unwrap().settings.auto_refresh);
+		let saved: Db = fs_to_value(&path).unwrap();
+		assert!(!saved.settings.auto_refresh);
+		drop(backend);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn waiting_mutations_are_made_on_top_of_external_edits() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let (mut gui, mut backend) = test_app(&path);
+		let mut edited = Db::default();
+		edited.settings.auto_refresh = false;
+		save_db(&path, &edited).unwrap();
+		gui.mutations
+			.send(Box::new(|db, _| {
+				db.queue.push(("feed".to_string(), "article".to_string()));
+				Ok(())
+			}))
+			.await
+			.unwrap();
+		backend.reload().await.unwrap();
+		let (level, _) = gui.recv_toast.try_recv().unwrap();
+		assert!(matches!(level, ToastLevel::Warning));
+		let saved: Db = fs_to_value(&path).unwrap();
+		assert!(!saved.settings.auto_refresh);
+		assert_eq!(saved.queue.len(), 1);
+		// Undo can take back the waiting change, but not the edit.
+		let history = backend.history.lock().unwrap();
+		assert_eq!(history.states.len(), 1);
+		assert!(!history.states[0].settings.auto_refresh);
+		drop(history);
+		drop(backend);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn external_edits_reach_the_gui() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let (mut gui, mut backend) = test_app(&path);
+		let backend = tokio::spawn(async move { backend.work().await });
+		let mut edited = Db::default();
+		edited.settings.auto_refresh = false;
+		save_db(&path, &edited).unwrap();
+		let reloaded = tokio::time::timeout(Duration::from_secs(10), gui.new_state.recv())
+			.await
+			.unwrap()
+			.unwrap();
+		assert!(!reloaded.settings.auto_refresh);
+		backend.abort();
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn shutting_down_saves_what_was_queued() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let (mut gui, mut backend) = test_app(&path);
+		for n in 0..3 {
+			gui.mutations
+				.send(Box::new(move |db, _| {
+					db.queue.push(("feed".to_string(), n.to_string()));
+					Ok(())
+				}))
+				.await
+				.unwrap();
+		}
+		gui.shutdown.take().unwrap().send(()).unwrap();
+		tokio::time::timeout(Duration::from_secs(10), backend.work())
+			.await
+			.unwrap()
+			.unwrap();
+		let saved: Db = fs_to_value(&path).unwrap();
+		assert_eq!(saved.queue.len(), 3);
+		drop(backend);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn a_burst_of_mutations_is_saved_once() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let (mut gui, mut backend) = test_app(&path);
+		let backend = tokio::spawn(async move { backend.work().await });
+		for n in 0..5 {
+			gui.send_mutation(Box::new(move |db, _| {
+				db.queue.push(("feed".to_string(), n.to_string()));
+				Ok(())
+			}));
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+		let saved = tokio::time::timeout(Duration::from_secs(10), gui.new_state.recv())
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(saved.queue.len(), 5);
+		backend.abort();
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn a_panicking_mutation_doesnt_stop_the_backend() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let (mut gui, mut backend) = test_app(&path);
+		backend
+			.apply(vec![
+				Box::new(|db, _| {
+					db.queue.push(("feed".to_string(), "before".to_string()));
+					Ok(())
+				}),
+				Box::new(|db, _| {
+					db.settings.auto_refresh = false;
+					panic!("oops")
+				}),
+				Box::new(|db, _| {
+					db.queue.push(("feed".to_string(), "after".to_string()));
+					Ok(())
+				}),
+			])
+			.await
+			.unwrap();
+		let (level, message) = gui.recv_toast.try_recv().unwrap();
+		assert!(matches!(level, ToastLevel::Error));
+		assert!(message.contains("oops"), "{message}");
+		assert!(message.contains("the 1 before it"), "{message}");
+		let saved: Db = fs_to_value(&path).unwrap();
+		assert!(saved.settings.auto_refresh);
+		assert_eq!(saved.queue, [("feed".to_string(), "after".to_string())]);
+		assert_eq!(backend.queued.load(std::sync::atomic::Ordering::Relaxed), 0);
+		drop(backend);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn read_state_outlives_a_shrinking_feed_for_a_while() {
+		let item = |guid: &str| Item {
+			guid: Some(rss::Guid {
+				value: guid.to_string(),
+				permalink: false,
+			}),
+			..Default::default()
+		};
+		let mut feed = Feed {
+			feed: Channel {
+				items: vec![item("a"), item("b"), item("c")],
+				..Default::default()
+			},
+			read_articles: [("a", 1.0), ("b", 0.5), ("c", 1.0)]
+				.map(|(key, fraction)| (key.to_string(), read(fraction)))
+				.into(),
+			..Default::default()
+		};
+		let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+		let grace = chrono::Duration::days(30);
+
+		feed.feed.items = vec![item("c")];
+		feed.prune_read_articles(start, grace);
+		assert_eq!(feed.read_articles.len(), 3);
+		assert_eq!(feed.missing_since.len(), 2);
+
+		// "b" comes back for a bit, and starts its grace window over once it's gone again.
+		feed.feed.items = vec![item("b"), item("c")];
+		feed.prune_read_articles(start + chrono::Duration::days(20), grace);
+		feed.feed.items = vec![item("c")];
+		feed.prune_read_articles(start + chrono::Duration::days(21), grace);
+		assert_eq!(feed.missing_since["b"], start + chrono::Duration::days(21));
+
+		feed.prune_read_articles(start + chrono::Duration::days(31), grace);
+		let mut kept: Vec<_> = feed.read_articles.keys().map(String::as_str).collect();
+		kept.sort();
+		assert_eq!(kept, ["b", "c"]);
+		assert_eq!(feed.missing_since.len(), 1);
+	}
+
+	/// Saves a big DB over and over, so it's left out of the usual run. See what applying each
+	/// batch to the DB in memory saves over reading it back in first with
+	/// `cargo test --release applying_batches_benchmark -- --ignored --nocapture`.
+	#[tokio::test]
+	#[ignore]
+	async fn applying_batches_benchmark() {
+		const BATCHES: usize = 5;
+		let path = std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()));
+		let mut db = Db::default();
+		for feed in 0..50 {
+			let items = (0..50)
+				.map(|item| Item {
+					title: Some(format!("Article {item}")),
+					description: Some("Some words. ".repeat(50)),
+					..Default::default()
+				})
+				.collect();
+			let feed = Feed {
+				feed: Channel {
+					title: format!("Feed {feed}"),
+					items,
+					..Default::default()
+				},
+				..Default::default()
+			};
+			db.feeds
+				.insert(format!("https://example.com/{}", feed.feed.title), feed);
+		}
+		let mut store = FsStore::open(&path, true).unwrap();
+		store.save(&Db::default(), &db).await.unwrap();
+		let mark = |db: &mut Db, batch: usize| {
+			let feed = db.feeds.values_mut().nth(batch).unwrap();
+			feed.read_articles
+				.insert("Article 0".to_string(), read(1.0));
+		};
+
+		let start = Instant::now();
+		for batch in 0..BATCHES {
+			let mut next: Db = fs_to_value(&path).unwrap();
+			mark(&mut next, batch);
+			store.save(&db, &next).await.unwrap();
+			db = next;
+		}
+		let reloading = start.elapsed();
+		let start = Instant::now();
+		for batch in BATCHES..2 * BATCHES {
+			let mut next = db.clone();
+			mark(&mut next, batch);
+			store.save(&db, &next).await.unwrap();
+			db = next;
 		}
+		let in_memory = start.elapsed();
+		println!("{BATCHES} batches: reloading {reloading:?}, in memory {in_memory:?}");
+		assert!(fs_to_value::<Db>(&path).unwrap() == db);
+		drop(store);
+		std::fs::remove_dir_all(&path).unwrap();
 	}
 }
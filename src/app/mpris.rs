@@ -0,0 +1,231 @@
+use super::PLAYBACK_RATES;
+use eframe::egui::Context;
+use mpris_server::{Metadata, PlaybackStatus, Player, Time, TrackId};
+use std::sync::{
+	mpsc::{self, Receiver},
+	Arc, OnceLock,
+};
+use tokio::{
+	sync::watch,
+	task::{JoinHandle, LocalSet},
+};
+
+/// What the desktop's media controls asked for.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MprisCommand {
+	PlayPause,
+	Play,
+	Pause,
+	Stop,
+	Next,
+	Previous,
+	/// Skip this many seconds forward, or back if negative.
+	Jump(f64),
+	/// Go to this many seconds in.
+	SetPosition(f64),
+}
+
+/// What's playing, as the media controls should show it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NowPlaying {
+	pub title: String,
+	/// The feed the episode came from.
+	pub feed: String,
+	pub url: String,
+	/// Seconds in.
+	pub time: f64,
+	/// Seconds long, or 0 if unknown.
+	pub length: f64,
+	pub paused: bool,
+	pub rate: f64,
+	pub volume: f64,
+	/// Whether there's anything queued to skip to.
+	pub can_go_next: bool,
+}
+
+/// The GUI's end of the MPRIS endpoint.
+pub struct Mpris {
+	pub commands: Receiver<MprisCommand>,
+	state: watch::Sender<Option<NowPlaying>>,
+}
+
+impl Mpris {
+	/// Shows `now` in the media controls, or takes Winter2 off the session bus if nothing's
+	/// playing.
+	pub fn update(&self, now: Option<NowPlaying>) {
+		self.state.send_if_modified(|state| {
+			let changed = *state != now;
+			*state = now;
+			changed
+		});
+	}
+}
+
+/// Serves the MPRIS endpoint from a thread of its own, since the player can't leave the thread it
+/// was made on. Commands wake the window once `window` is set.
+pub fn spawn(window: Arc<OnceLock<Context>>) -> Mpris {
+	let (send_commands, commands) = mpsc::channel();
+	let (state, recv_state) = watch::channel(None);
+	std::thread::spawn(move || {
+		let runtime = match tokio::runtime::Builder::new_current_thread()
+			.enable_all()
+			.build()
+		{
+			Ok(runtime) => runtime,
+			Err(e) => {
+				tracing::warn!("Couldn't start the MPRIS endpoint: {e}");
+				return;
+			}
+		};
+		let send = move |command| {
+			if send_commands.send(command).is_ok() {
+				if let Some(window) = window.get() {
+					window.request_repaint();
+				}
+			}
+		};
+		LocalSet::new().block_on(&runtime, serve(recv_state, send));
+	});
+	Mpris { commands, state }
+}
+
+/// The endpoint while it's on the bus. Dropping it takes it off.
+struct Registered {
+	player: Player,
+	server: JoinHandle<()>,
+}
+
+impl Drop for Registered {
+	fn drop(&mut self) {
+		self.server.abort();
+	}
+}
+
+async fn serve(
+	mut state: watch::Receiver<Option<NowPlaying>>,
+	send: impl Fn(MprisCommand) + Clone + 'static,
+) {
+	let mut registered: Option<Registered> = None;
+	// If there's no session bus to register with, there's no trying again until playback stops.
+	let mut failed = false;
+	let mut track = 0;
+	let mut last: Option<NowPlaying> = None;
+	loop {
+		let now = state.borrow_and_update().clone();
+		if let Some(now) = now {
+			if registered.is_none() && !failed {
+				match register(send.clone()).await {
+					Ok(player) => registered = Some(player),
+					Err(e) => {
+						tracing::warn!("Couldn't register with MPRIS: {e}");
+						failed = true;
+					}
+				}
+			}
+			if let Some(Registered { player, .. }) = &registered {
+				let new_track = last.as_ref().is_none_or(|last| last.url != now.url);
+				if new_track {
+					track += 1;
+				}
+				if let Err(e) = show(player, &now, track, last.as_ref(), new_track).await {
+					tracing::warn!("Couldn't update MPRIS: {e}");
+				}
+			}
+			last = Some(now);
+		} else {
+			registered = None;
+			failed = false;
+			last = None;
+		}
+		if state.changed().await.is_err() {
+			break;
+		}
+	}
+}
+
+/// Takes Winter2's name on the session bus, forwarding what it's asked to do to `send`.
+async fn register(
+	send: impl Fn(MprisCommand) + Clone + 'static,
+) -> mpris_server::zbus::Result<Registered> {
+	let player = Player::builder("winter2")
+		.identity("Winter2")
+		.can_play(true)
+		.can_pause(true)
+		.can_seek(true)
+		.can_go_previous(true)
+		.minimum_rate(PLAYBACK_RATES[0])
+		.maximum_rate(PLAYBACK_RATES[PLAYBACK_RATES.len() - 1])
+		.build()
+		.await?;
+	let on = |command| {
+		let send = send.clone();
+		move |_: &Player| send(command)
+	};
+	player.connect_play_pause(on(MprisCommand::PlayPause));
+	player.connect_play(on(MprisCommand::Play));
+	player.connect_pause(on(MprisCommand::Pause));
+	player.connect_stop(on(MprisCommand::Stop));
+	player.connect_next(on(MprisCommand::Next));
+	player.connect_previous(on(MprisCommand::Previous));
+	let seek = send.clone();
+	player.connect_seek(move |_, offset| seek(MprisCommand::Jump(seconds(offset))));
+	player.connect_set_position(move |player, track, position| {
+		// Asking to move within a track that's since ended does nothing.
+		if player.metadata().trackid().as_ref() == Some(track) {
+			send(MprisCommand::SetPosition(seconds(position)));
+		}
+	});
+	let server = tokio::task::spawn_local(player.run());
+	Ok(Registered { player, server })
+}
+
+async fn show(
+	player: &Player,
+	now: &NowPlaying,
+	track: u64,
+	last: Option<&NowPlaying>,
+	new_track: bool,
+) -> mpris_server::zbus::Result<()> {
+	if new_track || last.map(|last| last.length) != Some(now.length) {
+		let mut metadata = Metadata::builder()
+			.trackid(track_id(track))
+			.title(&now.title)
+			.album(&now.feed)
+			.artist([&now.feed])
+			.url(&now.url)
+			.build();
+		if now.length > 0.0 {
+			metadata.set_length(Some(time(now.length)));
+		}
+		player.set_metadata(metadata).await?;
+	}
+	player
+		.set_playback_status(if now.paused {
+			PlaybackStatus::Paused
+		} else {
+			PlaybackStatus::Playing
+		})
+		.await?;
+	player.set_position(time(now.time));
+	// Anything further off than a poll would account for was a seek.
+	let jumped = last.is_some_and(|last| (now.time - last.time).abs() > 2.0 * now.rate.max(1.0));
+	if jumped && !new_track {
+		player.seeked(time(now.time)).await?;
+	}
+	player.set_rate(now.rate).await?;
+	player.set_volume(now.volume).await?;
+	player.set_can_go_next(now.can_go_next).await?;
+	Ok(())
+}
+
+fn track_id(track: u64) -> TrackId {
+	TrackId::try_from(format!("/net/winter2/track/{track}")).unwrap_or(TrackId::NO_TRACK)
+}
+
+fn time(seconds: f64) -> Time {
+	Time::from_micros((seconds * 1e6) as i64)
+}
+
+fn seconds(time: Time) -> f64 {
+	time.as_micros() as f64 / 1e6
+}
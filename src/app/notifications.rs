@@ -0,0 +1,132 @@
+use eframe::egui::Context;
+use std::{
+	collections::BTreeMap,
+	sync::{Arc, OnceLock},
+	time::Duration,
+};
+use tokio::{
+	sync::mpsc::{self, Receiver, Sender},
+	time::Instant,
+};
+
+/// How long to keep collecting new articles after the first, so a whole refresh makes one
+/// notification rather than one per feed.
+const DEBOUNCE: Duration = Duration::from_secs(5);
+/// The most feeds listed by name in one notification.
+const MAX_LISTED: usize = 5;
+/// How long a notification stays up, and so how long its thread waits to see if it's clicked.
+const SHOWN_FOR: Duration = Duration::from_secs(30);
+
+/// A feed's title, and how many articles a refresh brought in.
+pub type NewArticles = (String, usize);
+
+/// Shows a desktop notification for the new articles sent to the returned channel. Clicking one
+/// brings the window forward once `window` is set, where the desktop supports it.
+pub fn spawn(window: Arc<OnceLock<Context>>) -> Sender<NewArticles> {
+	let (send, recv) = mpsc::channel(1024);
+	tokio::spawn(notify(recv, window));
+	send
+}
+
+async fn notify(mut recv: Receiver<NewArticles>, window: Arc<OnceLock<Context>>) {
+	let mut batch = vec![];
+	while recv.recv_many(&mut batch, 1024).await > 0 {
+		let deadline = Instant::now() + DEBOUNCE;
+		while let Ok(received) =
+			tokio::time::timeout_at(deadline, recv.recv_many(&mut batch, 1024)).await
+		{
+			if received == 0 {
+				break;
+			}
+		}
+		let (summary, body) = summarize(&batch);
+		batch.clear();
+		let window = window.clone();
+		// Showing it, and waiting to see if it's clicked, blocks until it's closed. That's on
+		// its own thread rather than the runtime's blocking pool, which shutdown waits for.
+		std::thread::spawn(move || show(&summary, &body, &window));
+	}
+}
+
+/// The notification's title and text for a batch of new articles.
+fn summarize(batch: &[NewArticles]) -> (String, String) {
+	let mut feeds: BTreeMap<&str, usize> = BTreeMap::new();
+	for (title, new) in batch {
+		*feeds.entry(title).or_default() += new;
+	}
+	let articles = |n: usize| if n == 1 { "article" } else { "articles" };
+	if let [(title, new)] = feeds.iter().collect::<Vec<_>>()[..] {
+		return (
+			format!("{title}: {new} new {}", articles(*new)),
+			String::new(),
+		);
+	}
+	let total: usize = feeds.values().sum();
+	let summary = format!("{total} new {} in {} feeds", articles(total), feeds.len());
+	let mut feeds: Vec<_> = feeds.into_iter().collect();
+	feeds.sort_by(|(a_title, a), (b_title, b)| b.cmp(a).then(a_title.cmp(b_title)));
+	let mut lines: Vec<_> = feeds
+		.iter()
+		.take(MAX_LISTED)
+		.map(|(title, new)| format!("{title}: {new}"))
+		.collect();
+	if feeds.len() > MAX_LISTED {
+		lines.push(format!("and {} more", feeds.len() - MAX_LISTED));
+	}
+	(summary, lines.join("\n"))
+}
+
+fn show(summary: &str, body: &str, window: &OnceLock<Context>) {
+	let shown = notify_rust::Notification::new()
+		.appname("Winter2")
+		.summary(summary)
+		.body(body)
+		.action("default", "Open Winter2")
+		.timeout(SHOWN_FOR)
+		.show();
+	match shown {
+		Err(e) => tracing::warn!(error = %e, "Showing a notification failed"),
+		#[cfg(all(unix, not(target_os = "macos")))]
+		Ok(handle) => handle.wait_for_action(|action| {
+			if let (Some(ctx), "default") = (window.get(), action) {
+				ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Focus);
+			}
+		}),
+		#[cfg(not(all(unix, not(target_os = "macos"))))]
+		Ok(_) => {
+			let _ = window;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::summarize;
+
+	#[test]
+	fn batches_are_summarized_per_feed() {
+		let batch = |feeds: &[(&str, usize)]| -> Vec<_> {
+			feeds.iter().map(|(t, n)| (t.to_string(), *n)).collect()
+		};
+		assert_eq!(
+			summarize(&batch(&[("Cast", 1)])),
+			("Cast: 1 new article".to_string(), String::new())
+		);
+		// The same feed refreshed twice is still one feed.
+		assert_eq!(
+			summarize(&batch(&[("Cast", 2), ("Cast", 1)])).0,
+			"Cast: 3 new articles"
+		);
+		let (summary, body) = summarize(&batch(&[
+			("A", 1),
+			("B", 5),
+			("C", 2),
+			("D", 1),
+			("E", 1),
+			("F", 1),
+			("G", 1),
+		]));
+		assert_eq!(summary, "12 new articles in 7 feeds");
+		assert_eq!(body, "B: 5\nC: 2\nA: 1\nD: 1\nE: 1\nand 2 more");
+	}
+}
@@ -0,0 +1,82 @@
+use super::{store::Store, Db, MkAppError};
+use std::{fmt::Write, path::Path};
+
+/// Loads the DB at `path` and renders its subscriptions with [`to_opml`].
+pub fn export_opml<S: Store>(path: &Path) -> Result<String, MkAppError> {
+	Ok(to_opml(&S::open(path, false)?.load()?.db))
+}
+
+/// Renders the subscription list as an OPML 2.0 document, which every feed reader can import.
+pub fn to_opml(db: &Db) -> String {
+	let mut feeds: Vec<_> = db.feeds.iter().collect();
+	feeds.sort_by(|(a_url, a), (b_url, b)| (a.title(), a_url).cmp(&(b.title(), b_url)));
+	let mut opml = String::from(
+		"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n\t<head>\n\t\t<title>Winter2 subscriptions</title>\n\t</head>\n\t<body>\n",
+	);
+	for (url, feed) in feeds {
+		let channel = &feed.feed;
+		let title = if feed.title().is_empty() {
+			url
+		} else {
+			feed.title()
+		};
+		let _ = write!(
+			opml,
+			"\t\t<outline type=\"rss\" text=\"{title}\" title=\"{title}\" xmlUrl=\"{url}\"",
+			title = escape(title),
+			url = escape(url),
+		);
+		if !channel.link.is_empty() {
+			let _ = write!(opml, " htmlUrl=\"{}\"", escape(&channel.link));
+		}
+		if !channel.description.is_empty() {
+			let _ = write!(opml, " description=\"{}\"", escape(&channel.description));
+		}
+		opml.push_str("/>\n");
+	}
+	opml.push_str("\t</body>\n</opml>\n");
+	opml
+}
+
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\n' => escaped.push_str("&#10;"),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+#[cfg(test)]
+mod tests {
+	use super::to_opml;
+	use crate::app::{Db, Feed};
+	use rss::Channel;
+
+	#[test]
+	fn exports_escaped_outlines() {
+		let mut db = Db::default();
+		db.feeds.insert(
+			"https://example.com/feed?a=1&b=2".to_string(),
+			Feed {
+				feed: Channel {
+					title: "Tom & \"Jerry\"".to_string(),
+					description: "<b>Cartoons</b>".to_string(),
+					..Default::default()
+				},
+				..Default::default()
+			},
+		);
+		let opml = to_opml(&db);
+		assert!(opml.contains(
+			"<outline type=\"rss\" text=\"Tom &amp; &quot;Jerry&quot;\" title=\"Tom &amp; &quot;Jerry&quot;\" xmlUrl=\"https://example.com/feed?a=1&amp;b=2\" description=\"&lt;b&gt;Cartoons&lt;/b&gt;\"/>"
+		));
+		assert!(opml.ends_with("</opml>\n"));
+	}
+}
@@ -0,0 +1,304 @@
+use super::{Db, Mutation};
+use crate::vlc::{Vlc, VlcError};
+use chrono::Utc;
+use egui_notify::ToastLevel;
+use std::{
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{
+	sync::mpsc::{Receiver, Sender},
+	task::JoinHandle,
+};
+
+/// How often the session asks VLC where it's at.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// An enclosure to play, and the article it came from.
+#[derive(Clone, PartialEq)]
+pub struct Episode {
+	pub feed_url: String,
+	/// The article's [`article_key`](super::article_key).
+	pub guid: String,
+	pub title: String,
+	pub url: String,
+}
+
+pub enum PlayerCommand {
+	/// Quit VLC and end the session.
+	Stop,
+	/// Pause, or resume if paused.
+	TogglePause,
+	/// Seek to a fraction of the way through.
+	Seek(f64),
+	/// Skip this many seconds forward, or back if negative.
+	Jump(f64),
+	/// Set the volume, where 1 is 100%.
+	SetVolume(f64),
+	/// Set the speed, where 1 is normal speed.
+	SetRate(f64),
+}
+
+/// Stopping within this many seconds of the end counts as finishing the episode.
+const END_SLACK: f64 = 5.0;
+
+/// How far a session got.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Outcome {
+	/// The last known position, as a fraction of the length.
+	pub progress: f64,
+	/// Whether it played through to the end, rather than being stopped early.
+	pub finished: bool,
+}
+
+/// What the session last heard from VLC, for the now-playing panel.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Status {
+	/// Seconds in.
+	pub time: f64,
+	/// Seconds long, or 0 if VLC doesn't know (yet, or for streams).
+	pub length: f64,
+	pub paused: bool,
+	pub outcome: Outcome,
+}
+
+/// A running VLC session. Dropping it closes VLC too.
+pub struct Playback {
+	pub episode: Episode,
+	commands: Sender<PlayerCommand>,
+	task: JoinHandle<()>,
+	status: Arc<Mutex<Status>>,
+}
+
+impl Playback {
+	/// Plays the episode at `volume` and `rate`, saving how far it got to the article's read state
+	/// once it's done.
+	pub fn start(
+		episode: Episode,
+		volume: f64,
+		rate: f64,
+		send_toast: Sender<(ToastLevel, String)>,
+		send_mutation: Sender<Mutation>,
+	) -> Self {
+		let (commands, recv_commands) = tokio::sync::mpsc::channel(16);
+		let Episode {
+			feed_url,
+			guid,
+			url,
+			..
+		} = episode.clone();
+		let status = Arc::new(Mutex::new(Status::default()));
+		let task = tokio::spawn({
+			let status = status.clone();
+			async move {
+				let result = run(&url, recv_commands, &status, volume, rate).await;
+				if let Err(e) = result {
+					let _ = send_toast
+						.send((ToastLevel::Error, format!("Playing {url} failed with {e}")))
+						.await;
+				}
+				let outcome = status.lock().unwrap().outcome;
+				if outcome.progress > 0.0 || outcome.finished {
+					let _ = send_mutation
+						.send(Box::new(move |db, _| {
+							record_progress(db, &feed_url, &guid, outcome);
+							Ok(())
+						}))
+						.await;
+				}
+			}
+		});
+		Self {
+			episode,
+			commands,
+			task,
+			status,
+		}
+	}
+
+	/// Sends a command to the session. It's dropped if the session is too busy to take it, which
+	/// only happens to rapid-fire ones like seeking while dragging.
+	pub fn send(&self, command: PlayerCommand) {
+		let _ = self.commands.try_send(command);
+	}
+
+	/// Asks VLC to quit gracefully, rather than killing it.
+	pub fn stop(&self) {
+		self.send(PlayerCommand::Stop);
+	}
+
+	/// What the session last heard from VLC.
+	pub fn status(&self) -> Status {
+		*self.status.lock().unwrap()
+	}
+
+	/// Whether the session is over, because VLC was closed or stopped.
+	pub fn is_finished(&self) -> bool {
+		self.task.is_finished()
+	}
+
+	/// Whether the session ended by playing through, so the next queued episode should start.
+	pub fn played_through(&self) -> bool {
+		self.is_finished() && self.status().outcome.finished
+	}
+}
+
+/// Saves a session's progress. An early stop never marks the article fully read, and never undoes
+/// an article already having been finished.
+pub fn record_progress(db: &mut Db, feed_url: &str, guid: &str, outcome: Outcome) {
+	let Some(feed) = db.feeds.get_mut(feed_url) else {
+		return;
+	};
+	let read = feed
+		.read_articles
+		.get(guid)
+		.map_or(0.0, |read| read.fraction);
+	if outcome.finished {
+		feed.set_read(guid.to_string(), 1.0, Utc::now());
+	} else if read < 1.0 {
+		feed.set_read(
+			guid.to_string(),
+			outcome.progress.clamp(0.0, 0.99),
+			Utc::now(),
+		);
+	}
+}
+
+/// Drives VLC until it's stopped, closed, or reaches the end, keeping `status` up to date as it
+/// goes so the outcome survives errors.
+async fn run(
+	url: &str,
+	mut commands: Receiver<PlayerCommand>,
+	status: &Mutex<Status>,
+	mut volume: f64,
+	mut rate: f64,
+) -> Result<(), VlcError> {
+	let mut vlc = Vlc::new(url).await?;
+	let mut poll = tokio::time::interval(POLL_INTERVAL);
+	let mut last = Status::default();
+	// VLC ignores the speed until the media has started, so it's held until then.
+	let mut started = false;
+	loop {
+		tokio::select! {
+			command = commands.recv() => match command {
+				// A closed channel means the `Playback` was dropped.
+				Some(PlayerCommand::Stop) | None => return vlc.shutdown().await,
+				Some(PlayerCommand::TogglePause) => {
+					vlc.pause().await?;
+					last.paused = !last.paused;
+				}
+				// Move the slider right away, since polling skips over pauses.
+				Some(PlayerCommand::Seek(fraction)) => {
+					vlc.seek_fraction(fraction).await?;
+					last.time = last.length * fraction.clamp(0.0, 1.0);
+				}
+				Some(PlayerCommand::Jump(delta)) => last.time = vlc.jump(delta).await?,
+				Some(PlayerCommand::SetVolume(new)) => {
+					volume = new;
+					if started {
+						vlc.set_volume(volume).await?;
+					}
+				}
+				Some(PlayerCommand::SetRate(new)) => {
+					rate = new;
+					if started {
+						vlc.set_rate(rate).await?;
+					}
+				}
+			},
+			_ = poll.tick() => {
+				let playing = match vlc.is_playing().await {
+					Ok(playing) => playing,
+					Err(VlcError::ProcessExited(_)) => return Ok(()),
+					Err(e) => return Err(e),
+				};
+				if playing {
+					if !started {
+						started = true;
+						vlc.set_volume(volume).await?;
+						vlc.set_rate(rate).await?;
+					}
+					last.paused = false;
+					last.time = vlc.play_time().await?;
+					last.length = vlc.video_length().await?;
+					if last.length > 0.0 {
+						last.outcome.progress = (last.time / last.length).clamp(0.0, 1.0);
+					}
+				} else if !last.paused && last.length > 0.0 && last.time >= last.length - END_SLACK {
+					// VLC idles at the end of its playlist, rewound to 0, rather than exiting.
+					last.outcome = Outcome {
+						progress: 1.0,
+						finished: true,
+					};
+					*status.lock().unwrap() = last;
+					return vlc.shutdown().await;
+				}
+			}
+		}
+		*status.lock().unwrap() = last;
+	}
+}
+
+/// Formats seconds like a media player does: `m:ss`, or `h:mm:ss` when it's that long.
+pub fn clock(seconds: f64) -> String {
+	let seconds = seconds.max(0.0) as u64;
+	let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+	if h > 0 {
+		format!("{h}:{m:02}:{s:02}")
+	} else {
+		format!("{m}:{s:02}")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{clock, record_progress, Outcome};
+	use crate::app::{Db, Feed};
+
+	#[test]
+	fn only_finishing_marks_read() {
+		let mut db = Db::default();
+		db.feeds.insert("feed".to_string(), Feed::default());
+		let read = |db: &Db, guid: &str| {
+			db.feeds["feed"]
+				.read_articles
+				.get(guid)
+				.map(|read| read.fraction)
+		};
+		let stopped = |progress| Outcome {
+			progress,
+			finished: false,
+		};
+
+		record_progress(&mut db, "feed", "a", stopped(0.5));
+		assert_eq!(read(&db, "a"), Some(0.5));
+		// Stopping a few seconds from the end still isn't finishing.
+		record_progress(&mut db, "feed", "a", stopped(1.0));
+		assert_eq!(read(&db, "a"), Some(0.99));
+		record_progress(
+			&mut db,
+			"feed",
+			"a",
+			Outcome {
+				progress: 1.0,
+				finished: true,
+			},
+		);
+		assert_eq!(read(&db, "a"), Some(1.0));
+		let finished = db.feeds["feed"].read_articles["a"].read_at;
+		assert!(finished.is_some());
+		// Replaying a finished episode and stopping early leaves it finished.
+		record_progress(&mut db, "feed", "a", stopped(0.1));
+		assert_eq!(read(&db, "a"), Some(1.0));
+		assert_eq!(db.feeds["feed"].read_articles["a"].read_at, finished);
+		record_progress(&mut db, "gone", "a", stopped(0.1));
+	}
+
+	#[test]
+	fn clock_formats_like_a_player() {
+		assert_eq!(clock(0.0), "0:00");
+		assert_eq!(clock(65.9), "1:05");
+		assert_eq!(clock(3600.0 + 2.0 * 60.0 + 3.0), "1:02:03");
+		assert_eq!(clock(-1.0), "0:00");
+	}
+}
@@ -0,0 +1,89 @@
+use eframe::egui::Visuals;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// User preferences, saved in the `Db` alongside the feeds. Fields missing from older databases
+/// take their defaults.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+	/// Whether feeds are refreshed every [`Settings::refresh_interval_mins`].
+	pub auto_refresh: bool,
+	pub refresh_interval_mins: u64,
+	/// How many feeds may be downloading at once.
+	pub max_concurrent_fetches: usize,
+	/// The speed episodes play at, where 1 is normal speed.
+	pub playback_rate: f64,
+	pub theme: Theme,
+	/// How long articles that have dropped out of their feed keep their read state, in case they
+	/// come back.
+	pub read_state_grace_days: u32,
+}
+
+/// Whether the GUI is dark or light.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub enum Theme {
+	/// Whatever the OS is set to, or dark if it won't say.
+	#[default]
+	System,
+	Dark,
+	Light,
+}
+
+impl Theme {
+	pub fn visuals(self, system: Option<eframe::Theme>) -> Visuals {
+		match (self, system) {
+			(Theme::Light, _) | (Theme::System, Some(eframe::Theme::Light)) => Visuals::light(),
+			_ => Visuals::dark(),
+		}
+	}
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		Self {
+			auto_refresh: true,
+			refresh_interval_mins: 30,
+			max_concurrent_fetches: 8,
+			playback_rate: 1.0,
+			theme: Theme::default(),
+			read_state_grace_days: 30,
+		}
+	}
+}
+
+impl Settings {
+	pub fn refresh_interval(&self) -> Duration {
+		Duration::from_secs(self.refresh_interval_mins.max(1) * 60)
+	}
+
+	pub fn read_state_grace(&self) -> chrono::Duration {
+		chrono::Duration::days(self.read_state_grace_days.into())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Settings, Theme};
+	use crate::app::Db;
+	use serde_json::json;
+
+	#[test]
+	fn missing_settings_take_defaults() {
+		let db: Db = serde_json::from_value(json!({"feeds": {}})).unwrap();
+		assert!(db.settings == Settings::default());
+		let settings: Settings = serde_json::from_value(json!({"auto_refresh": false})).unwrap();
+		assert!(!settings.auto_refresh);
+		assert_eq!(settings.refresh_interval_mins, 30);
+		assert_eq!(settings.playback_rate, 1.0);
+		assert_eq!(settings.theme, Theme::System);
+	}
+
+	#[test]
+	fn system_theme_falls_back_to_dark() {
+		assert!(Theme::System.visuals(None).dark_mode);
+		assert!(!Theme::System.visuals(Some(eframe::Theme::Light)).dark_mode);
+		assert!(Theme::Dark.visuals(Some(eframe::Theme::Light)).dark_mode);
+		assert!(!Theme::Light.visuals(Some(eframe::Theme::Dark)).dark_mode);
+	}
+}
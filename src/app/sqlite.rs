@@ -0,0 +1,381 @@
+use super::{
+	store::{lock_file, Loaded, Store},
+	Db, Feed, MkAppError, ReadState,
+};
+use crate::db_fmt::{LoadReport, WriteStats};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::{
+	fs::File,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+const SCHEMA: &str = "
+	CREATE TABLE IF NOT EXISTS feeds (
+		url TEXT PRIMARY KEY,
+		-- The rest of the feed as JSON, minus its articles and read state.
+		feed TEXT NOT NULL
+	);
+	CREATE TABLE IF NOT EXISTS articles (
+		feed_url TEXT NOT NULL REFERENCES feeds (url) ON DELETE CASCADE,
+		position INTEGER NOT NULL,
+		item TEXT NOT NULL,
+		PRIMARY KEY (feed_url, position)
+	);
+	CREATE TABLE IF NOT EXISTS read_articles (
+		feed_url TEXT NOT NULL REFERENCES feeds (url) ON DELETE CASCADE,
+		article TEXT NOT NULL,
+		fraction REAL NOT NULL,
+		read_at TEXT,
+		PRIMARY KEY (feed_url, article)
+	);
+	-- The queue and settings, as JSON.
+	CREATE TABLE IF NOT EXISTS meta (
+		key TEXT PRIMARY KEY,
+		value TEXT NOT NULL
+	);
+";
+
+/// One SQLite file, with a row per feed, article, and read article. Big DBs load and save much
+/// faster than as a directory tree, since a save only touches the rows that changed.
+pub struct SqliteStore {
+	path: PathBuf,
+	/// Shared with the blocking threads that `reload` and `save` run on.
+	connection: Arc<Mutex<Connection>>,
+	_lock: File,
+}
+
+impl SqliteStore {
+	/// SQLite's own locking only covers single writes, not a whole session.
+	fn lock_path(path: &Path) -> PathBuf {
+		let mut lock = path.as_os_str().to_owned();
+		lock.push(".lock");
+		lock.into()
+	}
+}
+
+impl Store for SqliteStore {
+	fn open(path: &Path, init: bool) -> Result<Self, MkAppError> {
+		if !init && !path.is_file() {
+			return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+		}
+		let lock = lock_file(&Self::lock_path(path))?;
+		let connection = Connection::open(path)?;
+		connection.pragma_update(None, "foreign_keys", true)?;
+		connection.execute_batch(SCHEMA)?;
+		add_read_at(&connection)?;
+		Ok(Self {
+			path: path.to_path_buf(),
+			connection: Arc::new(Mutex::new(connection)),
+			_lock: lock,
+		})
+	}
+
+	fn path(&self) -> &Path {
+		&self.path
+	}
+
+	fn load(&mut self) -> Result<Loaded, MkAppError> {
+		let db = read(&self.connection.lock().unwrap())?;
+		Ok(Loaded {
+			db,
+			report: LoadReport::default(),
+			recovered: 0,
+		})
+	}
+
+	fn replace(&mut self, db: &Db) -> Result<(), MkAppError> {
+		let mut connection = self.connection.lock().unwrap();
+		let tx = connection.transaction()?;
+		tx.execute_batch("DELETE FROM feeds; DELETE FROM meta;")?;
+		write_changes(&tx, &Db::default(), db)?;
+		tx.commit()?;
+		Ok(())
+	}
+
+	async fn reload(&mut self) -> eyre::Result<Db> {
+		let connection = self.connection.clone();
+		Ok(tokio::task::spawn_blocking(move || read(&connection.lock().unwrap())).await??)
+	}
+
+	/// Only writes the rows that changed, in one transaction, on a blocking thread so the
+	/// backend's other work carries on.
+	async fn save(&mut self, old: &Db, new: &Db) -> eyre::Result<WriteStats> {
+		let (connection, old, new) = (self.connection.clone(), old.clone(), new.clone());
+		let written = tokio::task::spawn_blocking(move || {
+			let mut connection = connection.lock().unwrap();
+			let tx = connection.transaction()?;
+			let stats = write_changes(&tx, &old, &new)?;
+			tx.commit()?;
+			Ok::<_, MkAppError>(stats)
+		});
+		Ok(written.await??)
+	}
+}
+
+/// Reads every table back into a `Db`.
+fn read(connection: &Connection) -> Result<Db, MkAppError> {
+	let mut db = Db::default();
+	let mut feeds = connection.prepare("SELECT url, feed FROM feeds")?;
+	let mut rows = feeds.query([])?;
+	while let Some(row) = rows.next()? {
+		let feed: Feed = serde_json::from_str(&row.get::<_, String>(1)?)?;
+		db.feeds.insert(row.get(0)?, feed);
+	}
+	let mut articles =
+		connection.prepare("SELECT feed_url, item FROM articles ORDER BY feed_url, position")?;
+	let mut rows = articles.query([])?;
+	while let Some(row) = rows.next()? {
+		if let Some(feed) = db.feeds.get_mut(&row.get::<_, String>(0)?) {
+			let item = serde_json::from_str(&row.get::<_, String>(1)?)?;
+			feed.feed.items.push(item);
+		}
+	}
+	let mut read =
+		connection.prepare("SELECT feed_url, article, fraction, read_at FROM read_articles")?;
+	let mut rows = read.query([])?;
+	while let Some(row) = rows.next()? {
+		if let Some(feed) = db.feeds.get_mut(&row.get::<_, String>(0)?) {
+			let read = ReadState {
+				fraction: row.get(2)?,
+				read_at: row.get(3)?,
+			};
+			feed.read_articles.insert(row.get(1)?, read);
+		}
+	}
+	let meta = |key: &str| -> Result<Option<String>, rusqlite::Error> {
+		connection
+			.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+				row.get(0)
+			})
+			.optional()
+	};
+	if let Some(queue) = meta("queue")? {
+		db.queue = serde_json::from_str(&queue)?;
+	}
+	if let Some(settings) = meta("settings")? {
+		db.settings = serde_json::from_str(&settings)?;
+	}
+	Ok(db)
+}
+
+/// Files made before reads were timestamped have no `read_at` column.
+fn add_read_at(connection: &Connection) -> Result<(), rusqlite::Error> {
+	let has_it: bool = connection.query_row(
+		"SELECT COUNT(*) > 0 FROM pragma_table_info('read_articles') WHERE name = 'read_at'",
+		[],
+		|row| row.get(0),
+	)?;
+	if !has_it {
+		connection.execute("ALTER TABLE read_articles ADD COLUMN read_at TEXT", [])?;
+	}
+	Ok(())
+}
+
+/// The feed without its articles or read state, which have tables of their own.
+fn header(feed: &Feed) -> Feed {
+	let mut header = feed.clone();
+	header.feed.items.clear();
+	header.read_articles.clear();
+	header
+}
+
+/// Writes the rows that differ between `old` and `new`, counting them as if they were files.
+fn write_changes(tx: &Transaction, old: &Db, new: &Db) -> Result<WriteStats, MkAppError> {
+	let mut stats = WriteStats::default();
+	let mut written = |bytes: usize| {
+		stats.files_written += 1;
+		stats.bytes += bytes as u64;
+	};
+	for url in old.feeds.keys().filter(|url| !new.feeds.contains_key(*url)) {
+		// Its articles and read state go with it.
+		tx.execute("DELETE FROM feeds WHERE url = ?1", [url])?;
+		stats.files_removed += 1;
+	}
+	for (url, feed) in &new.feeds {
+		let old = old.feeds.get(url);
+		if old == Some(feed) {
+			continue;
+		}
+		let json = serde_json::to_string(&header(feed))?;
+		tx.execute(
+			"INSERT INTO feeds (url, feed) VALUES (?1, ?2)
+			ON CONFLICT (url) DO UPDATE SET feed = excluded.feed",
+			params![url, json],
+		)?;
+		written(json.len());
+		if old.map(|old| &old.feed.items) != Some(&feed.feed.items) {
+			tx.execute("DELETE FROM articles WHERE feed_url = ?1", [url])?;
+			for (position, item) in feed.feed.items.iter().enumerate() {
+				let json = serde_json::to_string(item)?;
+				tx.execute(
+					"INSERT INTO articles (feed_url, position, item) VALUES (?1, ?2, ?3)",
+					params![url, position, json],
+				)?;
+				written(json.len());
+			}
+		}
+		let old_read = old.map(|old| &old.read_articles);
+		for (article, read) in &feed.read_articles {
+			if old_read.and_then(|old| old.get(article)) == Some(read) {
+				continue;
+			}
+			tx.execute(
+				"INSERT INTO read_articles (feed_url, article, fraction, read_at)
+				VALUES (?1, ?2, ?3, ?4)
+				ON CONFLICT (feed_url, article)
+				DO UPDATE SET fraction = excluded.fraction, read_at = excluded.read_at",
+				params![url, article, read.fraction, read.read_at],
+			)?;
+			written(article.len() + 8);
+		}
+		for article in old_read
+			.into_iter()
+			.flat_map(|read| read.keys())
+			.filter(|article| !feed.read_articles.contains_key(*article))
+		{
+			tx.execute(
+				"DELETE FROM read_articles WHERE feed_url = ?1 AND article = ?2",
+				params![url, article],
+			)?;
+			stats.files_removed += 1;
+		}
+	}
+	let meta = [
+		(
+			"queue",
+			old.queue != new.queue,
+			serde_json::to_string(&new.queue)?,
+		),
+		(
+			"settings",
+			old.settings != new.settings,
+			serde_json::to_string(&new.settings)?,
+		),
+	];
+	for (key, _, json) in meta.iter().filter(|(_, changed, _)| *changed) {
+		tx.execute(
+			"INSERT INTO meta (key, value) VALUES (?1, ?2)
+			ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+			params![key, json],
+		)?;
+		written(json.len());
+	}
+	Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::SqliteStore;
+	use crate::app::{store::Store, Db, Feed, ReadState};
+	use chrono::{TimeZone, Utc};
+	use rss::{Channel, Item};
+	use uuid::Uuid;
+
+	#[tokio::test]
+	async fn saves_only_what_changed_and_loads_it_back() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}.sqlite", Uuid::new_v4()));
+		let mut store = SqliteStore::open(&path, true).unwrap();
+		let mut db = Db::default();
+		for n in 0..3 {
+			db.feeds.insert(
+				format!("https://example.com/{n}"),
+				Feed {
+					feed: Channel {
+						title: format!("Feed {n}"),
+						items: (0..4)
+							.map(|i| Item {
+								title: Some(format!("Article {i}")),
+								..Default::default()
+							})
+							.collect(),
+						..Default::default()
+					},
+					read_articles: [(
+						"Article 0".to_string(),
+						ReadState {
+							fraction: 1.0,
+							read_at: None,
+						},
+					)]
+					.into(),
+					category: Some("News".to_string()),
+					..Default::default()
+				},
+			);
+		}
+		db.queue
+			.push(("https://example.com/0".to_string(), "Article 1".to_string()));
+		store.save(&Db::default(), &db).await.unwrap();
+		assert!(store.load().unwrap().db == db);
+
+		let mut next = db.clone();
+		next.feeds.remove("https://example.com/2");
+		let feed = next.feeds.get_mut("https://example.com/1").unwrap();
+		let finished = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+		feed.read_articles
+			.insert("Article 3".to_string(), ReadState::at(1.0, finished));
+		feed.read_articles.remove("Article 0");
+		let stats = store.save(&db, &next).await.unwrap();
+		// The feed's own row and one read article.
+		assert_eq!(stats.files_written, 2);
+		// The deleted feed, and the other read article.
+		assert_eq!(stats.files_removed, 2);
+		drop(store);
+
+		let mut reopened = SqliteStore::open(&path, false).unwrap();
+		assert!(reopened.load().unwrap().db == next);
+		let removed: i64 = reopened
+			.connection
+			.lock()
+			.unwrap()
+			.query_row(
+				"SELECT COUNT(*) FROM articles WHERE feed_url = 'https://example.com/2'",
+				[],
+				|row| row.get(0),
+			)
+			.unwrap();
+		assert_eq!(removed, 0);
+		drop(reopened);
+		std::fs::remove_file(&path).unwrap();
+		std::fs::remove_file(SqliteStore::lock_path(&path)).unwrap();
+	}
+
+	#[test]
+	fn files_from_before_read_times_get_the_column() {
+		let path = std::env::temp_dir().join(format!("winter2_test_{}.sqlite", Uuid::new_v4()));
+		let old = rusqlite::Connection::open(&path).unwrap();
+		old.execute_batch(
+			"CREATE TABLE feeds (url TEXT PRIMARY KEY, feed TEXT NOT NULL);
+			CREATE TABLE read_articles (
+				feed_url TEXT NOT NULL REFERENCES feeds (url) ON DELETE CASCADE,
+				article TEXT NOT NULL,
+				fraction REAL NOT NULL,
+				PRIMARY KEY (feed_url, article)
+			);",
+		)
+		.unwrap();
+		let feed = serde_json::to_string(&Feed::default()).unwrap();
+		old.execute("INSERT INTO feeds (url, feed) VALUES ('feed', ?1)", [feed])
+			.unwrap();
+		old.execute(
+			"INSERT INTO read_articles (feed_url, article, fraction) VALUES ('feed', 'a', 1.0)",
+			[],
+		)
+		.unwrap();
+		drop(old);
+
+		let mut store = SqliteStore::open(&path, false).unwrap();
+		let read = store.load().unwrap().db.feeds["feed"].read_articles["a"];
+		assert_eq!(
+			read,
+			ReadState {
+				fraction: 1.0,
+				read_at: None
+			}
+		);
+		drop(store);
+		std::fs::remove_file(&path).unwrap();
+		std::fs::remove_file(SqliteStore::lock_path(&path)).unwrap();
+	}
+}
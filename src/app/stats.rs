@@ -0,0 +1,190 @@
+use super::{article_key, completion, Db};
+use chrono::{DateTime, Utc};
+use eframe::egui::{vec2, Grid, ProgressBar, Rect, Sense, Ui};
+
+/// How many weeks the completion chart goes back.
+pub const WEEKS: usize = 12;
+
+/// How tall the completion chart is drawn, in points.
+const CHART_HEIGHT: f32 = 80.0;
+
+/// The user's reading habits, worked out from the DB whenever they're shown.
+#[derive(PartialEq, Debug, Default)]
+pub struct Stats {
+	pub read: usize,
+	/// Started but not finished.
+	pub in_progress: usize,
+	pub unread: usize,
+	/// Each feed's title, how many of its articles are read, and how many it has, most read first.
+	pub per_feed: Vec<(String, usize, usize)>,
+	/// How many articles were finished in each of the last [`WEEKS`] weeks, oldest first. Weeks
+	/// are counted back from `now`, so the last one is the past seven days.
+	pub per_week: [usize; WEEKS],
+}
+
+impl Stats {
+	/// Counts the articles still in their feeds, and every finish with a time on it, including
+	/// those of articles that have since dropped out.
+	pub fn of(db: &Db, now: DateTime<Utc>) -> Self {
+		let mut stats = Self::default();
+		for feed in db.feeds.values() {
+			let mut read = 0;
+			for article in &feed.feed.items {
+				match completion(feed, &article_key(article)) {
+					done if done >= 1.0 => read += 1,
+					started if started > 0.0 => stats.in_progress += 1,
+					_ => stats.unread += 1,
+				}
+			}
+			stats.read += read;
+			let total = feed.feed.items.len();
+			stats.per_feed.push((feed.title().to_string(), read, total));
+			let finished = feed.read_articles.values().filter_map(|read| read.read_at);
+			for read_at in finished {
+				let weeks_ago = (now - read_at).num_weeks();
+				if (0..WEEKS as i64).contains(&weeks_ago) {
+					stats.per_week[WEEKS - 1 - weeks_ago as usize] += 1;
+				}
+			}
+		}
+		stats
+			.per_feed
+			.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+		stats
+	}
+
+	pub fn show(&self, ui: &mut Ui) {
+		let total = self.read + self.in_progress + self.unread;
+		ui.label(format!(
+			"{} read, {} in progress, {} unread",
+			self.read, self.in_progress, self.unread
+		));
+		if total > 0 {
+			ui.add(ProgressBar::new(self.read as f32 / total as f32).show_percentage());
+		}
+		ui.separator();
+		ui.heading("Finished per week");
+		if self.per_week.iter().all(|&n| n == 0) {
+			ui.label("Nothing finished lately");
+		} else {
+			self.week_chart(ui);
+		}
+		ui.separator();
+		ui.heading("Read per feed");
+		Grid::new("stats_per_feed").num_columns(2).show(ui, |ui| {
+			for (title, read, total) in &self.per_feed {
+				ui.label(title);
+				let fraction = if *total == 0 {
+					0.0
+				} else {
+					*read as f32 / *total as f32
+				};
+				ui.add(
+					ProgressBar::new(fraction)
+						.desired_width(200.0)
+						.text(format!("{read}/{total}")),
+				);
+				ui.end_row();
+			}
+		});
+	}
+
+	/// A column per week, scaled to the busiest one, with each week's count on hover.
+	fn week_chart(&self, ui: &mut Ui) {
+		let most = self.per_week.iter().copied().max().unwrap_or(0).max(1);
+		let width = ui.available_width().min(WEEKS as f32 * 24.0);
+		let (rect, response) = ui.allocate_exact_size(vec2(width, CHART_HEIGHT), Sense::hover());
+		let column = rect.width() / WEEKS as f32;
+		let color = ui.visuals().selection.bg_fill;
+		let painter = ui.painter_at(rect);
+		for (n, &count) in self.per_week.iter().enumerate() {
+			let height = rect.height() * count as f32 / most as f32;
+			let left = rect.left() + column * n as f32;
+			let bar = Rect::from_min_max(
+				[left + 2.0, rect.bottom() - height].into(),
+				[left + column - 2.0, rect.bottom()].into(),
+			);
+			painter.rect_filled(bar, 2.0, color);
+		}
+		let hovered = response
+			.hover_pos()
+			.map(|pos| (((pos.x - rect.left()) / column) as usize).min(WEEKS - 1));
+		if let Some(n) = hovered {
+			let count = self.per_week[n];
+			response.on_hover_text(match WEEKS - 1 - n {
+				0 => format!("{count} this week"),
+				1 => format!("{count} last week"),
+				ago => format!("{count} {ago} weeks ago"),
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Stats, WEEKS};
+	use crate::app::{Db, Feed, ReadState};
+	use chrono::{Duration, TimeZone, Utc};
+	use rss::{Channel, Item};
+
+	#[test]
+	fn counts_reads_per_feed_and_week() {
+		let now = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+		let item = |guid: &str| Item {
+			guid: Some(rss::Guid {
+				value: guid.to_string(),
+				..Default::default()
+			}),
+			..Default::default()
+		};
+		let read = |fraction, days_ago: Option<i64>| ReadState {
+			fraction,
+			read_at: days_ago.map(|days| now - Duration::days(days)),
+		};
+		let mut db = Db::default();
+		db.feeds.insert(
+			"a".to_string(),
+			Feed {
+				feed: Channel {
+					title: "A".to_string(),
+					items: vec![item("1"), item("2"), item("3")],
+					..Default::default()
+				},
+				read_articles: [
+					("1", read(1.0, Some(1))),
+					("2", read(0.5, None)),
+					// Gone from the feed, but it was still read that week.
+					("gone", read(1.0, Some(3))),
+					("long ago", read(1.0, Some(7 * WEEKS as i64))),
+				]
+				.map(|(key, read)| (key.to_string(), read))
+				.into(),
+				..Default::default()
+			},
+		);
+		db.feeds.insert(
+			"b".to_string(),
+			Feed {
+				feed: Channel {
+					title: "B".to_string(),
+					items: vec![item("1"), item("2")],
+					..Default::default()
+				},
+				read_articles: [("1", read(1.0, Some(8))), ("2", read(1.0, None))]
+					.map(|(key, read)| (key.to_string(), read))
+					.into(),
+				..Default::default()
+			},
+		);
+		let stats = Stats::of(&db, now);
+		assert_eq!((stats.read, stats.in_progress, stats.unread), (3, 1, 1));
+		assert_eq!(
+			stats.per_feed,
+			[("B".to_string(), 2, 2), ("A".to_string(), 1, 3)]
+		);
+		let mut per_week = [0; WEEKS];
+		per_week[WEEKS - 1] = 2;
+		per_week[WEEKS - 2] = 1;
+		assert_eq!(stats.per_week, per_week);
+	}
+}
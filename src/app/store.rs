@@ -0,0 +1,114 @@
+use super::{
+	journal,
+	migrations::{load_db, save_db, save_db_async},
+	Db, MkAppError,
+};
+use crate::db_fmt::{fs_to_value_async, LoadReport, WriteStats};
+use std::{
+	fs::{File, TryLockError},
+	future::Future,
+	path::{Path, PathBuf},
+};
+
+/// Where the DB is kept between launches. The backend saves through exactly one of these, and
+/// holds it (and so its lock) for as long as it runs.
+pub trait Store: Send + Sized + 'static {
+	/// Opens the DB at `path`, locking it so a second instance can't clobber it. If `init`, an
+	/// empty DB is created there first.
+	fn open(path: &Path, init: bool) -> Result<Self, MkAppError>;
+
+	/// What to watch for other programs changing the DB.
+	fn path(&self) -> &Path;
+
+	/// Loads the whole DB, finishing any save a crash cut short.
+	fn load(&mut self) -> Result<Loaded, MkAppError>;
+
+	/// Replaces whatever is stored with `db`.
+	fn replace(&mut self, db: &Db) -> Result<(), MkAppError>;
+
+	/// Loads the DB again after another program changed it.
+	fn reload(&mut self) -> impl Future<Output = eyre::Result<Db>> + Send;
+
+	/// Saves `new`, given that `old` is what was saved last, so only what changed has to be
+	/// written.
+	fn save(&mut self, old: &Db, new: &Db)
+		-> impl Future<Output = eyre::Result<WriteStats>> + Send;
+}
+
+pub struct Loaded {
+	pub db: Db,
+	/// What had to be dropped or repaired to load it.
+	pub report: LoadReport,
+	/// How many batches of changes a crash had cut short, which have now been saved.
+	pub recovered: usize,
+}
+
+/// Takes the lock file at `path`, which stays locked for as long as the returned `File` is open.
+pub fn lock_file(path: &Path) -> Result<File, MkAppError> {
+	let lock = File::create(path)?;
+	match lock.try_lock() {
+		Ok(()) => Ok(lock),
+		Err(TryLockError::WouldBlock) => Err(MkAppError::Locked),
+		Err(TryLockError::Error(e)) => Err(e.into()),
+	}
+}
+
+/// The directory tree written by [`crate::db_fmt`], with every field of the DB in its own file.
+pub struct FsStore {
+	path: PathBuf,
+	_lock: File,
+}
+
+impl Store for FsStore {
+	fn open(path: &Path, init: bool) -> Result<Self, MkAppError> {
+		if init {
+			std::fs::create_dir_all(path)?;
+		}
+		let store = Self {
+			path: path.to_path_buf(),
+			_lock: lock_file(&path.join(".lock"))?,
+		};
+		if init {
+			save_db(path, &Db::default())?;
+		}
+		Ok(store)
+	}
+
+	fn path(&self) -> &Path {
+		&self.path
+	}
+
+	fn load(&mut self) -> Result<Loaded, MkAppError> {
+		load_db(&self.path)
+	}
+
+	fn replace(&mut self, db: &Db) -> Result<(), MkAppError> {
+		save_db(&self.path, db)?;
+		Ok(())
+	}
+
+	async fn reload(&mut self) -> eyre::Result<Db> {
+		Ok(fs_to_value_async(&self.path).await?)
+	}
+
+	/// Rewriting the tree isn't atomic, so the change is journaled first; if the write is cut short
+	/// the next launch can finish it.
+	async fn save(&mut self, old: &Db, new: &Db) -> eyre::Result<WriteStats> {
+		let old = serde_json::to_value(old)?;
+		let new_value = serde_json::to_value(new)?;
+		let patch = journal::merge_patch(&old, &new_value);
+		if let Some(patch) = &patch {
+			journal::append(&self.path, patch).await?;
+		}
+		match save_db_async(&self.path, new).await {
+			Ok(stats) => Ok(stats),
+			Err(e) => {
+				// Journal the way back too, so recovering doesn't bring back what wasn't kept.
+				if let Some(undo) = patch.and_then(|_| journal::merge_patch(&new_value, &old)) {
+					let _ = journal::append(&self.path, &undo).await;
+				}
+				Err(e.into())
+			}
+		}
+	}
+}
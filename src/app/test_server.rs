@@ -0,0 +1,47 @@
+use std::net::SocketAddr;
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::TcpListener,
+	sync::mpsc::{self, UnboundedReceiver},
+};
+
+/// Answers every request with what `respond` makes of it, one connection at a time, until the
+/// test ends. Returns the server's address, and each request as it comes in.
+pub async fn serve(
+	respond: impl Fn(&str) -> String + Send + 'static,
+) -> (SocketAddr, UnboundedReceiver<String>) {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap();
+	let (send, requests) = mpsc::unbounded_channel();
+	tokio::spawn(async move {
+		loop {
+			let (mut socket, _) = listener.accept().await.unwrap();
+			let mut request = vec![0; 4096];
+			let len = socket.read(&mut request).await.unwrap();
+			let request = String::from_utf8_lossy(&request[..len]).into_owned();
+			let response = respond(&request);
+			// Before answering, so it's there by the time the client has its response.
+			let _ = send.send(request);
+			socket.write_all(response.as_bytes()).await.unwrap();
+		}
+	});
+	(addr, requests)
+}
+
+/// A 200 response carrying `body`.
+pub fn ok(body: &str) -> String {
+	format!(
+		"HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+		body.len()
+	)
+}
+
+/// A response with just a status line, like "404 Not Found".
+pub fn empty(status: &str) -> String {
+	format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+}
+
+/// An RSS feed with only a title.
+pub fn feed(title: &str) -> String {
+	format!("<rss version=\"2.0\"><channel><title>{title}</title></channel></rss>")
+}
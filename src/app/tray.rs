@@ -0,0 +1,240 @@
+use eframe::egui::Context;
+#[cfg(target_os = "linux")]
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	Mutex,
+};
+use std::sync::{
+	mpsc::{self, Receiver, Sender},
+	Arc,
+};
+use tray_icon::{
+	menu::{Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem},
+	Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent,
+};
+
+/// What was picked from the tray icon's menu.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrayCommand {
+	Refresh,
+	PlayPause,
+	ToggleWindow,
+	Quit,
+}
+
+/// How big the icon is drawn, in pixels.
+const ICON_SIZE: u32 = 32;
+
+/// The tray icon, which keeps the app running with its window closed where [`Tray::can_hide`].
+pub struct Tray {
+	commands: Receiver<TrayCommand>,
+	send: Sender<TrayCommand>,
+	icon: Option<Shown>,
+	/// The unread count last shown on the icon.
+	unread: Option<usize>,
+	/// Whether the window's been sent to the tray.
+	pub hidden: bool,
+	/// Whether Quit was picked, so closing the window should really close it.
+	pub quitting: bool,
+}
+
+/// On Linux, the icon needs GTK, so it lives on a thread running GTK's loop and gets the unread
+/// count from there. Elsewhere it has to be made on the main thread, once the window's open.
+#[cfg(target_os = "linux")]
+struct Shown {
+	unread: Arc<Mutex<Option<usize>>>,
+	up: Arc<AtomicBool>,
+}
+
+/// `None` if it couldn't be made.
+#[cfg(not(target_os = "linux"))]
+struct Shown(Option<TrayIcon>);
+
+impl Default for Tray {
+	fn default() -> Self {
+		let (send, commands) = mpsc::channel();
+		Self {
+			commands,
+			send,
+			icon: None,
+			unread: None,
+			hidden: false,
+			quitting: false,
+		}
+	}
+}
+
+impl Tray {
+	/// Puts the icon in the tray the first time it's called, which has to be from the main
+	/// thread, and shows `unread` on it. Returns what was picked from its menu since.
+	pub fn update(&mut self, window: &Context, unread: usize) -> Vec<TrayCommand> {
+		let icon = self
+			.icon
+			.get_or_insert_with(|| Shown::spawn(self.send.clone(), window.clone()));
+		if self.unread != Some(unread) {
+			self.unread = Some(unread);
+			icon.show_unread(unread);
+		}
+		self.commands.try_iter().collect()
+	}
+
+	/// Whether the icon made it into the tray, so there's a way to get the window back.
+	pub fn is_up(&self) -> bool {
+		self.icon.as_ref().is_some_and(Shown::is_up)
+	}
+
+	/// Whether the window can be hidden and still be woken to handle the menu. On X11 a hidden
+	/// window still gets the repaints the icon asks for. Wayland can't hide a window, and eframe
+	/// skips repainting minimized ones, so anywhere else closing the window quits as usual.
+	pub fn can_hide() -> bool {
+		cfg!(target_os = "linux") && std::env::var_os("WAYLAND_DISPLAY").is_none()
+	}
+}
+
+#[cfg(target_os = "linux")]
+impl Shown {
+	fn spawn(send: Sender<TrayCommand>, window: Context) -> Self {
+		let unread = Arc::new(Mutex::new(None));
+		let up = Arc::new(AtomicBool::new(false));
+		let shown = Self {
+			unread: unread.clone(),
+			up: up.clone(),
+		};
+		std::thread::spawn(move || {
+			if let Err(e) = gtk::init() {
+				tracing::warn!("Couldn't put an icon in the tray: {e}");
+				return;
+			}
+			let icon = match build(send, window) {
+				Ok(icon) => icon,
+				Err(e) => {
+					tracing::warn!("Couldn't put an icon in the tray: {e}");
+					return;
+				}
+			};
+			up.store(true, Ordering::Relaxed);
+			gtk::glib::timeout_add_local(std::time::Duration::from_millis(500), move || {
+				if let Some(unread) = unread.lock().unwrap().take() {
+					show_unread(&icon, unread);
+				}
+				gtk::glib::ControlFlow::Continue
+			});
+			gtk::main();
+		});
+		shown
+	}
+
+	fn show_unread(&self, unread: usize) {
+		*self.unread.lock().unwrap() = Some(unread);
+	}
+
+	fn is_up(&self) -> bool {
+		self.up.load(Ordering::Relaxed)
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Shown {
+	fn spawn(send: Sender<TrayCommand>, window: Context) -> Self {
+		let icon = build(send, window)
+			.map_err(|e| tracing::warn!("Couldn't put an icon in the tray: {e}"))
+			.ok();
+		Self(icon)
+	}
+
+	fn show_unread(&self, unread: usize) {
+		if let Some(icon) = &self.0 {
+			show_unread(icon, unread);
+		}
+	}
+
+	fn is_up(&self) -> bool {
+		self.0.is_some()
+	}
+}
+
+/// Makes the icon and its menu, sending what's picked from it to `send` and waking `window` to
+/// handle it.
+fn build(send: Sender<TrayCommand>, window: Context) -> eyre::Result<TrayIcon> {
+	let refresh = MenuItem::new("Refresh", true, None);
+	let play_pause = MenuItem::new("Play/Pause", true, None);
+	let toggle_window = MenuItem::new("Show/Hide window", true, None);
+	let quit = MenuItem::new("Quit", true, None);
+	let menu = Menu::new();
+	menu.append_items(&[
+		&refresh,
+		&play_pause,
+		&toggle_window,
+		&PredefinedMenuItem::separator(),
+		&quit,
+	])?;
+	let items: Vec<(MenuId, TrayCommand)> = vec![
+		(refresh.id().clone(), TrayCommand::Refresh),
+		(play_pause.id().clone(), TrayCommand::PlayPause),
+		(toggle_window.id().clone(), TrayCommand::ToggleWindow),
+		(quit.id().clone(), TrayCommand::Quit),
+	];
+	let send = Arc::new(move |command| {
+		if send.send(command).is_ok() {
+			window.request_repaint();
+		}
+	});
+	let on_menu = send.clone();
+	MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+		if let Some((_, command)) = items.iter().find(|(id, _)| *id == event.id) {
+			on_menu(*command);
+		}
+	}));
+	// Clicking the icon itself does nothing on Linux, where there's only the menu.
+	TrayIconEvent::set_event_handler(Some(move |event| {
+		if let TrayIconEvent::Click {
+			button: MouseButton::Left,
+			button_state: MouseButtonState::Up,
+			..
+		} = event
+		{
+			send(TrayCommand::ToggleWindow);
+		}
+	}));
+	Ok(TrayIconBuilder::new()
+		.with_menu(Box::new(menu))
+		.with_icon(icon_image(false))
+		.with_tooltip("Winter2")
+		.build()?)
+}
+
+fn show_unread(icon: &TrayIcon, unread: usize) {
+	let text = match unread {
+		0 => "Winter2".to_string(),
+		n => format!("Winter2: {n} unread"),
+	};
+	if let Err(e) = icon.set_tooltip(Some(&text)) {
+		tracing::warn!("Couldn't update the tray icon: {e}");
+	}
+	// Shown beside the icon, where the tray supports it.
+	icon.set_title((unread > 0).then(|| unread.to_string()));
+	if let Err(e) = icon.set_icon(Some(icon_image(unread > 0))) {
+		tracing::warn!("Couldn't update the tray icon: {e}");
+	}
+}
+
+/// A pale blue disc, with a red dot in the corner if there's anything unread.
+fn icon_image(badge: bool) -> Icon {
+	let size = ICON_SIZE as f32;
+	let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+	for y in 0..ICON_SIZE {
+		for x in 0..ICON_SIZE {
+			let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+			let within = |cx: f32, cy: f32, r: f32| (x - cx).powi(2) + (y - cy).powi(2) <= r * r;
+			let pixel = if badge && within(size * 0.78, size * 0.22, size * 0.2) {
+				[0xe0, 0x30, 0x30, 0xff]
+			} else if within(size / 2.0, size / 2.0, size / 2.0 - 1.0) {
+				[0x9c, 0xd3, 0xf0, 0xff]
+			} else {
+				[0, 0, 0, 0]
+			};
+			rgba.extend_from_slice(&pixel);
+		}
+	}
+	Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE).expect("The icon is the size it says")
+}
@@ -0,0 +1,80 @@
+use super::{fetch::FeedAuth, fetch_new_feed, Feed, FetchClient};
+use futures::{stream, StreamExt};
+use std::{collections::HashSet, fmt};
+
+/// The feed URLs in a plain list, one per line, like Newsboat's `urls` file. Blank lines and `#`
+/// comments are skipped, as is anything after the URL (Newsboat's tags), and each URL is only
+/// listed once.
+pub fn parse_url_list(text: &str) -> Vec<String> {
+	let mut seen = HashSet::new();
+	text.lines()
+		.filter_map(|line| line.split_whitespace().next())
+		// Quoted entries are Newsboat's query and filter feeds, which aren't URLs.
+		.filter(|url| !url.starts_with('#') && !url.starts_with('"'))
+		.filter(|url| seen.insert(url.to_string()))
+		.map(str::to_string)
+		.collect()
+}
+
+/// How importing one URL from a list went.
+pub enum UrlImport {
+	/// It was added, with this title.
+	Added(String),
+	AlreadySubscribed,
+	Failed(eyre::Report),
+}
+
+impl fmt::Display for UrlImport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Added(title) => write!(f, "added as {title}"),
+			Self::AlreadySubscribed => write!(f, "already subscribed"),
+			Self::Failed(e) => write!(f, "failed: {e}"),
+		}
+	}
+}
+
+/// Downloads each of `urls` that `subscribed` doesn't already have, `limit` at a time. Results
+/// are in the list's order; the feeds that downloaded come with them, ready to add.
+pub async fn fetch_url_list(
+	client: &FetchClient,
+	urls: Vec<String>,
+	subscribed: impl Fn(&str) -> bool,
+	limit: usize,
+) -> Vec<(String, UrlImport, Option<Feed>)> {
+	let fetches = urls.into_iter().map(|url| {
+		let known = subscribed(&url);
+		async move {
+			if known {
+				return (url, UrlImport::AlreadySubscribed, None);
+			}
+			match fetch_new_feed(client, &url, FeedAuth::None).await {
+				Ok(feed) => (url, UrlImport::Added(feed.title().to_string()), Some(feed)),
+				Err(e) => (url, UrlImport::Failed(e), None),
+			}
+		}
+	});
+	stream::iter(fetches).buffered(limit.max(1)).collect().await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::parse_url_list;
+
+	#[test]
+	fn lists_skip_comments_tags_and_repeats() {
+		let list = "
+			# My feeds
+			https://a.example.com/feed
+			https://b.example.com/rss \"~B's blog\" tech
+
+			\"query:Unread:unread = \\\"yes\\\"\"
+			https://a.example.com/feed
+		";
+		assert_eq!(
+			parse_url_list(list),
+			["https://a.example.com/feed", "https://b.example.com/rss"]
+		);
+		assert!(parse_url_list("").is_empty());
+	}
+}
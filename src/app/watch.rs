@@ -0,0 +1,44 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use tokio::sync::mpsc::Receiver;
+
+/// Watches the DB directory for changes, whoever makes them; the backend's own saves show up
+/// too. Only that something changed is reported, since what changed is found by reloading.
+pub struct DbWatcher {
+	/// Stops watching when dropped.
+	_watcher: RecommendedWatcher,
+	pub changes: Receiver<()>,
+}
+
+impl DbWatcher {
+	pub fn new(path: &Path) -> notify::Result<Self> {
+		// A change that's waiting to be noticed covers any that come after it, so one slot will do.
+		let (send, changes) = tokio::sync::mpsc::channel(1);
+		let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+			let Ok(event) = event else {
+				return;
+			};
+			if matches!(
+				event.kind,
+				EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+			) {
+				let _ = send.try_send(());
+			}
+		})?;
+		watcher.watch(path, RecursiveMode::Recursive)?;
+		Ok(Self {
+			_watcher: watcher,
+			changes,
+		})
+	}
+
+	/// Waits for the next change. Without a watcher, that's forever.
+	pub async fn changed(watcher: &mut Option<Self>) {
+		if let Some(watcher) = watcher {
+			if watcher.changes.recv().await.is_some() {
+				return;
+			}
+		}
+		std::future::pending().await
+	}
+}
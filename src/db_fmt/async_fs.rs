@@ -0,0 +1,48 @@
+use super::{
+	checked_value, storage_to_value_report, write_checked, FsStorage, FsToValueError, LoadReport,
+	ReadOptions, ValueToFsError, WriteOptions, WriteStats,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{io, path::Path};
+
+/// Like [`super::value_to_fs`], but runs the walk on a blocking thread so callers can await a save
+/// instead of stalling the runtime on it. The value is serialized before the thread starts, so
+/// `value` needn't outlive the call; once started, the write runs to completion even if the
+/// future is dropped.
+pub async fn value_to_fs_async<S: Serialize>(
+	path: &Path,
+	value: &S,
+) -> Result<WriteStats, ValueToFsError> {
+	value_to_fs_async_with(path, value, &WriteOptions::default()).await
+}
+
+pub async fn value_to_fs_async_with<S: Serialize>(
+	path: &Path,
+	value: &S,
+	options: &WriteOptions,
+) -> Result<WriteStats, ValueToFsError> {
+	let value = checked_value(path, value)?;
+	let (path, options) = (path.to_path_buf(), options.clone());
+	tokio::task::spawn_blocking(move || write_checked(&FsStorage, &path, &value, &options))
+		.await
+		.map_err(io::Error::other)?
+}
+
+pub async fn fs_to_value_async<D: DeserializeOwned + Send + 'static>(
+	path: &Path,
+) -> Result<D, FsToValueError> {
+	fs_to_value_async_with(path, &ReadOptions::default())
+		.await
+		.map(|(value, _)| value)
+}
+
+/// Like [`super::fs_to_value_report`].
+pub async fn fs_to_value_async_with<D: DeserializeOwned + Send + 'static>(
+	path: &Path,
+	options: &ReadOptions,
+) -> Result<(D, LoadReport), FsToValueError> {
+	let (path, options) = (path.to_path_buf(), options.clone());
+	tokio::task::spawn_blocking(move || storage_to_value_report(&FsStorage, &path, &options))
+		.await
+		.map_err(io::Error::other)?
+}
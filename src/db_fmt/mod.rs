@@ -0,0 +1,1856 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	io::{Read, Write},
+	path::{Path, PathBuf},
+	string::FromUtf8Error,
+	sync::Mutex,
+	time::Instant,
+};
+use thiserror::Error;
+
+mod async_fs;
+mod storage;
+pub use async_fs::{
+	fs_to_value_async, fs_to_value_async_with, value_to_fs_async, value_to_fs_async_with,
+};
+use storage::{is_temp_name, CountingStorage, DryRunStorage};
+pub use storage::{EntryKind, FsStorage, MemStorage, Storage};
+
+/// Most filesystems cap a single file name at 255 bytes. Object keys whose encoded name would be
+/// longer are stored under a hash instead, with the real key recorded in a `.keymap` file.
+const MAX_NAME_LEN: usize = 255;
+
+const BYTES_MARKER: &str = "$winter2_bytes";
+
+/// A byte buffer that `value_to_fs` stores as a raw binary file (a directory with a `.type` of
+/// `bytes`) rather than as a JSON array of numbers, which is ~4x larger and slow to parse.
+///
+/// JSON can't tell bytes from numbers, so it serializes as a single-key marker object holding the
+/// base64 payload.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Bytes(pub Vec<u8>);
+
+#[derive(Serialize, Deserialize)]
+struct BytesRepr {
+	#[serde(rename = "$winter2_bytes")]
+	data: String,
+}
+
+impl Bytes {
+	fn is_marker(object: &serde_json::Map<String, Value>) -> bool {
+		object.len() == 1 && matches!(object.get(BYTES_MARKER), Some(Value::String(_)))
+	}
+}
+
+impl Serialize for Bytes {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		BytesRepr {
+			data: STANDARD.encode(&self.0),
+		}
+		.serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let repr = BytesRepr::deserialize(deserializer)?;
+		STANDARD
+			.decode(repr.data)
+			.map(Bytes)
+			.map_err(serde::de::Error::custom)
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum ValueToFsError {
+	#[error("IO")]
+	IO(#[from] std::io::Error),
+	#[error("JSON")]
+	JSON(#[from] serde_json::Error),
+	#[error("Bad byte payload")]
+	BadBytes(String),
+	#[error("Refusing to delete {} since it resolves outside the database", .0.display())]
+	PathEscape(PathBuf),
+	#[error("Nested deeper than {0} levels")]
+	TooDeep(usize),
+	#[error("NaN or infinite float at {0}, which JSON can't store")]
+	NonFiniteFloat(String),
+	#[error("{source}, failed at {}", path.display())]
+	At {
+		path: PathBuf,
+		source: Box<ValueToFsError>,
+	},
+}
+
+impl ValueToFsError {
+	/// Attaches the path being written, keeping the innermost one if there already is one.
+	fn at(self, path: &Path) -> Self {
+		match self {
+			at @ Self::At { .. } => at,
+			other => Self::At {
+				path: path.to_path_buf(),
+				source: Box::new(other),
+			},
+		}
+	}
+}
+
+/// Whether writes are `fsync`ed before returning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DurabilityMode {
+	/// Sync every written file and the directories whose entries changed, so the data survives
+	/// power loss once `value_to_fs` returns.
+	#[default]
+	Durable,
+	/// Leave flushing to the OS. Much faster, since this format produces many tiny files.
+	Fast,
+}
+
+/// Whether leaf files are compressed. Reads detect compressed leaves by their extension, so
+/// databases written in either mode (or a mix) always load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionMode {
+	#[default]
+	None,
+	/// Gzip leaves into `<name>.json.gz`. Leaves shorter than [`GZIP_MIN_LEN`] stay plain, since
+	/// the gzip header alone would make them bigger.
+	Gzip,
+}
+
+pub const GZIP_MIN_LEN: usize = 128;
+const GZIP_SUFFIX: &str = ".json.gz";
+
+/// Whether identical leaves share one copy on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlobMode {
+	/// Every leaf holds its own JSON, so the tree stays readable with `cat`.
+	#[default]
+	Inline,
+	/// Store each distinct leaf once, as plain JSON in `.blobs/<sha256>` at the root, and write a
+	/// `<name>.blob` file holding the hash in its place. Leaves shorter than [`BLOB_MIN_LEN`] stay
+	/// inline, since the reference would be bigger than they are.
+	Shared,
+}
+
+pub const BLOB_MIN_LEN: usize = 64;
+const BLOB_SUFFIX: &str = ".blob";
+const BLOBS_DIR: &str = ".blobs";
+
+/// How deep a tree may nest before reads and writes give up, so a pathological or looping
+/// directory can't blow the stack.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+	pub durability: DurabilityMode,
+	pub compression: CompressionMode,
+	pub blobs: BlobMode,
+	pub max_depth: usize,
+}
+
+impl Default for WriteOptions {
+	fn default() -> Self {
+		Self {
+			durability: DurabilityMode::default(),
+			compression: CompressionMode::default(),
+			blobs: BlobMode::default(),
+			max_depth: DEFAULT_MAX_DEPTH,
+		}
+	}
+}
+
+/// What a write actually changed on disk. Unchanged files aren't rewritten, so a save that only
+/// touched one feed should only count a handful of files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WriteStats {
+	pub files_written: usize,
+	/// Files or whole directories deleted because they no longer hold part of the value.
+	pub files_removed: usize,
+	pub dirs_created: usize,
+	/// Bytes written, after compression.
+	pub bytes: u64,
+}
+
+impl WriteStats {
+	fn log(&self, path: &Path, started: Instant) {
+		tracing::debug!(
+			path = %path.display(),
+			files_written = self.files_written,
+			files_removed = self.files_removed,
+			dirs_created = self.dirs_created,
+			bytes = self.bytes,
+			took = ?started.elapsed(),
+			"Wrote tree"
+		);
+	}
+}
+
+pub fn value_to_fs<S: Serialize>(path: &Path, value: &S) -> Result<WriteStats, ValueToFsError> {
+	value_to_fs_with(path, value, &WriteOptions::default())
+}
+
+pub fn value_to_fs_with<S: Serialize>(
+	path: &Path,
+	value: &S,
+	options: &WriteOptions,
+) -> Result<WriteStats, ValueToFsError> {
+	value_to_storage(&FsStorage, path, value, options)
+}
+
+/// Like [`value_to_fs_with`], but writes into any [`Storage`].
+pub fn value_to_storage<S: Serialize>(
+	storage: &impl Storage,
+	path: &Path,
+	value: &S,
+	options: &WriteOptions,
+) -> Result<WriteStats, ValueToFsError> {
+	let value = checked_value(path, value)?;
+	write_checked(storage, path, &value, options)
+}
+
+/// Writes a value that's already been through [`checked_value`], tallying what changed.
+fn write_checked(
+	storage: &impl Storage,
+	path: &Path,
+	value: &Value,
+	options: &WriteOptions,
+) -> Result<WriteStats, ValueToFsError> {
+	let started = Instant::now();
+	let storage = CountingStorage {
+		inner: storage,
+		stats: Mutex::default(),
+	};
+	write_tree(&storage, path, value, options)?;
+	let stats = storage.stats.into_inner().unwrap();
+	stats.log(path, started);
+	Ok(stats)
+}
+
+struct WriteContext<'a> {
+	options: &'a WriteOptions,
+	root: &'a Path,
+	/// The blobs referenced by what's been written so far.
+	blobs: Mutex<BTreeSet<String>>,
+}
+
+impl<'a> WriteContext<'a> {
+	fn new(root: &'a Path, options: &'a WriteOptions) -> Self {
+		Self {
+			options,
+			root,
+			blobs: Mutex::default(),
+		}
+	}
+}
+
+fn write_tree(
+	storage: &impl Storage,
+	path: &Path,
+	value: &Value,
+	options: &WriteOptions,
+) -> Result<(), ValueToFsError> {
+	let context = WriteContext::new(path, options);
+	value_to_fs_inner(storage, path, value, &context, 0)?;
+	prune_blobs(storage, &context).map_err(|e| ValueToFsError::from(e).at(path))
+}
+
+/// A file that a write would change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsChange {
+	Added(PathBuf),
+	Modified(PathBuf),
+	/// A file or a whole directory.
+	Removed(PathBuf),
+}
+
+impl FsChange {
+	pub fn path(&self) -> &Path {
+		match self {
+			Self::Added(path) | Self::Modified(path) | Self::Removed(path) => path,
+		}
+	}
+}
+
+/// Lists what [`value_to_fs`] would change, without touching the disk.
+pub fn fs_diff<S: Serialize>(path: &Path, value: &S) -> Result<Vec<FsChange>, ValueToFsError> {
+	fs_diff_with(path, value, &WriteOptions::default())
+}
+
+pub fn fs_diff_with<S: Serialize>(
+	path: &Path,
+	value: &S,
+	options: &WriteOptions,
+) -> Result<Vec<FsChange>, ValueToFsError> {
+	let value = checked_value(path, value)?;
+	let storage = DryRunStorage::new(&FsStorage);
+	write_tree(&storage, path, &value, options)?;
+	Ok(storage.changes()?)
+}
+
+fn checked_value<S: Serialize>(path: &Path, value: &S) -> Result<Value, ValueToFsError> {
+	if let Err(FiniteCheckError::NonFinite(field)) = value.serialize(&mut FiniteCheck::default()) {
+		return Err(ValueToFsError::NonFiniteFloat(field).at(path));
+	}
+	serde_json::to_value(value).map_err(|e| ValueToFsError::from(e).at(path))
+}
+
+fn value_to_fs_inner(
+	storage: &impl Storage,
+	path: &Path,
+	value: &Value,
+	context: &WriteContext,
+	depth: usize,
+) -> Result<(), ValueToFsError> {
+	write_node(storage, path, value, context, depth).map_err(|e| e.at(path))
+}
+
+fn write_node(
+	storage: &impl Storage,
+	path: &Path,
+	value: &Value,
+	context: &WriteContext,
+	depth: usize,
+) -> Result<(), ValueToFsError> {
+	let options = context.options;
+	if depth > options.max_depth {
+		return Err(ValueToFsError::TooDeep(options.max_depth));
+	}
+	match value {
+		// Exception for arrays of only numbers, since those might be byte arrays, which can be *very* long...
+		Value::Array(array) if !array.iter().all(|el| matches!(el, Value::Number(_))) => {
+			prepare_dir(storage, path, options)?;
+			write_atomic(storage, &path.join(".type"), b"array", options)?;
+			remove_if_file(storage, &path.join("data"))?;
+			let mut names = Vec::with_capacity(array.len());
+			for (n, item) in array.iter().enumerate() {
+				let name = format!("elem_{n}");
+				let child = child_path(storage, path, &name)?;
+				value_to_fs_inner(storage, &child, item, context, depth + 1)?;
+				names.push(name);
+			}
+			prune_dir(storage, path, &["elem_"], &names, options)?;
+		}
+		Value::Object(object) if Bytes::is_marker(object) => {
+			let Some(Value::String(encoded)) = object.get(BYTES_MARKER) else {
+				unreachable!()
+			};
+			let bytes = STANDARD
+				.decode(encoded)
+				.map_err(|e| ValueToFsError::BadBytes(e.to_string()))?;
+			prepare_dir(storage, path, options)?;
+			write_atomic(storage, &path.join(".type"), b"bytes", options)?;
+			write_atomic(storage, &path.join("data"), &bytes, options)?;
+			prune_dir(storage, path, &["key_", "hkey_", "elem_"], &[], options)?;
+		}
+		Value::Object(object) => {
+			prepare_dir(storage, path, options)?;
+			write_atomic(storage, &path.join(".type"), b"dict", options)?;
+			remove_if_file(storage, &path.join("data"))?;
+			let mut names = Vec::with_capacity(object.len());
+			let mut keymap = BTreeMap::new();
+			for (key, item) in object.iter() {
+				let (name, hashed) = key_name(key);
+				if hashed {
+					keymap.insert(name.clone(), key.clone());
+				}
+				let child = child_path(storage, path, &name)?;
+				value_to_fs_inner(storage, &child, item, context, depth + 1)?;
+				names.push(name);
+			}
+			let keymap_path = path.join(".keymap");
+			if !keymap.is_empty() {
+				write_atomic(
+					storage,
+					&keymap_path,
+					&serde_json::to_vec(&keymap)?,
+					options,
+				)?;
+			} else {
+				remove_if_file(storage, &keymap_path)?;
+			}
+			prune_dir(storage, path, &["key_", "hkey_"], &names, options)?;
+		}
+		other => {
+			if storage.kind(path)? == Some(EntryKind::Dir) {
+				storage.remove(path)?;
+			}
+			let written = match encode_leaf(other, options, depth)? {
+				Leaf::Plain(json) => {
+					write_atomic(storage, path, &json, options)?;
+					path.to_path_buf()
+				}
+				Leaf::Gzip(compressed) => {
+					let gzip_path = gzip_path(path);
+					write_atomic(storage, &gzip_path, &compressed, options)?;
+					gzip_path
+				}
+				Leaf::Blob { hash, json } => {
+					write_blob(storage, context, &hash, &json)?;
+					let blob_path = blob_path(path);
+					write_atomic(storage, &blob_path, hash.as_bytes(), options)?;
+					blob_path
+				}
+			};
+			for form in leaf_forms(path) {
+				if form != written {
+					remove_if_file(storage, &form)?;
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+/// The entry name for an object key, and whether it had to be hashed to fit in [`MAX_NAME_LEN`].
+fn key_name(key: &str) -> (String, bool) {
+	let name = format!("key_{}", STANDARD.encode(key));
+	if name.len() > MAX_NAME_LEN {
+		(format!("hkey_{:x}", Sha256::digest(key)), true)
+	} else {
+		(name, false)
+	}
+}
+
+/// How a leaf ends up on disk.
+enum Leaf {
+	Plain(Vec<u8>),
+	Gzip(Vec<u8>),
+	Blob { hash: String, json: Vec<u8> },
+}
+
+/// Serializes a leaf, moving it into a blob or gzipping it if `options` ask for that and it's
+/// worth it. The root is never a blob, since the blobs live inside it.
+fn encode_leaf(
+	value: &Value,
+	options: &WriteOptions,
+	depth: usize,
+) -> Result<Leaf, ValueToFsError> {
+	let json = serde_json::to_vec(value)?;
+	if options.blobs == BlobMode::Shared && depth > 0 && json.len() >= BLOB_MIN_LEN {
+		let hash = format!("{:x}", Sha256::digest(&json));
+		Ok(Leaf::Blob { hash, json })
+	} else if options.compression == CompressionMode::Gzip && json.len() >= GZIP_MIN_LEN {
+		let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(&json)?;
+		Ok(Leaf::Gzip(encoder.finish()?))
+	} else {
+		Ok(Leaf::Plain(json))
+	}
+}
+
+/// Writes a blob unless this write or an earlier one already did. Blobs are named by their
+/// contents, so an existing one never needs rewriting.
+fn write_blob(
+	storage: &impl Storage,
+	context: &WriteContext,
+	hash: &str,
+	json: &[u8],
+) -> std::io::Result<()> {
+	if !context.blobs.lock().unwrap().insert(hash.to_string()) {
+		return Ok(());
+	}
+	let dir = context.root.join(BLOBS_DIR);
+	if storage.kind(&dir)? != Some(EntryKind::Dir) {
+		storage.create_dir_all(&dir)?;
+		storage.sync_dir(context.root, context.options.durability)?;
+	}
+	let blob = dir.join(hash);
+	if storage.kind(&blob)? != Some(EntryKind::File) {
+		storage.write(&blob, json, context.options.durability)?;
+	}
+	Ok(())
+}
+
+/// Drops blobs nothing refers to anymore, once the whole tree has been written.
+fn prune_blobs(storage: &impl Storage, context: &WriteContext) -> std::io::Result<()> {
+	let dir = context.root.join(BLOBS_DIR);
+	if storage.kind(&dir)? != Some(EntryKind::Dir) {
+		return Ok(());
+	}
+	let blobs = context.blobs.lock().unwrap();
+	if blobs.is_empty() {
+		storage.remove(&dir)?;
+		return storage.sync_dir(context.root, context.options.durability);
+	}
+	let mut removed = false;
+	for name in storage.read_dir(&dir)? {
+		if !blobs.contains(&name) {
+			storage.remove(&dir.join(name))?;
+			removed = true;
+		}
+	}
+	if removed {
+		storage.sync_dir(&dir, context.options.durability)?;
+	}
+	Ok(())
+}
+
+/// Whether the contents of a `.blob` file are a hash, rather than a path that could point
+/// anywhere.
+fn is_blob_hash(hash: &str) -> bool {
+	hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Joins a child entry's path, refusing to write through a symlink, which could point anywhere.
+fn child_path(storage: &impl Storage, path: &Path, name: &str) -> Result<PathBuf, ValueToFsError> {
+	let child = path.join(name);
+	for candidate in &leaf_forms(&child) {
+		if storage.is_symlink(candidate) {
+			return Err(ValueToFsError::PathEscape(candidate.to_path_buf()));
+		}
+	}
+	Ok(child)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+	let mut name = path.as_os_str().to_os_string();
+	name.push(suffix);
+	PathBuf::from(name)
+}
+
+fn gzip_path(path: &Path) -> PathBuf {
+	with_suffix(path, GZIP_SUFFIX)
+}
+
+fn blob_path(path: &Path) -> PathBuf {
+	with_suffix(path, BLOB_SUFFIX)
+}
+
+/// Every file a leaf at `path` might have been written as.
+fn leaf_forms(path: &Path) -> [PathBuf; 3] {
+	[path.to_path_buf(), gzip_path(path), blob_path(path)]
+}
+
+/// The entry name a leaf was written under, whatever form it was written in.
+fn leaf_name(name: &str) -> &str {
+	name.strip_suffix(GZIP_SUFFIX)
+		.or_else(|| name.strip_suffix(BLOB_SUFFIX))
+		.unwrap_or(name)
+}
+
+fn remove_if_file(storage: &impl Storage, path: &Path) -> std::io::Result<()> {
+	if storage.kind(path)? == Some(EntryKind::File) {
+		storage.remove(path)?;
+	}
+	Ok(())
+}
+
+/// Makes sure `path` is a directory, replacing a leaf that used to live there.
+fn prepare_dir(storage: &impl Storage, path: &Path, options: &WriteOptions) -> std::io::Result<()> {
+	for form in leaf_forms(path) {
+		remove_if_file(storage, &form)?;
+	}
+	if storage.kind(path)? != Some(EntryKind::Dir) {
+		storage.create_dir_all(path)?;
+		if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+			storage.sync_dir(parent, options.durability)?;
+		}
+	}
+	Ok(())
+}
+
+/// Removes children with one of `prefixes` that aren't in `keep`, along with temp files left
+/// behind by an interrupted write. This runs *after* the new children are written, so an
+/// interruption never leaves a good entry deleted without its replacement.
+fn prune_dir(
+	storage: &impl Storage,
+	path: &Path,
+	prefixes: &[&str],
+	keep: &[String],
+	options: &WriteOptions,
+) -> Result<(), ValueToFsError> {
+	let mut removed = false;
+	stale_entries(storage.read_dir(path)?, prefixes, keep)
+		.map(|name| path.join(name))
+		.try_for_each(|child| -> Result<(), ValueToFsError> {
+			removed = true;
+			// Never follow a symlink out of the database, not even to delete it.
+			if storage.is_symlink(&child) {
+				return Err(ValueToFsError::PathEscape(child));
+			}
+			if storage.kind(&child)? == Some(EntryKind::Dir) && !storage.contains(path, &child)? {
+				return Err(ValueToFsError::PathEscape(child));
+			}
+			storage.remove(&child)?;
+			Ok(())
+		})?;
+	if removed {
+		storage.sync_dir(path, options.durability)?;
+	}
+	Ok(())
+}
+
+/// The entries of a directory that [`prune_dir`] should remove.
+fn stale_entries<'a>(
+	names: Vec<String>,
+	prefixes: &'a [&str],
+	keep: &'a [String],
+) -> impl Iterator<Item = String> + 'a {
+	names.into_iter().filter(|name| {
+		(prefixes.iter().any(|p| name.starts_with(p))
+			&& !keep.iter().any(|keep| keep == leaf_name(name)))
+			|| is_temp_name(name)
+	})
+}
+
+/// Files that already hold `contents` are left alone, so saving a mostly-unchanged DB only
+/// touches what changed.
+fn write_atomic(
+	storage: &impl Storage,
+	path: &Path,
+	contents: &[u8],
+	options: &WriteOptions,
+) -> std::io::Result<()> {
+	if storage
+		.read(path)
+		.is_ok_and(|existing| existing == contents)
+	{
+		return Ok(());
+	}
+	storage.write(path, contents, options.durability)
+}
+
+#[derive(Error, Debug)]
+pub enum FsToValueError {
+	#[error("IO")]
+	IO(#[from] std::io::Error),
+	#[error("JSON")]
+	JSON(#[from] serde_json::Error),
+	#[error("Bad directory type")]
+	BadDirType(String),
+	#[error("No directory type")]
+	NoDirType,
+	#[error("Bad Base64")]
+	Base64(#[from] base64::DecodeError),
+	#[error("Non-UTF8 name")]
+	StringDecode(#[from] FromUtf8Error),
+	#[error("Hashed key missing from .keymap")]
+	MissingKeyMapping(String),
+	#[error("Nested deeper than {0} levels")]
+	TooDeep(usize),
+	#[error("Bad blob reference")]
+	BadBlobRef(String),
+	#[error("{source}, failed at {}", path.display())]
+	At {
+		path: PathBuf,
+		source: Box<FsToValueError>,
+	},
+}
+
+impl FsToValueError {
+	/// Attaches the path being read, keeping the innermost one if there already is one.
+	fn at(self, path: &Path) -> Self {
+		match self {
+			at @ Self::At { .. } => at,
+			other => Self::At {
+				path: path.to_path_buf(),
+				source: Box::new(other),
+			},
+		}
+	}
+}
+
+/// What to do with a leaf file that can't be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoadMode {
+	/// Fail the whole load.
+	#[default]
+	Strict,
+	/// Substitute `null`, record the path, and keep going, so one bad byte doesn't make the
+	/// entire database unloadable.
+	Lenient,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+	pub max_depth: usize,
+	pub mode: LoadMode,
+}
+
+impl Default for ReadOptions {
+	fn default() -> Self {
+		Self {
+			max_depth: DEFAULT_MAX_DEPTH,
+			mode: LoadMode::default(),
+		}
+	}
+}
+
+/// What a load had to work around to succeed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LoadReport {
+	/// Corrupt leaves that [`LoadMode::Lenient`] replaced with `null`.
+	pub skipped: Vec<PathBuf>,
+	/// Directories whose `.type` was missing and had to be inferred from their contents.
+	pub repaired: Vec<PathBuf>,
+}
+
+impl LoadReport {
+	pub fn is_empty(&self) -> bool {
+		self.skipped.is_empty() && self.repaired.is_empty()
+	}
+}
+
+struct ReadContext<'a> {
+	options: &'a ReadOptions,
+	root: &'a Path,
+	skipped: Mutex<Vec<PathBuf>>,
+	repaired: Mutex<Vec<PathBuf>>,
+	started: Instant,
+}
+
+impl<'a> ReadContext<'a> {
+	fn new(root: &'a Path, options: &'a ReadOptions) -> Self {
+		Self {
+			options,
+			root,
+			skipped: Mutex::default(),
+			repaired: Mutex::default(),
+			started: Instant::now(),
+		}
+	}
+
+	fn finish<D: DeserializeOwned>(
+		self,
+		path: &Path,
+		value: Value,
+	) -> Result<(D, LoadReport), FsToValueError> {
+		let value = serde_json::from_value(value).map_err(|e| FsToValueError::from(e).at(path))?;
+		tracing::debug!(path = %path.display(), took = ?self.started.elapsed(), "Read tree");
+		let mut report = LoadReport {
+			skipped: self.skipped.into_inner().unwrap(),
+			repaired: self.repaired.into_inner().unwrap(),
+		};
+		report.skipped.sort();
+		report.repaired.sort();
+		for repaired in &report.repaired {
+			tracing::warn!(path = %repaired.display(), "Repaired a directory with no .type");
+		}
+		Ok((value, report))
+	}
+}
+
+pub fn fs_to_value<D: DeserializeOwned>(path: &Path) -> Result<D, FsToValueError> {
+	fs_to_value_with(path, &ReadOptions::default())
+}
+
+pub fn fs_to_value_with<D: DeserializeOwned>(
+	path: &Path,
+	options: &ReadOptions,
+) -> Result<D, FsToValueError> {
+	fs_to_value_report(path, options).map(|(value, _)| value)
+}
+
+/// Like [`fs_to_value_with`], but also reports what had to be skipped or repaired.
+pub fn fs_to_value_report<D: DeserializeOwned>(
+	path: &Path,
+	options: &ReadOptions,
+) -> Result<(D, LoadReport), FsToValueError> {
+	storage_to_value_report(&FsStorage, path, options)
+}
+
+/// Like [`fs_to_value_with`], but reads from any [`Storage`].
+pub fn storage_to_value<D: DeserializeOwned>(
+	storage: &impl Storage,
+	path: &Path,
+	options: &ReadOptions,
+) -> Result<D, FsToValueError> {
+	storage_to_value_report(storage, path, options).map(|(value, _)| value)
+}
+
+pub fn storage_to_value_report<D: DeserializeOwned>(
+	storage: &impl Storage,
+	path: &Path,
+	options: &ReadOptions,
+) -> Result<(D, LoadReport), FsToValueError> {
+	let context = ReadContext::new(path, options);
+	let value = fs_to_value_inner(storage, path, &context, 0)?;
+	context.finish(path, value)
+}
+
+fn fs_to_value_inner(
+	storage: &impl Storage,
+	path: &Path,
+	context: &ReadContext,
+	depth: usize,
+) -> Result<Value, FsToValueError> {
+	read_node(storage, path, context, depth).map_err(|e| e.at(path))
+}
+
+fn read_leaf(path: &Path, file: impl Read, context: &ReadContext) -> Result<Value, FsToValueError> {
+	match serde_json::from_reader(file) {
+		Ok(value) => Ok(value),
+		Err(e) if context.options.mode == LoadMode::Lenient => {
+			tracing::warn!(path = %path.display(), error = %e, "Skipped an unreadable entry");
+			context.skipped.lock().unwrap().push(path.to_path_buf());
+			Ok(Value::Null)
+		}
+		Err(e) => Err(e.into()),
+	}
+}
+
+fn bytes_value(bytes: &[u8]) -> Value {
+	serde_json::json!({ BYTES_MARKER: STANDARD.encode(bytes) })
+}
+
+/// The element paths of an array directory, in order.
+///
+/// Elements are `elem_{index}`, or `elem_{index}_{uuid}` in databases written before names were
+/// made stable. If an interrupted migration left both, the stable one wins.
+fn array_entries(path: &Path, names: Vec<String>) -> Vec<PathBuf> {
+	let mut names: Vec<_> = names
+		.into_iter()
+		.filter(|s| s.starts_with("elem_"))
+		.map(|s| leaf_name(&s).to_string())
+		.map(|s| (s[5..].to_string(), path.join(s)))
+		.filter_map(|(index_and_name, path)| {
+			let (index, legacy) = match index_and_name.split_once('_') {
+				Some((index, _)) => (index, true),
+				None => (index_and_name.as_str(), false),
+			};
+			Some((index.parse().ok()?, legacy, path))
+		})
+		.collect();
+	names.sort_by_key(|(index, legacy, _)| -> (usize, bool) { (*index, *legacy) });
+	names.dedup_by_key(|(index, _, _)| *index);
+	names.into_iter().map(|(_, _, path)| path).collect()
+}
+
+/// The entry paths of a dict directory, along with the keys they hold.
+fn dict_entries(
+	path: &Path,
+	names: Vec<String>,
+	keymap: &BTreeMap<String, String>,
+) -> Result<Vec<(PathBuf, String)>, FsToValueError> {
+	names
+		.into_iter()
+		.filter(|s| s.starts_with("key_") || s.starts_with("hkey_"))
+		.map(|s| leaf_name(&s).to_string())
+		.map(|orig_s| {
+			if orig_s.starts_with("hkey_") {
+				return keymap
+					.get(&orig_s)
+					.map(|key| (path.join(&orig_s), key.clone()))
+					.ok_or(FsToValueError::MissingKeyMapping(orig_s));
+			}
+			STANDARD
+				.decode(&orig_s[4..])
+				.map_err(|e| -> FsToValueError { e.into() })
+				.and_then(|b| String::from_utf8(b).map_err(|e| e.into()))
+				.map(|s| (path.join(&orig_s), s))
+		})
+		.collect()
+}
+
+fn read_node(
+	storage: &impl Storage,
+	path: &Path,
+	context: &ReadContext,
+	depth: usize,
+) -> Result<Value, FsToValueError> {
+	let max_depth = context.options.max_depth;
+	if depth > max_depth {
+		return Err(FsToValueError::TooDeep(max_depth));
+	}
+	let kind = storage.kind(path)?;
+	let gzip_path = gzip_path(path);
+	if kind.is_none() && storage.kind(&gzip_path)? == Some(EntryKind::File) {
+		let bytes = storage.read(&gzip_path)?;
+		return read_leaf(&gzip_path, GzDecoder::new(bytes.as_slice()), context);
+	}
+	let blob_path = blob_path(path);
+	if kind.is_none() && storage.kind(&blob_path)? == Some(EntryKind::File) {
+		let hash = String::from_utf8_lossy(&storage.read(&blob_path)?).to_string();
+		if !is_blob_hash(&hash) {
+			return Err(FsToValueError::BadBlobRef(hash));
+		}
+		let json = storage.read(&context.root.join(BLOBS_DIR).join(hash))?;
+		return read_leaf(&blob_path, json.as_slice(), context);
+	}
+	let Some(kind) = kind else {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::NotFound,
+			format!("{} not found", path.display()),
+		)
+		.into());
+	};
+	let dir_type = match (kind, storage.read(&path.join(".type"))) {
+		(EntryKind::Dir, Ok(t)) => String::from_utf8_lossy(&t).to_string(),
+		(EntryKind::Dir, Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+			let dir_type = infer_dir_type(&storage.read_dir(path)?)?;
+			context.repaired.lock().unwrap().push(path.to_path_buf());
+			dir_type.to_string()
+		}
+		(EntryKind::Dir, Err(e)) => return Err(e.into()),
+		(EntryKind::File, _) => String::new(),
+	};
+	match (kind, dir_type.as_str()) {
+		(EntryKind::File, _) => read_leaf(path, storage.read(path)?.as_slice(), context),
+		(EntryKind::Dir, "array") => Ok(Value::Array(
+			array_entries(path, storage.read_dir(path)?)
+				.into_par_iter()
+				.map(|path| fs_to_value_inner(storage, &path, context, depth + 1))
+				.collect::<Result<_, _>>()?,
+		)),
+		(EntryKind::Dir, "dict") => {
+			let keymap = match storage.read(&path.join(".keymap")) {
+				Ok(keymap) => serde_json::from_slice(&keymap)?,
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+				Err(e) => return Err(e.into()),
+			};
+			let entries: Vec<_> = dict_entries(path, storage.read_dir(path)?, &keymap)?
+				.into_par_iter()
+				.map(|(path, name)| {
+					fs_to_value_inner(storage, &path, context, depth + 1).map(|value| (name, value))
+				})
+				.collect::<Result<_, _>>()?;
+			Ok(Value::Object(entries.into_iter().collect()))
+		}
+		(EntryKind::Dir, "bytes") => Ok(bytes_value(&storage.read(&path.join("data"))?)),
+		(EntryKind::Dir, dir_type) => Err(FsToValueError::BadDirType(dir_type.to_string())),
+	}
+}
+
+/// Guesses the `.type` of a directory that lost it (say, to an interrupted write) from what's in
+/// it. A directory with nothing to go on was most likely an empty object.
+fn infer_dir_type(names: &[String]) -> Result<&'static str, FsToValueError> {
+	let has = |prefix: &str| names.iter().any(|name| name.starts_with(prefix));
+	match (has("key_") || has("hkey_"), has("elem_")) {
+		(true, true) => Err(FsToValueError::NoDirType),
+		(true, false) => Ok("dict"),
+		(false, true) => Ok("array"),
+		(false, false) if names.iter().any(|name| name == "data") => Ok("bytes"),
+		(false, false) => Ok("dict"),
+	}
+}
+
+/// Walks a value the way serde_json would, looking only for floats JSON can't represent.
+/// serde_json quietly writes NaN and infinities as `null`, which then fails to load back into an
+/// `f64`, making the whole database unloadable.
+#[derive(Default)]
+struct FiniteCheck {
+	path: Vec<String>,
+}
+
+#[derive(Debug)]
+enum FiniteCheckError {
+	NonFinite(String),
+	/// Some other serialization failure; `serde_json::to_value` will report it properly.
+	Other,
+}
+
+impl std::fmt::Display for FiniteCheckError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::NonFinite(path) => write!(f, "non-finite float at {path}"),
+			Self::Other => write!(f, "serialization failed"),
+		}
+	}
+}
+
+impl std::error::Error for FiniteCheckError {}
+
+impl serde::ser::Error for FiniteCheckError {
+	fn custom<T: std::fmt::Display>(_: T) -> Self {
+		Self::Other
+	}
+}
+
+impl FiniteCheck {
+	fn float(&self, value: f64) -> Result<(), FiniteCheckError> {
+		if value.is_finite() {
+			Ok(())
+		} else {
+			Err(FiniteCheckError::NonFinite(self.path.join(".")))
+		}
+	}
+
+	fn nested<T: ?Sized + Serialize>(
+		&mut self,
+		segment: String,
+		value: &T,
+	) -> Result<(), FiniteCheckError> {
+		self.path.push(segment);
+		value.serialize(&mut *self)?;
+		self.path.pop();
+		Ok(())
+	}
+}
+
+struct FiniteCheckCompound<'a> {
+	check: &'a mut FiniteCheck,
+	index: usize,
+	key: String,
+}
+
+impl<'a> FiniteCheckCompound<'a> {
+	fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), FiniteCheckError> {
+		self.index += 1;
+		self.check.nested((self.index - 1).to_string(), value)
+	}
+}
+
+impl<'a> serde::Serializer for &'a mut FiniteCheck {
+	type Ok = ();
+	type Error = FiniteCheckError;
+	type SerializeSeq = FiniteCheckCompound<'a>;
+	type SerializeTuple = FiniteCheckCompound<'a>;
+	type SerializeTupleStruct = FiniteCheckCompound<'a>;
+	type SerializeTupleVariant = FiniteCheckCompound<'a>;
+	type SerializeMap = FiniteCheckCompound<'a>;
+	type SerializeStruct = FiniteCheckCompound<'a>;
+	type SerializeStructVariant = FiniteCheckCompound<'a>;
+
+	fn serialize_bool(self, _: bool) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_i8(self, _: i8) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_i16(self, _: i16) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_i32(self, _: i32) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_i64(self, _: i64) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_u8(self, _: u8) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_u16(self, _: u16) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_u32(self, _: u32) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_u64(self, _: u64) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_f32(self, v: f32) -> Result<(), FiniteCheckError> {
+		self.float(v.into())
+	}
+	fn serialize_f64(self, v: f64) -> Result<(), FiniteCheckError> {
+		self.float(v)
+	}
+	fn serialize_char(self, _: char) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_str(self, _: &str) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_bytes(self, _: &[u8]) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_none(self) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), FiniteCheckError> {
+		value.serialize(self)
+	}
+	fn serialize_unit(self) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_unit_struct(self, _: &'static str) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_unit_variant(
+		self,
+		_: &'static str,
+		_: u32,
+		_: &'static str,
+	) -> Result<(), FiniteCheckError> {
+		Ok(())
+	}
+	fn serialize_newtype_struct<T: ?Sized + Serialize>(
+		self,
+		_: &'static str,
+		value: &T,
+	) -> Result<(), FiniteCheckError> {
+		value.serialize(self)
+	}
+	fn serialize_newtype_variant<T: ?Sized + Serialize>(
+		self,
+		_: &'static str,
+		_: u32,
+		variant: &'static str,
+		value: &T,
+	) -> Result<(), FiniteCheckError> {
+		self.nested(variant.to_string(), value)
+	}
+	fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, FiniteCheckError> {
+		Ok(self.compound(String::new()))
+	}
+	fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, FiniteCheckError> {
+		Ok(self.compound(String::new()))
+	}
+	fn serialize_tuple_struct(
+		self,
+		_: &'static str,
+		_: usize,
+	) -> Result<Self::SerializeTupleStruct, FiniteCheckError> {
+		Ok(self.compound(String::new()))
+	}
+	fn serialize_tuple_variant(
+		self,
+		_: &'static str,
+		_: u32,
+		variant: &'static str,
+		_: usize,
+	) -> Result<Self::SerializeTupleVariant, FiniteCheckError> {
+		self.path.push(variant.to_string());
+		Ok(self.compound(String::new()))
+	}
+	fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, FiniteCheckError> {
+		Ok(self.compound(String::new()))
+	}
+	fn serialize_struct(
+		self,
+		_: &'static str,
+		_: usize,
+	) -> Result<Self::SerializeStruct, FiniteCheckError> {
+		Ok(self.compound(String::new()))
+	}
+	fn serialize_struct_variant(
+		self,
+		_: &'static str,
+		_: u32,
+		variant: &'static str,
+		_: usize,
+	) -> Result<Self::SerializeStructVariant, FiniteCheckError> {
+		self.path.push(variant.to_string());
+		Ok(self.compound(String::new()))
+	}
+}
+
+impl FiniteCheck {
+	fn compound(&mut self, key: String) -> FiniteCheckCompound<'_> {
+		FiniteCheckCompound {
+			check: self,
+			index: 0,
+			key,
+		}
+	}
+}
+
+impl serde::ser::SerializeSeq for FiniteCheckCompound<'_> {
+	type Ok = ();
+	type Error = FiniteCheckError;
+	fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+		self.element(value)
+	}
+	fn end(self) -> Result<(), Self::Error> {
+		Ok(())
+	}
+}
+
+impl serde::ser::SerializeTuple for FiniteCheckCompound<'_> {
+	type Ok = ();
+	type Error = FiniteCheckError;
+	fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+		self.element(value)
+	}
+	fn end(self) -> Result<(), Self::Error> {
+		Ok(())
+	}
+}
+
+impl serde::ser::SerializeTupleStruct for FiniteCheckCompound<'_> {
+	type Ok = ();
+	type Error = FiniteCheckError;
+	fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+		self.element(value)
+	}
+	fn end(self) -> Result<(), Self::Error> {
+		Ok(())
+	}
+}
+
+impl serde::ser::SerializeTupleVariant for FiniteCheckCompound<'_> {
+	type Ok = ();
+	type Error = FiniteCheckError;
+	fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+		self.element(value)
+	}
+	fn end(self) -> Result<(), Self::Error> {
+		self.check.path.pop();
+		Ok(())
+	}
+}
+
+impl serde::ser::SerializeMap for FiniteCheckCompound<'_> {
+	type Ok = ();
+	type Error = FiniteCheckError;
+	fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+		self.key = match serde_json::to_value(key) {
+			Ok(Value::String(key)) => key,
+			Ok(other) => other.to_string(),
+			Err(_) => return Err(FiniteCheckError::Other),
+		};
+		Ok(())
+	}
+	fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+		let key = std::mem::take(&mut self.key);
+		self.check.nested(key, value)
+	}
+	fn end(self) -> Result<(), Self::Error> {
+		Ok(())
+	}
+}
+
+impl serde::ser::SerializeStruct for FiniteCheckCompound<'_> {
+	type Ok = ();
+	type Error = FiniteCheckError;
+	fn serialize_field<T: ?Sized + Serialize>(
+		&mut self,
+		key: &'static str,
+		value: &T,
+	) -> Result<(), Self::Error> {
+		self.check.nested(key.to_string(), value)
+	}
+	fn end(self) -> Result<(), Self::Error> {
+		Ok(())
+	}
+}
+
+impl serde::ser::SerializeStructVariant for FiniteCheckCompound<'_> {
+	type Ok = ();
+	type Error = FiniteCheckError;
+	fn serialize_field<T: ?Sized + Serialize>(
+		&mut self,
+		key: &'static str,
+		value: &T,
+	) -> Result<(), Self::Error> {
+		self.check.nested(key.to_string(), value)
+	}
+	fn end(self) -> Result<(), Self::Error> {
+		self.check.path.pop();
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{
+		fs_diff, fs_to_value, fs_to_value_async, fs_to_value_report, fs_to_value_with,
+		storage_to_value, storage_to_value_report, value_to_fs, value_to_fs_async,
+		value_to_fs_async_with, value_to_fs_with, value_to_storage, BlobMode, Bytes,
+		CompressionMode, DurabilityMode, FsChange, FsToValueError, LoadMode, MemStorage,
+		ReadOptions, Storage, ValueToFsError, WriteOptions, WriteStats,
+	};
+	use rss::Channel;
+	use serde::{Deserialize, Serialize};
+	use serde_json::{json, Value};
+	use std::{
+		collections::HashMap,
+		io::BufReader,
+		path::{Path, PathBuf},
+	};
+	use uuid::Uuid;
+
+	fn test_dir() -> PathBuf {
+		std::env::temp_dir().join(format!("winter2_test_{}", Uuid::new_v4()))
+	}
+
+	#[test]
+	fn test_atom_feed() {
+		let feed = reqwest::blocking::get("https://www.spreaker.com/show/4488937/episodes/feed")
+			.unwrap()
+			.bytes()
+			.unwrap();
+		let feed = Channel::read_from(BufReader::new(&feed[..])).unwrap();
+		let path = PathBuf::from("./___test_ser_dir");
+		value_to_fs(&path, &feed).unwrap();
+		let read: Channel = fs_to_value(&path).unwrap();
+		assert_eq!(feed.items.len(), read.items.len());
+
+		let gzip_path = PathBuf::from("./___test_ser_dir_gz");
+		value_to_fs_with(
+			&gzip_path,
+			&feed,
+			&WriteOptions {
+				compression: CompressionMode::Gzip,
+				..Default::default()
+			},
+		)
+		.unwrap();
+		let read: Channel = fs_to_value(&gzip_path).unwrap();
+		assert_eq!(feed, read);
+		assert!(tree_size(&gzip_path) < tree_size(&path));
+	}
+
+	fn tree_size(path: &Path) -> u64 {
+		listing(path)
+			.iter()
+			.filter(|p| p.is_file())
+			.map(|p| std::fs::metadata(p).unwrap().len())
+			.sum()
+	}
+
+	#[test]
+	fn stray_temp_file_is_ignored_and_cleaned() {
+		let path = test_dir();
+		let value = json!({"a": "hello", "b": ["x", "y"]});
+		value_to_fs(&path, &value).unwrap();
+		// Simulate a write of `a` that was interrupted before the rename.
+		let stray = path.join(".key_YQ==.tmp");
+		std::fs::write(&stray, "\"hel").unwrap();
+		assert_eq!(fs_to_value::<Value>(&path).unwrap(), value);
+		value_to_fs(&path, &value).unwrap();
+		assert!(!stray.exists());
+		assert_eq!(fs_to_value::<Value>(&path).unwrap(), value);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn read_errors_carry_the_failing_path() {
+		let path = test_dir();
+		value_to_fs(&path, &json!({"feed": {"title": "ok"}})).unwrap();
+		let leaf = path.join("key_ZmVlZA==").join("key_dGl0bGU=");
+		std::fs::write(&leaf, "{not json").unwrap();
+		match fs_to_value::<Value>(&path) {
+			Err(FsToValueError::At { path: at, source }) => {
+				assert_eq!(at, leaf);
+				assert!(matches!(*source, FsToValueError::JSON(_)));
+			}
+			other => panic!("expected a path-tagged error, got {other:?}"),
+		}
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	fn listing(path: &Path) -> Vec<PathBuf> {
+		let mut entries = vec![];
+		for entry in std::fs::read_dir(path).unwrap().flatten() {
+			entries.push(entry.path());
+			if entry.path().is_dir() {
+				entries.extend(listing(&entry.path()));
+			}
+		}
+		entries.sort();
+		entries
+	}
+
+	#[test]
+	fn array_names_are_stable() {
+		let path = test_dir();
+		let value = json!({"items": ["a", "b", "c"]});
+		value_to_fs(&path, &value).unwrap();
+		let before = listing(&path);
+		value_to_fs(&path, &value).unwrap();
+		assert_eq!(before, listing(&path));
+
+		let shrunk = json!({"items": ["a"]});
+		value_to_fs(&path, &shrunk).unwrap();
+		assert_eq!(fs_to_value::<Value>(&path).unwrap(), shrunk);
+		assert!(!path.join("key_aXRlbXM=").join("elem_1").exists());
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn legacy_array_names_still_load() {
+		let path = test_dir();
+		std::fs::create_dir_all(&path).unwrap();
+		std::fs::write(path.join(".type"), "array").unwrap();
+		std::fs::write(path.join(format!("elem_1_{}", Uuid::new_v4())), "\"b\"").unwrap();
+		std::fs::write(path.join(format!("elem_0_{}", Uuid::new_v4())), "\"a\"").unwrap();
+		assert_eq!(fs_to_value::<Value>(&path).unwrap(), json!(["a", "b"]));
+		value_to_fs(&path, &json!(["a", "b"])).unwrap();
+		let mut names: Vec<_> = std::fs::read_dir(&path)
+			.unwrap()
+			.flatten()
+			.map(|e| e.file_name().to_string_lossy().to_string())
+			.collect();
+		names.sort();
+		assert_eq!(names, [".type", "elem_0", "elem_1"]);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn unchanged_leaves_are_not_rewritten() {
+		use std::os::unix::fs::MetadataExt;
+
+		let inodes = |path: &Path| -> Vec<(PathBuf, u64)> {
+			listing(path)
+				.into_iter()
+				.map(|p| {
+					let ino = std::fs::metadata(&p).unwrap().ino();
+					(p, ino)
+				})
+				.collect()
+		};
+		let path = test_dir();
+		value_to_fs(&path, &json!({"a": 0.5, "b": 1.0})).unwrap();
+		let before = inodes(&path);
+		value_to_fs(&path, &json!({"a": 0.5, "b": 1.0})).unwrap();
+		assert_eq!(before, inodes(&path));
+
+		value_to_fs(&path, &json!({"a": 0.5, "b": 0.0})).unwrap();
+		let changed: Vec<_> = before
+			.iter()
+			.zip(inodes(&path))
+			.filter(|(old, new)| **old != *new)
+			.map(|(_, (path, _))| path)
+			.collect();
+		assert_eq!(changed, [path.join("key_Yg==")]);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn long_keys_round_trip() {
+		let path = test_dir();
+		let long_key = "https://example.com/".to_string() + &"a".repeat(280);
+		let mut value = json!({"short": 1});
+		value[&long_key] = json!({"read": 0.5});
+		value_to_fs(&path, &value).unwrap();
+		assert!(path.join(".keymap").exists());
+		assert_eq!(fs_to_value::<Value>(&path).unwrap(), value);
+
+		value_to_fs(&path, &json!({"short": 1})).unwrap();
+		assert!(!path.join(".keymap").exists());
+		assert_eq!(fs_to_value::<Value>(&path).unwrap(), json!({"short": 1}));
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn bytes_are_stored_raw() {
+		let path = test_dir();
+		let blob = Bytes((0..=255).collect());
+		let value = json!({"blob": blob, "other": [1, 2, 3]});
+		value_to_fs(&path, &value).unwrap();
+		let data = path.join("key_YmxvYg==").join("data");
+		assert_eq!(std::fs::read(data).unwrap(), blob.0);
+		let read: Value = fs_to_value(&path).unwrap();
+		assert_eq!(read, value);
+		let read: Bytes = serde_json::from_value(read["blob"].clone()).unwrap();
+		assert_eq!(read, blob);
+
+		value_to_fs(&path, &json!({"blob": "now a string"})).unwrap();
+		assert_eq!(
+			fs_to_value::<Value>(&path).unwrap(),
+			json!({"blob": "now a string"})
+		);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn compressed_leaves_round_trip_and_switch_modes() {
+		let path = test_dir();
+		let long = "a long description ".repeat(20);
+		let value = json!({"description": long, "title": "short", "items": [long]});
+		let gzip = WriteOptions {
+			compression: CompressionMode::Gzip,
+			..Default::default()
+		};
+		value_to_fs_with(&path, &value, &gzip).unwrap();
+		assert!(path.join("key_ZGVzY3JpcHRpb24=.json.gz").is_file());
+		assert!(path.join("key_dGl0bGU=").is_file());
+		assert_eq!(fs_to_value::<Value>(&path).unwrap(), value);
+
+		value_to_fs(&path, &value).unwrap();
+		assert!(!path.join("key_ZGVzY3JpcHRpb24=.json.gz").exists());
+		assert_eq!(fs_to_value::<Value>(&path).unwrap(), value);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	/// Writes 10k files, so it's left out of the usual run. See how loading them in parallel
+	/// compares with `cargo test --release parallel_load_benchmark -- --ignored --nocapture`.
+	#[test]
+	#[ignore]
+	fn parallel_load_benchmark() {
+		let path = test_dir();
+		let value: Value = (0..100)
+			.map(|feed| {
+				let items: Vec<_> = (0..100).map(|item| format!("{feed}/{item}")).collect();
+				(format!("feed_{feed}"), json!(items))
+			})
+			.collect::<serde_json::Map<_, _>>()
+			.into();
+		let fast = WriteOptions {
+			durability: DurabilityMode::Fast,
+			..Default::default()
+		};
+		value_to_fs_with(&path, &value, &fast).unwrap();
+
+		let serial_pool = rayon::ThreadPoolBuilder::new()
+			.num_threads(1)
+			.build()
+			.unwrap();
+		let start = std::time::Instant::now();
+		let serial: Value = serial_pool.install(|| fs_to_value(&path)).unwrap();
+		let serial_time = start.elapsed();
+		let start = std::time::Instant::now();
+		let parallel: Value = fs_to_value(&path).unwrap();
+		let parallel_time = start.elapsed();
+		println!("10k leaves: serial {serial_time:?}, parallel {parallel_time:?}");
+		assert_eq!(serial, value);
+		assert_eq!(parallel, value);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn pruning_a_dir_outside_the_db_is_an_error() {
+		let path = test_dir();
+		let outside = test_dir();
+		std::fs::create_dir_all(&outside).unwrap();
+		std::fs::write(outside.join("precious"), "keep me").unwrap();
+		value_to_fs(&path, &json!({})).unwrap();
+		std::os::unix::fs::symlink(&outside, path.join("key_YQ==")).unwrap();
+
+		match value_to_fs(&path, &json!({})) {
+			Err(ValueToFsError::At { source, .. }) => {
+				assert!(matches!(*source, ValueToFsError::PathEscape(_)))
+			}
+			other => panic!("expected PathEscape, got {other:?}"),
+		}
+		assert!(outside.join("precious").exists());
+		std::fs::remove_dir_all(&path).unwrap();
+		std::fs::remove_dir_all(&outside).unwrap();
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn symlinked_entries_are_not_followed() {
+		let path = test_dir();
+		let outside = test_dir();
+		std::fs::create_dir_all(&outside).unwrap();
+		std::fs::write(outside.join(".type"), "dict").unwrap();
+		std::fs::write(outside.join("key_Yg=="), "\"keep me\"").unwrap();
+		value_to_fs(&path, &json!({})).unwrap();
+		let link = path.join("key_YQ==");
+		std::os::unix::fs::symlink(&outside, &link).unwrap();
+
+		// Writing into the symlinked key must neither write through it nor prune behind it.
+		assert!(value_to_fs(&path, &json!({"a": {"c": 1}})).is_err());
+		assert!(!outside.join("key_Yw==").exists());
+		assert_eq!(
+			std::fs::read_to_string(outside.join("key_Yg==")).unwrap(),
+			"\"keep me\""
+		);
+		assert!(std::fs::symlink_metadata(&link).is_ok());
+		std::fs::remove_dir_all(&path).unwrap();
+		std::fs::remove_dir_all(&outside).unwrap();
+	}
+
+	#[test]
+	fn depth_limit_is_enforced() {
+		let path = test_dir();
+		let mut value = json!("leaf");
+		for _ in 0..5 {
+			value = json!({ "a": value });
+		}
+		let shallow = WriteOptions {
+			max_depth: 3,
+			..Default::default()
+		};
+		let Err(ValueToFsError::At { source, .. }) = value_to_fs_with(&path, &value, &shallow)
+		else {
+			panic!("expected a write error");
+		};
+		assert!(matches!(*source, ValueToFsError::TooDeep(3)));
+
+		value_to_fs(&path, &value).unwrap();
+		let shallow = ReadOptions {
+			max_depth: 3,
+			..Default::default()
+		};
+		let Err(FsToValueError::At { source, .. }) = fs_to_value_with::<Value>(&path, &shallow)
+		else {
+			panic!("expected a read error");
+		};
+		assert!(matches!(*source, FsToValueError::TooDeep(3)));
+		assert_eq!(fs_to_value::<Value>(&path).unwrap(), value);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn lenient_mode_skips_corrupt_leaves() {
+		let path = test_dir();
+		value_to_fs(&path, &json!({"good": "fine", "bad": "soon broken"})).unwrap();
+		let bad = path.join("key_YmFk");
+		std::fs::write(&bad, "\"soon br").unwrap();
+		assert!(fs_to_value::<Value>(&path).is_err());
+
+		let lenient = ReadOptions {
+			mode: LoadMode::Lenient,
+			..Default::default()
+		};
+		let (value, report) = fs_to_value_report::<Value>(&path, &lenient).unwrap();
+		assert_eq!(value, json!({"good": "fine", "bad": null}));
+		assert_eq!(report.skipped, [bad]);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn numbers_keep_their_type_and_precision() {
+		// serde_json keeps integers and floats apart in `Value::Number` and prints floats in
+		// shortest round-trip form, so no `arbitrary_precision` is needed for these.
+		#[derive(Serialize, Deserialize, PartialEq, Debug)]
+		struct Numbers {
+			unsigned: Vec<u64>,
+			signed: Vec<i64>,
+			floats: Vec<f64>,
+			one_int: u64,
+			one_float: f64,
+			negative_zero: f64,
+			read_articles: HashMap<String, f64>,
+		}
+		let numbers = Numbers {
+			unsigned: vec![0, 1, (1 << 53) - 1, 1 << 53, (1 << 53) + 1, u64::MAX],
+			signed: vec![-1, -(1 << 53) - 1, i64::MIN, i64::MAX],
+			floats: vec![0.0, 1.0, 0.1, -2.5, 1e300, f64::MIN_POSITIVE, f64::MAX],
+			one_int: 1,
+			one_float: 1.0,
+			negative_zero: -0.0,
+			read_articles: [("a".to_string(), 1.0), ("b".to_string(), 0.0)].into(),
+		};
+		let path = test_dir();
+		value_to_fs(&path, &numbers).unwrap();
+		let read: Numbers = fs_to_value(&path).unwrap();
+		assert_eq!(read, numbers);
+		assert!(read.negative_zero.is_sign_negative());
+
+		let raw: Value = fs_to_value(&path).unwrap();
+		assert!(raw["one_int"].is_u64());
+		assert!(raw["one_float"].is_f64());
+		assert!(raw["read_articles"]["a"].is_f64());
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn non_finite_floats_are_rejected() {
+		let path = test_dir();
+		let read_articles = HashMap::from([("guid", f64::NAN)]);
+		let value = HashMap::from([("feed", HashMap::from([("read_articles", read_articles)]))]);
+		let Err(ValueToFsError::At { source, .. }) = value_to_fs(&path, &value) else {
+			panic!("expected a write error");
+		};
+		assert!(
+			matches!(*source, ValueToFsError::NonFiniteFloat(ref at) if at == "feed.read_articles.guid")
+		);
+		assert!(!path.exists());
+		value_to_fs(&path, &json!({"floats": [0.0, 1.0]})).unwrap();
+		assert!(value_to_fs(&path, &HashMap::from([("floats", [f64::INFINITY])])).is_err());
+		assert_eq!(
+			fs_to_value::<Value>(&path).unwrap(),
+			json!({"floats": [0.0, 1.0]})
+		);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn in_memory_round_trip() {
+		let storage = MemStorage::new();
+		let path = Path::new("/db");
+		let value = json!({
+			"feeds": {"a": {"items": [{"title": "one"}, {"title": "two"}], "read": 1.5}},
+			"raw": Bytes(vec![0, 1, 2, 255]),
+		});
+		value_to_storage(&storage, path, &value, &WriteOptions::default()).unwrap();
+		let read: Value = storage_to_value(&storage, path, &ReadOptions::default()).unwrap();
+		assert_eq!(read, value);
+		assert_eq!(
+			storage.read(&path.join("key_cmF3/data")).unwrap(),
+			[0, 1, 2, 255]
+		);
+
+		let smaller = json!({"feeds": {}});
+		value_to_storage(&storage, path, &smaller, &WriteOptions::default()).unwrap();
+		assert!(storage.kind(&path.join("key_cmF3")).unwrap().is_none());
+		let read: Value = storage_to_value(&storage, path, &ReadOptions::default()).unwrap();
+		assert_eq!(read, smaller);
+	}
+
+	#[test]
+	fn in_memory_corruption_and_interruption() {
+		let storage = MemStorage::new();
+		let path = Path::new("/db");
+		let value = json!({"a": "kept", "b": "corrupted"});
+		value_to_storage(&storage, path, &value, &WriteOptions::default()).unwrap();
+		// A temp file from an interrupted write is ignored on load and cleaned on the next save.
+		let temp = path.join(".key_YQ==.tmp");
+		storage
+			.write(&temp, b"\"half", DurabilityMode::Fast)
+			.unwrap();
+		let read: Value = storage_to_value(&storage, path, &ReadOptions::default()).unwrap();
+		assert_eq!(read, value);
+		value_to_storage(&storage, path, &value, &WriteOptions::default()).unwrap();
+		assert!(storage.kind(&temp).unwrap().is_none());
+
+		let corrupt = path.join("key_Yg==");
+		storage
+			.write(&corrupt, b"{not json", DurabilityMode::Fast)
+			.unwrap();
+		assert!(storage_to_value::<Value>(&storage, path, &ReadOptions::default()).is_err());
+		let options = ReadOptions {
+			mode: LoadMode::Lenient,
+			..Default::default()
+		};
+		let (read, report): (Value, _) = storage_to_value_report(&storage, path, &options).unwrap();
+		assert_eq!(read, json!({"a": "kept", "b": null}));
+		assert_eq!(report.skipped, [corrupt]);
+	}
+
+	#[tokio::test]
+	async fn async_matches_sync() {
+		let value = json!({
+			"feeds": {"a": {"items": [{"title": "one"}, {"title": "two"}], "read": 1.5}},
+			"raw": Bytes(vec![0, 1, 2, 255]),
+			"long": {"k".repeat(300): true},
+		});
+		let (sync_path, async_path) = (test_dir(), test_dir());
+		let sync_stats = value_to_fs(&sync_path, &value).unwrap();
+		let async_stats = value_to_fs_async(&async_path, &value).await.unwrap();
+		assert_eq!(sync_stats, async_stats);
+		let relative = |root: &Path| -> Vec<PathBuf> {
+			listing(root)
+				.into_iter()
+				.map(|p| p.strip_prefix(root).unwrap().to_path_buf())
+				.collect()
+		};
+		assert_eq!(relative(&sync_path), relative(&async_path));
+		assert_eq!(fs_to_value_async::<Value>(&sync_path).await.unwrap(), value);
+
+		let smaller = json!({"feeds": {}});
+		value_to_fs_async(&async_path, &smaller).await.unwrap();
+		value_to_fs(&sync_path, &smaller).unwrap();
+		assert_eq!(relative(&sync_path), relative(&async_path));
+		assert_eq!(fs_to_value::<Value>(&async_path).unwrap(), smaller);
+		std::fs::remove_dir_all(&sync_path).unwrap();
+		std::fs::remove_dir_all(&async_path).unwrap();
+	}
+
+	#[test]
+	fn write_stats_count_churn() {
+		let storage = MemStorage::new();
+		let path = Path::new("/db");
+		let options = WriteOptions::default();
+		let value = json!({"a": {"b": 1}, "c": "two"});
+		let stats = value_to_storage(&storage, path, &value, &options).unwrap();
+		// Two `.type` files plus the `b` and `c` leaves.
+		assert_eq!(stats.files_written, 4);
+		assert_eq!(stats.dirs_created, 2);
+		assert_eq!(stats.files_removed, 0);
+		assert_eq!(stats.bytes, b"dictdict1\"two\"".len() as u64);
+
+		let stats = value_to_storage(&storage, path, &value, &options).unwrap();
+		assert_eq!(stats, WriteStats::default());
+
+		let value = json!({"a": {"b": 2}});
+		let stats = value_to_storage(&storage, path, &value, &options).unwrap();
+		assert_eq!(stats.files_written, 1);
+		assert_eq!(stats.files_removed, 1);
+	}
+
+	#[test]
+	fn diff_reports_only_what_changed() {
+		let path = test_dir();
+		let items: Vec<_> = (0..5)
+			.map(|n| json!({"title": format!("item {n}")}))
+			.collect();
+		let value = json!({"items": items, "old": "gone soon"});
+		assert!(fs_diff(&path, &value)
+			.unwrap()
+			.iter()
+			.all(|c| matches!(c, FsChange::Added(_))));
+		assert!(!path.exists());
+
+		value_to_fs(&path, &value).unwrap();
+		assert_eq!(fs_diff(&path, &value).unwrap(), []);
+
+		let mut changed = value.clone();
+		changed["items"][2]["title"] = json!("renamed");
+		changed.as_object_mut().unwrap().remove("old");
+		let items = path.join("key_aXRlbXM=");
+		assert_eq!(
+			fs_diff(&path, &changed).unwrap(),
+			[
+				FsChange::Modified(items.join("elem_2").join("key_dGl0bGU=")),
+				FsChange::Removed(path.join("key_b2xk")),
+			]
+		);
+		assert_eq!(fs_diff(&path, &value).unwrap(), []);
+		std::fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn shared_blobs_dedupe_and_switch_modes() {
+		let author = "A. Very Prolific Author <author@example.com>, who writes every item";
+		let items: Vec<_> = (0..10).map(|n| json!({"author": author, "n": n})).collect();
+		let value = json!({"items": items, "title": "short"});
+		let shared = WriteOptions {
+			blobs: BlobMode::Shared,
+			..Default::default()
+		};
+		let blob_count = |path: &Path| std::fs::read_dir(path.join(".blobs")).unwrap().count();
+		let (path, async_path) = (test_dir(), test_dir());
+		value_to_fs_with(&path, &value, &shared).unwrap();
+		value_to_fs_async_with(&async_path, &value, &shared)
+			.await
+			.unwrap();
+		for path in [&path, &async_path] {
+			assert_eq!(blob_count(path), 1);
+			assert!(path.join("key_dGl0bGU=").is_file());
+			assert_eq!(fs_to_value::<Value>(path).unwrap(), value);
+			assert_eq!(fs_to_value_async::<Value>(path).await.unwrap(), value);
+		}
+
+		let mut changed = value.clone();
+		changed["items"][0]["author"] = json!(author.repeat(2));
+		value_to_fs_with(&path, &changed, &shared).unwrap();
+		assert_eq!(blob_count(&path), 2);
+		changed["items"] = json!([]);
+		value_to_fs_with(&path, &changed, &shared).unwrap();
+		assert!(!path.join(".blobs").exists());
+
+		value_to_fs(&async_path, &value).unwrap();
+		assert!(!async_path.join(".blobs").exists());
+		assert_eq!(fs_to_value::<Value>(&async_path).unwrap(), value);
+		std::fs::remove_dir_all(&path).unwrap();
+		std::fs::remove_dir_all(&async_path).unwrap();
+	}
+
+	#[test]
+	fn missing_dir_types_are_inferred() {
+		let storage = MemStorage::new();
+		let path = Path::new("/db");
+		let value =
+			json!({"dict": {"a": 1}, "array": [{"b": 2}], "empty": {}, "raw": Bytes(vec![1])});
+		value_to_storage(&storage, path, &value, &WriteOptions::default()).unwrap();
+		// `dict`, `array`, `empty`, and `raw`.
+		let lost =
+			["key_ZGljdA==", "key_YXJyYXk=", "key_ZW1wdHk=", "key_cmF3"].map(|n| path.join(n));
+		for dir in &lost {
+			storage.remove(&dir.join(".type")).unwrap();
+		}
+		let (read, report): (Value, _) =
+			storage_to_value_report(&storage, path, &ReadOptions::default()).unwrap();
+		assert_eq!(read, value);
+		let mut lost = lost.to_vec();
+		lost.sort();
+		assert_eq!(report.repaired, lost);
+		assert!(report.skipped.is_empty());
+
+		storage
+			.write(
+				&path.join("key_ZGljdA==/elem_0"),
+				b"1",
+				DurabilityMode::Fast,
+			)
+			.unwrap();
+		let Err(FsToValueError::At { source, .. }) =
+			storage_to_value::<Value>(&storage, path, &ReadOptions::default())
+		else {
+			panic!("expected an ambiguous dir to fail");
+		};
+		assert!(matches!(*source, FsToValueError::NoDirType));
+	}
+}
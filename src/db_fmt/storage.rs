@@ -0,0 +1,413 @@
+use super::{DurabilityMode, WriteStats};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fs::File,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+	File,
+	Dir,
+}
+
+/// Where the tree of files making up a database actually lives.
+pub trait Storage: Sync {
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+	/// Replaces the file at `path` so that readers see either the old or the new contents, never a
+	/// mix.
+	fn write(&self, path: &Path, contents: &[u8], durability: DurabilityMode) -> io::Result<()>;
+	/// Removes a file, or a directory and everything in it. Symlinks are removed, not followed.
+	fn remove(&self, path: &Path) -> io::Result<()>;
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<String>>;
+	fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+	/// What's at `path` after following symlinks, or `None` if nothing is.
+	fn kind(&self, path: &Path) -> io::Result<Option<EntryKind>>;
+	fn is_symlink(&self, _path: &Path) -> bool {
+		false
+	}
+	/// Whether `path` is still inside `root` once links are resolved.
+	fn contains(&self, _root: &Path, _path: &Path) -> io::Result<bool> {
+		Ok(true)
+	}
+	/// Makes entries created, renamed, or removed in `path` durable.
+	fn sync_dir(&self, _path: &Path, _durability: DurabilityMode) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+/// The real filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsStorage;
+
+impl FsStorage {
+	fn sync_parent(&self, path: &Path, durability: DurabilityMode) -> io::Result<()> {
+		match path.parent() {
+			Some(parent) if parent.as_os_str().is_empty() => {
+				self.sync_dir(Path::new("."), durability)
+			}
+			Some(parent) => self.sync_dir(parent, durability),
+			None => Ok(()),
+		}
+	}
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+	let name = path
+		.file_name()
+		.map(|n| n.to_string_lossy().to_string())
+		.unwrap_or_default();
+	path.with_file_name(format!(".{name}.tmp"))
+}
+
+pub(super) fn is_temp_name(name: &str) -> bool {
+	name.starts_with('.') && name.ends_with(".tmp")
+}
+
+impl Storage for FsStorage {
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+		std::fs::read(path)
+	}
+
+	/// Writes into a sibling temp file and renames it into place, so a crash mid-write can't leave
+	/// a half-written file where a good one used to be.
+	fn write(&self, path: &Path, contents: &[u8], durability: DurabilityMode) -> io::Result<()> {
+		let temp = temp_path(path);
+		let mut file = File::create(&temp)?;
+		file.write_all(contents)?;
+		if durability == DurabilityMode::Durable {
+			file.sync_all()?;
+		}
+		drop(file);
+		std::fs::rename(&temp, path)?;
+		self.sync_parent(path, durability)
+	}
+
+	fn remove(&self, path: &Path) -> io::Result<()> {
+		if std::fs::symlink_metadata(path)?.is_dir() {
+			std::fs::remove_dir_all(path)
+		} else {
+			std::fs::remove_file(path)
+		}
+	}
+
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+		Ok(std::fs::read_dir(path)?
+			.flatten()
+			.flat_map(|e| e.file_name().to_str().map(|s| s.to_string()))
+			.collect())
+	}
+
+	fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+		std::fs::create_dir_all(path)
+	}
+
+	fn kind(&self, path: &Path) -> io::Result<Option<EntryKind>> {
+		match std::fs::metadata(path) {
+			Ok(m) if m.is_dir() => Ok(Some(EntryKind::Dir)),
+			Ok(_) => Ok(Some(EntryKind::File)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn is_symlink(&self, path: &Path) -> bool {
+		std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+	}
+
+	fn contains(&self, root: &Path, path: &Path) -> io::Result<bool> {
+		Ok(std::fs::canonicalize(path)?.starts_with(std::fs::canonicalize(root)?))
+	}
+
+	fn sync_dir(&self, path: &Path, durability: DurabilityMode) -> io::Result<()> {
+		// Directories can't be opened as files on every platform; Unix is where this matters.
+		if cfg!(unix) && durability == DurabilityMode::Durable {
+			File::open(path)?.sync_all()?;
+		}
+		Ok(())
+	}
+}
+
+/// Keeps the whole tree in memory, for tests that don't need to touch the disk.
+#[derive(Debug, Default)]
+pub struct MemStorage {
+	inner: Mutex<MemTree>,
+}
+
+#[derive(Debug, Default)]
+struct MemTree {
+	files: BTreeMap<PathBuf, Vec<u8>>,
+	dirs: BTreeSet<PathBuf>,
+}
+
+fn not_found(path: &Path) -> io::Error {
+	io::Error::new(io::ErrorKind::NotFound, path.display().to_string())
+}
+
+impl MemStorage {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl Storage for MemStorage {
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+		let tree = self.inner.lock().unwrap();
+		tree.files.get(path).cloned().ok_or_else(|| not_found(path))
+	}
+
+	fn write(&self, path: &Path, contents: &[u8], _durability: DurabilityMode) -> io::Result<()> {
+		let mut tree = self.inner.lock().unwrap();
+		if tree.dirs.contains(path) {
+			return Err(io::Error::other(format!(
+				"{} is a directory",
+				path.display()
+			)));
+		}
+		tree.files.insert(path.to_path_buf(), contents.to_vec());
+		Ok(())
+	}
+
+	fn remove(&self, path: &Path) -> io::Result<()> {
+		let mut tree = self.inner.lock().unwrap();
+		if tree.files.remove(path).is_some() {
+			return Ok(());
+		}
+		if !tree.dirs.contains(path) {
+			return Err(not_found(path));
+		}
+		tree.files.retain(|p, _| !p.starts_with(path));
+		tree.dirs.retain(|p| !p.starts_with(path));
+		Ok(())
+	}
+
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+		let tree = self.inner.lock().unwrap();
+		if !tree.dirs.contains(path) {
+			return Err(not_found(path));
+		}
+		Ok(tree
+			.files
+			.keys()
+			.chain(tree.dirs.iter())
+			.filter(|p| p.parent() == Some(path))
+			.flat_map(|p| p.file_name()?.to_str().map(|s| s.to_string()))
+			.collect())
+	}
+
+	fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+		let mut tree = self.inner.lock().unwrap();
+		for dir in path.ancestors().filter(|p| !p.as_os_str().is_empty()) {
+			if tree.files.contains_key(dir) {
+				return Err(io::Error::new(
+					io::ErrorKind::AlreadyExists,
+					format!("{} is a file", dir.display()),
+				));
+			}
+			tree.dirs.insert(dir.to_path_buf());
+		}
+		Ok(())
+	}
+
+	fn kind(&self, path: &Path) -> io::Result<Option<EntryKind>> {
+		let tree = self.inner.lock().unwrap();
+		Ok(if tree.files.contains_key(path) {
+			Some(EntryKind::File)
+		} else if tree.dirs.contains(path) {
+			Some(EntryKind::Dir)
+		} else {
+			None
+		})
+	}
+}
+
+/// Passes everything through to `inner`, tallying what actually changed.
+pub(super) struct CountingStorage<'a, S> {
+	pub inner: &'a S,
+	pub stats: Mutex<WriteStats>,
+}
+
+impl<S: Storage> Storage for CountingStorage<'_, S> {
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+		self.inner.read(path)
+	}
+
+	fn write(&self, path: &Path, contents: &[u8], durability: DurabilityMode) -> io::Result<()> {
+		self.inner.write(path, contents, durability)?;
+		let mut stats = self.stats.lock().unwrap();
+		stats.files_written += 1;
+		stats.bytes += contents.len() as u64;
+		Ok(())
+	}
+
+	fn remove(&self, path: &Path) -> io::Result<()> {
+		self.inner.remove(path)?;
+		self.stats.lock().unwrap().files_removed += 1;
+		Ok(())
+	}
+
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+		self.inner.read_dir(path)
+	}
+
+	fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+		self.inner.create_dir_all(path)?;
+		self.stats.lock().unwrap().dirs_created += 1;
+		Ok(())
+	}
+
+	fn kind(&self, path: &Path) -> io::Result<Option<EntryKind>> {
+		self.inner.kind(path)
+	}
+
+	fn is_symlink(&self, path: &Path) -> bool {
+		self.inner.is_symlink(path)
+	}
+
+	fn contains(&self, root: &Path, path: &Path) -> io::Result<bool> {
+		self.inner.contains(root, path)
+	}
+
+	fn sync_dir(&self, path: &Path, durability: DurabilityMode) -> io::Result<()> {
+		self.inner.sync_dir(path, durability)
+	}
+}
+
+/// Records changes in memory instead of applying them, while presenting `inner` as if they had
+/// been applied, so the write walk behaves exactly as it would for real.
+pub(super) struct DryRunStorage<'a, S> {
+	inner: &'a S,
+	overlay: Mutex<Overlay>,
+}
+
+#[derive(Default)]
+struct Overlay {
+	files: BTreeMap<PathBuf, Vec<u8>>,
+	dirs: BTreeSet<PathBuf>,
+	/// Entries of `inner` that have been removed, along with everything under them.
+	removed: BTreeSet<PathBuf>,
+}
+
+impl Overlay {
+	fn hides(&self, path: &Path) -> bool {
+		path.ancestors().any(|p| self.removed.contains(p))
+	}
+}
+
+impl<'a, S: Storage> DryRunStorage<'a, S> {
+	pub fn new(inner: &'a S) -> Self {
+		Self {
+			inner,
+			overlay: Mutex::default(),
+		}
+	}
+
+	/// What would have changed in `inner`, sorted by path.
+	pub fn changes(self) -> io::Result<Vec<super::FsChange>> {
+		use super::FsChange;
+		let overlay = self.overlay.into_inner().unwrap();
+		let mut changes = vec![];
+		for path in overlay.files.into_keys() {
+			changes.push(match self.inner.kind(&path)? {
+				Some(_) => FsChange::Modified(path),
+				None => FsChange::Added(path),
+			});
+		}
+		for path in overlay.removed {
+			let replaced = changes.iter().any(|c| c.path() == path);
+			if !replaced && self.inner.kind(&path)?.is_some() {
+				changes.push(FsChange::Removed(path));
+			}
+		}
+		changes.sort_by(|a, b| a.path().cmp(b.path()));
+		Ok(changes)
+	}
+}
+
+impl<S: Storage> Storage for DryRunStorage<'_, S> {
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+		let overlay = self.overlay.lock().unwrap();
+		if let Some(contents) = overlay.files.get(path) {
+			return Ok(contents.clone());
+		}
+		if overlay.dirs.contains(path) || overlay.hides(path) {
+			return Err(not_found(path));
+		}
+		self.inner.read(path)
+	}
+
+	fn write(&self, path: &Path, contents: &[u8], _durability: DurabilityMode) -> io::Result<()> {
+		let mut overlay = self.overlay.lock().unwrap();
+		overlay.files.insert(path.to_path_buf(), contents.to_vec());
+		Ok(())
+	}
+
+	fn remove(&self, path: &Path) -> io::Result<()> {
+		let mut overlay = self.overlay.lock().unwrap();
+		overlay.files.retain(|p, _| !p.starts_with(path));
+		overlay.dirs.retain(|p| !p.starts_with(path));
+		overlay.removed.insert(path.to_path_buf());
+		Ok(())
+	}
+
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+		let overlay = self.overlay.lock().unwrap();
+		let mut names: BTreeSet<String> = overlay
+			.files
+			.keys()
+			.chain(overlay.dirs.iter())
+			.filter(|p| p.parent() == Some(path))
+			.flat_map(|p| p.file_name()?.to_str().map(|s| s.to_string()))
+			.collect();
+		if !overlay.hides(path) {
+			match self.inner.read_dir(path) {
+				Ok(inner) => names.extend(
+					inner
+						.into_iter()
+						.filter(|name| !overlay.hides(&path.join(name))),
+				),
+				Err(e) if e.kind() == io::ErrorKind::NotFound && overlay.dirs.contains(path) => {}
+				Err(e) => return Err(e),
+			}
+		} else if !overlay.dirs.contains(path) {
+			return Err(not_found(path));
+		}
+		Ok(names.into_iter().collect())
+	}
+
+	fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+		let mut overlay = self.overlay.lock().unwrap();
+		for dir in path.ancestors().filter(|p| !p.as_os_str().is_empty()) {
+			overlay.dirs.insert(dir.to_path_buf());
+		}
+		Ok(())
+	}
+
+	fn kind(&self, path: &Path) -> io::Result<Option<EntryKind>> {
+		let overlay = self.overlay.lock().unwrap();
+		if overlay.files.contains_key(path) {
+			Ok(Some(EntryKind::File))
+		} else if overlay.dirs.contains(path) {
+			Ok(Some(EntryKind::Dir))
+		} else if overlay.hides(path) {
+			Ok(None)
+		} else {
+			self.inner.kind(path)
+		}
+	}
+
+	fn is_symlink(&self, path: &Path) -> bool {
+		let overlay = self.overlay.lock().unwrap();
+		let shadowed = overlay.files.contains_key(path) || overlay.dirs.contains(path);
+		!shadowed && !overlay.hides(path) && self.inner.is_symlink(path)
+	}
+
+	fn contains(&self, root: &Path, path: &Path) -> io::Result<bool> {
+		if self.overlay.lock().unwrap().dirs.contains(path) {
+			return Ok(true);
+		}
+		self.inner.contains(root, path)
+	}
+}
@@ -1,22 +1,235 @@
-use clap::Parser;
+use clap::{ArgAction, Parser, Subcommand};
 use eframe::NativeOptions;
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
+use tracing_subscriber::EnvFilter;
+use winter2::app::{Config, FetchError, FsStore, SqliteStore, Storage, Store, UrlImport};
 
 #[derive(Parser)]
 pub struct Args {
-	#[arg(long, env = "WINTER2_DB_LOCATION", default_value = "./.winter2db")]
-	pub winter_db: PathBuf,
+	/// Where the database is kept [default: `db` from the config file, or ./.winter2db]
+	#[arg(long, env = "WINTER2_DB_LOCATION")]
+	pub winter_db: Option<PathBuf>,
+	/// How the database is kept on disk [default: `storage` from the config file, or fs]
+	#[arg(long, env = "WINTER2_STORAGE", value_enum)]
+	pub storage: Option<Storage>,
+	/// The TOML config file to read, instead of config.toml in the platform's config directory.
+	#[arg(long, env = "WINTER2_CONFIG")]
+	pub config: Option<PathBuf>,
+	/// Log more to stderr: -v for what's being fetched and saved, -vv for details. RUST_LOG
+	/// overrides this.
+	#[arg(short, long, action = ArgAction::Count)]
+	pub verbose: u8,
+	/// Serve the feeds and articles as JSON over HTTP at this address while the window is open:
+	/// GET /feeds, GET /articles?feed=URL, and POST /read with {"feed": URL, "key": KEY}. Needs
+	/// the serve feature. The API has no login, so only loopback addresses like 127.0.0.1:8080
+	/// are accepted.
+	#[arg(long, value_name = "ADDR")]
+	pub serve: Option<SocketAddr>,
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+	/// Print the whole database to stdout as one JSON document.
+	Export,
+	/// Print the subscriptions to stdout as OPML, for other feed readers.
+	ExportOpml,
+	/// Replace the database with a JSON document from `export`.
+	Import {
+		/// Read from stdin if omitted.
+		file: Option<PathBuf>,
+	},
+	/// Download every feed and save them without opening a window, for running from cron. Exits
+	/// with an error if any feed failed.
+	Refresh,
+	/// Subscribe to a feed, once it has downloaded and parsed.
+	Add { url: String },
+	/// Print each feed's URL, title and number of unread articles, tab separated.
+	List,
+	/// Unsubscribe from a feed, forgetting which of its articles were read.
+	Remove { url: String },
+	/// Subscribe to every feed in a list of URLs, one per line, like Newsboat's `urls` file.
+	/// Blank lines and `#` comments are skipped, as are feeds already subscribed to. Exits with an
+	/// error if any feed failed.
+	ImportUrls {
+		/// Read from stdin if omitted.
+		file: Option<PathBuf>,
+	},
 }
 
 #[tokio::main]
-async fn main() {
-	let Args { winter_db } = Args::parse();
-	let (gui, mut backend) = winter2::app::mk_app(winter_db.clone(), !winter_db.is_dir()).unwrap();
-	tokio::spawn(async move { backend.work().await });
+async fn main() -> eyre::Result<()> {
+	let Args {
+		winter_db,
+		storage,
+		config,
+		verbose,
+		serve,
+		command,
+	} = Args::parse();
+	init_logging(verbose);
+	let mut config = Config::load(config.as_deref())?;
+	// Flags win over the config file.
+	config.db = winter_db.unwrap_or(config.db);
+	config.storage = storage.unwrap_or(config.storage);
+	match config.storage {
+		Storage::Fs => run::<FsStore>(config, command, serve).await,
+		Storage::Sqlite => run::<SqliteStore>(config, command, serve).await,
+	}
+}
+
+fn init_logging(verbose: u8) {
+	let level = match verbose {
+		0 => "warn",
+		1 => "info",
+		2 => "debug",
+		_ => "trace",
+	};
+	// Only Winter2's own logs get louder; dependencies stay at warnings.
+	let filter = EnvFilter::try_from_default_env()
+		.unwrap_or_else(|_| EnvFilter::new(format!("warn,winter2={level}")));
+	tracing_subscriber::fmt()
+		.with_env_filter(filter)
+		.with_writer(std::io::stderr)
+		.init();
+}
+
+async fn run<S: Store>(
+	config: Config,
+	command: Option<Command>,
+	serve: Option<SocketAddr>,
+) -> eyre::Result<()> {
+	let winter_db = &config.db;
+	match command {
+		Some(Command::Export) => {
+			println!("{}", winter2::app::export_json::<S>(winter_db)?);
+			return Ok(());
+		}
+		Some(Command::ExportOpml) => {
+			print!("{}", winter2::app::export_opml::<S>(winter_db)?);
+			return Ok(());
+		}
+		Some(Command::Import { file }) => {
+			let json = match file {
+				Some(file) => std::fs::read_to_string(file)?,
+				None => std::io::read_to_string(std::io::stdin())?,
+			};
+			winter2::app::import_json::<S>(winter_db, &json)?;
+			return Ok(());
+		}
+		Some(Command::Refresh) => {
+			let results = winter2::app::refresh_all::<S>(winter_db, &config.client()?).await?;
+			for (url, result) in &results {
+				match result {
+					Ok(()) => println!("ok      {url}"),
+					Err(e) => println!("failed  {url}: {e}"),
+				}
+			}
+			let failed = results.values().filter(|result| result.is_err()).count();
+			println!("Refreshed {} feeds, {failed} failed", results.len());
+			if failed > 0 {
+				eyre::bail!("{failed} feeds failed to refresh");
+			}
+			return Ok(());
+		}
+		Some(Command::Add { url }) => {
+			let title = match winter2::app::subscribe::<S>(winter_db, &config.client()?, &url).await
+			{
+				Ok(title) => title,
+				Err(e) => {
+					if let Some(FetchError::NotAFeed(links)) = e.downcast_ref() {
+						for link in links {
+							eprintln!("It links to {link}");
+						}
+					}
+					return Err(e);
+				}
+			};
+			println!("Added {title}");
+			return Ok(());
+		}
+		Some(Command::List) => {
+			for (url, title, unread) in winter2::app::list_feeds::<S>(winter_db)? {
+				println!("{url}\t{title}\t{unread}");
+			}
+			return Ok(());
+		}
+		Some(Command::Remove { url }) => {
+			let title = winter2::app::unsubscribe::<S>(winter_db, &url).await?;
+			println!("Removed {title}");
+			return Ok(());
+		}
+		Some(Command::ImportUrls { file }) => {
+			let list = match file {
+				Some(file) => std::fs::read_to_string(file)?,
+				None => std::io::read_to_string(std::io::stdin())?,
+			};
+			let results =
+				winter2::app::import_urls::<S>(winter_db, &config.client()?, &list).await?;
+			let (mut added, mut failed) = (0, 0);
+			for (url, result) in &results {
+				match result {
+					UrlImport::Added(title) => {
+						added += 1;
+						println!("added   {url}: {title}");
+					}
+					UrlImport::AlreadySubscribed => println!("skipped {url}: already subscribed"),
+					UrlImport::Failed(e) => {
+						failed += 1;
+						println!("failed  {url}: {e}");
+					}
+				}
+			}
+			println!("Imported {added} feeds, {failed} failed");
+			if failed > 0 {
+				eyre::bail!("{failed} feeds failed to import");
+			}
+			return Ok(());
+		}
+		None => {}
+	}
+	#[cfg(not(feature = "serve"))]
+	if serve.is_some() {
+		eyre::bail!("This build can't --serve, since it doesn't have the serve feature");
+	}
+	if let Some(addr) = serve.filter(|addr| !addr.ip().is_loopback()) {
+		eyre::bail!(
+			"Refusing to --serve on {addr}: the API has no login, so use a loopback address"
+		);
+	}
+	let (mut gui, mut backend) = winter2::app::mk_app::<S>(&config, !winter_db.exists())?;
+	#[cfg(feature = "serve")]
+	if let Some(addr) = serve {
+		let listener = tokio::net::TcpListener::bind(addr).await?;
+		let api = winter2::app::Api::new(&gui, &backend);
+		tokio::spawn(async move {
+			if let Err(e) = api.serve(listener).await {
+				tracing::error!("The HTTP API stopped: {e}");
+			}
+		});
+	}
+	let backend = tokio::spawn(async move {
+		if let Err(e) = backend.work().await {
+			tracing::error!("The backend stopped, so nothing more will be saved: {e}");
+		}
+	});
 	eframe::run_native(
 		"Winter2",
-		NativeOptions::default(),
-		Box::new(|_| Box::new(gui)),
+		NativeOptions {
+			// Lets the "Follow system" theme see the OS setting on every platform.
+			follow_system_theme: true,
+			// Reopens the window where it was, and restores egui's scroll positions.
+			persist_window: true,
+			..Default::default()
+		},
+		Box::new(|cc| {
+			gui.restore(cc.storage);
+			Box::new(gui)
+		}),
 	)
 	.unwrap();
+	// Closing the window told the backend to stop; let it finish saving first.
+	backend.await?;
+	Ok(())
 }
@@ -1,13 +1,39 @@
-use std::{num::ParseFloatError, process::Stdio, time::Duration};
+use std::{
+	num::ParseFloatError,
+	process::{ExitStatus, Stdio},
+	time::Duration,
+};
 
 use thiserror::Error;
 use tokio::{
-	io::{AsyncReadExt, AsyncWriteExt},
+	io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+	net::TcpStream,
 	process::{Child, Command},
 };
 
+const QUIT_TIMEOUT: Duration = Duration::from_secs(2);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct Vlc {
 	child: Child,
+	input: Box<dyn AsyncWrite + Unpin + Send>,
+	output: Box<dyn AsyncRead + Unpin + Send>,
+	url: String,
+	tcp: Option<(String, u16)>,
+	last_time: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrackList {
+	pub tracks: Vec<(u32, String)>,
+	pub active: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NowPlaying {
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
 }
 
 #[derive(Debug, Error)]
@@ -18,11 +44,15 @@ pub enum VlcError {
 	API(String),
 	#[error("Bad float")]
 	BadFloat(#[from] ParseFloatError),
+	#[error("Timed out connecting to VLC")]
+	ConnectTimeout,
+	#[error("VLC exited ({0})")]
+	ProcessExited(ExitStatus),
 }
 
 impl Vlc {
 	pub async fn new(url: &str) -> Result<Self, VlcError> {
-		let child = Command::new("vlc")
+		let mut child = Command::new("vlc")
 			.stdin(Stdio::piped())
 			.stdout(Stdio::piped())
 			.arg("--extraintf")
@@ -30,9 +60,62 @@ impl Vlc {
 			.arg(url)
 			.kill_on_drop(true)
 			.spawn()?;
-		let mut vlc = Self { child };
-		let out = vlc.child.stdout.as_mut().unwrap();
-		while let Ok(r) = out.read_u8().await {
+		let input = Box::new(child.stdin.take().unwrap());
+		let output = Box::new(child.stdout.take().unwrap());
+		Self::handshake(child, input, output, url, None).await
+	}
+
+	/// Like [`Vlc::new`], but talks to VLC over its RC TCP socket so log lines on stdout can't
+	/// desync the prompt parser.
+	pub async fn new_tcp(url: &str, host: &str, port: u16) -> Result<Self, VlcError> {
+		let child = Command::new("vlc")
+			.stdin(Stdio::null())
+			.stdout(Stdio::null())
+			.arg("--extraintf")
+			.arg("rc")
+			.arg("--rc-host")
+			.arg(format!("{host}:{port}"))
+			.arg(url)
+			.kill_on_drop(true)
+			.spawn()?;
+		// VLC takes a moment to open the socket after starting.
+		let stream = tokio::time::timeout(CONNECT_TIMEOUT, async {
+			loop {
+				match TcpStream::connect((host, port)).await {
+					Ok(stream) => break stream,
+					Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+				}
+			}
+		})
+		.await
+		.map_err(|_| VlcError::ConnectTimeout)?;
+		let (output, input) = stream.into_split();
+		Self::handshake(
+			child,
+			Box::new(input),
+			Box::new(output),
+			url,
+			Some((host.to_string(), port)),
+		)
+		.await
+	}
+
+	async fn handshake(
+		child: Child,
+		input: Box<dyn AsyncWrite + Unpin + Send>,
+		output: Box<dyn AsyncRead + Unpin + Send>,
+		url: &str,
+		tcp: Option<(String, u16)>,
+	) -> Result<Self, VlcError> {
+		let mut vlc = Self {
+			child,
+			input,
+			output,
+			url: url.to_string(),
+			tcp,
+			last_time: 0.0,
+		};
+		while let Ok(r) = vlc.output.read_u8().await {
 			if r == b'>' {
 				break;
 			}
@@ -40,30 +123,54 @@ impl Vlc {
 
 		Ok(vlc)
 	}
+
 	pub async fn cmd(&mut self, cmd: &str) -> Result<String, VlcError> {
-		self.child
-			.stdin
-			.as_mut()
-			.unwrap()
-			.write_all(format!("{cmd}\n").as_bytes())
-			.await?;
-		let out = self.child.stdout.as_mut().unwrap();
+		self.check_alive()?;
+		let written = self.input.write_all(format!("{cmd}\n").as_bytes()).await;
+		self.check_alive()?;
+		written?;
 		let mut output = Vec::new();
-		while let Ok(read) = out.read_u8().await {
-			if read == b'>' {
-				break;
+		loop {
+			match self.output.read_u8().await {
+				Ok(b'>') => break,
+				Ok(read) => output.push(read),
+				Err(e) => {
+					self.check_alive()?;
+					return Err(e.into());
+				}
 			}
-			output.push(read)
 		}
 		Ok(String::from_utf8_lossy(&output).trim().to_string())
 	}
 
+	fn check_alive(&mut self) -> Result<(), VlcError> {
+		match self.child.try_wait()? {
+			Some(status) => Err(VlcError::ProcessExited(status)),
+			None => Ok(()),
+		}
+	}
+
+	/// Respawns VLC at the last URL and seeks back to the last known position, e.g. after
+	/// a crash reported as [`VlcError::ProcessExited`].
+	pub async fn reconnect(&mut self) -> Result<(), VlcError> {
+		let last_time = self.last_time;
+		*self = match self.tcp.clone() {
+			Some((host, port)) => Self::new_tcp(&self.url, &host, port).await?,
+			None => Self::new(&self.url).await?,
+		};
+		self.wait_for_playing().await?;
+		self.seek(last_time).await?;
+		self.last_time = last_time;
+		Ok(())
+	}
+
 	pub async fn is_playing(&mut self) -> Result<bool, VlcError> {
-		match self.cmd("is_playing").await?.as_str() {
-			"0" => Ok(false),
-			"1" => Ok(true),
-			malformed => Err(VlcError::API(malformed.to_string())),
+		let output = self.cmd("is_playing").await?;
+		if let Some(playing) = parse_is_playing(&output) {
+			return Ok(playing);
 		}
+		let status = self.cmd("status").await?;
+		parse_status_state(&status).ok_or(VlcError::API(output))
 	}
 
 	pub async fn wait_for_playing(&mut self) -> Result<(), VlcError> {
@@ -74,7 +181,8 @@ impl Vlc {
 	}
 
 	pub async fn play_time(&mut self) -> Result<f64, VlcError> {
-		Ok(self.cmd("get_time").await?.parse()?)
+		self.last_time = self.cmd("get_time").await?.parse()?;
+		Ok(self.last_time)
 	}
 
 	pub async fn video_length(&mut self) -> Result<f64, VlcError> {
@@ -84,11 +192,203 @@ impl Vlc {
 	pub async fn progress(&mut self) -> Result<f64, VlcError> {
 		Ok(self.play_time().await? / self.video_length().await?)
 	}
+
+	pub async fn seek(&mut self, time: f64) -> Result<(), VlcError> {
+		self.cmd(&format!("seek {}", time.max(0.0))).await?;
+		Ok(())
+	}
+
+	/// Seeks to `fraction` (from 0 to 1) of the way through the media.
+	pub async fn seek_fraction(&mut self, fraction: f64) -> Result<(), VlcError> {
+		let length = self.video_length().await?;
+		self.seek(length * fraction.clamp(0.0, 1.0)).await
+	}
+
+	/// Pauses, or resumes if already paused.
+	pub async fn pause(&mut self) -> Result<(), VlcError> {
+		self.cmd("pause").await?;
+		Ok(())
+	}
+
+	/// Sets the volume, where 1 is 100%. VLC counts 256 steps to 100%.
+	pub async fn set_volume(&mut self, volume: f64) -> Result<(), VlcError> {
+		let steps = (volume.max(0.0) * 256.0).round();
+		self.cmd(&format!("volume {steps}")).await?;
+		Ok(())
+	}
+
+	/// Sets the playback speed, where 1 is normal speed.
+	pub async fn set_rate(&mut self, rate: f64) -> Result<(), VlcError> {
+		self.cmd(&format!("rate {rate}")).await?;
+		Ok(())
+	}
+
+	/// Skips `delta` seconds forward (or back, if negative) and returns the new position.
+	/// The target is clamped to the media so we never seek past the end.
+	pub async fn jump(&mut self, delta: f64) -> Result<f64, VlcError> {
+		let length = self.video_length().await?;
+		let mut position = (self.play_time().await? + delta).max(0.0);
+		// Live streams report a length of 0, so there's no end to clamp to.
+		if length > 0.0 {
+			position = position.min(length);
+		}
+		self.seek(position).await?;
+		Ok(position)
+	}
+
+	pub async fn audio_tracks(&mut self) -> Result<TrackList, VlcError> {
+		parse_track_list(&self.cmd("atrack").await?)
+	}
+
+	pub async fn subtitle_tracks(&mut self) -> Result<TrackList, VlcError> {
+		parse_track_list(&self.cmd("strack").await?)
+	}
+
+	pub async fn set_audio_track(&mut self, id: u32) -> Result<(), VlcError> {
+		self.cmd(&format!("atrack {id}")).await?;
+		Ok(())
+	}
+
+	pub async fn set_subtitle_track(&mut self, id: u32) -> Result<(), VlcError> {
+		self.cmd(&format!("strack {id}")).await?;
+		Ok(())
+	}
+
+	pub async fn now_playing(&mut self) -> Result<NowPlaying, VlcError> {
+		parse_now_playing(&self.cmd("info").await?)
+	}
+
+	/// Asks VLC to exit, killing it if it hasn't gone away after a short grace period.
+	pub async fn quit(&mut self) -> Result<(), VlcError> {
+		// VLC won't print another prompt after `quit`, so don't go through `cmd`.
+		let sent = self.input.write_all(b"quit\n").await;
+		if sent.is_ok()
+			&& tokio::time::timeout(QUIT_TIMEOUT, self.child.wait())
+				.await
+				.is_ok()
+		{
+			return Ok(());
+		}
+		self.child.kill().await?;
+		Ok(())
+	}
+
+	/// Closes VLC cleanly when possible. Prefer this over dropping, which SIGKILLs it.
+	pub async fn shutdown(mut self) -> Result<(), VlcError> {
+		self.quit().await
+	}
+}
+
+/// Pulls title/artist/album out of the `Meta data` section of VLC's `info` output.
+/// Fields VLC doesn't report are left as `None`.
+fn parse_now_playing(output: &str) -> Result<NowPlaying, VlcError> {
+	let mut now_playing = NowPlaying::default();
+	let mut seen_section = false;
+	let mut in_meta = false;
+	for line in output.lines().map(str::trim) {
+		if let Some(section) = line.strip_prefix("+----[") {
+			seen_section = true;
+			in_meta = section.to_lowercase().contains("meta") && !section.contains("end of");
+			continue;
+		}
+		if !in_meta {
+			continue;
+		}
+		let Some((key, value)) = line.trim_start_matches('|').split_once(':') else {
+			continue;
+		};
+		let value = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+		match key.trim().to_lowercase().as_str() {
+			"title" => now_playing.title = value,
+			"artist" => now_playing.artist = value,
+			"album" => now_playing.album = value,
+			_ => {}
+		}
+	}
+	if !seen_section {
+		return Err(VlcError::API(output.to_string()));
+	}
+	Ok(now_playing)
+}
+
+/// Finds the `0`/`1` answer to `is_playing`, ignoring `status change: ( ... )` noise that some
+/// VLC builds interleave with command output.
+fn parse_is_playing(output: &str) -> Option<bool> {
+	let mut cleaned = output.to_string();
+	while let Some(start) = cleaned.find("status change:") {
+		let end = cleaned[start..]
+			.find(')')
+			.map(|end| start + end + 1)
+			.unwrap_or(cleaned.len());
+		cleaned.replace_range(start..end, " ");
+	}
+	cleaned
+		.split_whitespace()
+		.rev()
+		.find_map(|token| match token {
+			"0" => Some(false),
+			"1" => Some(true),
+			_ => None,
+		})
+}
+
+/// Reads the `( state playing )` line of the `status` command.
+fn parse_status_state(output: &str) -> Option<bool> {
+	output.lines().find_map(|line| {
+		let state = line
+			.trim()
+			.strip_prefix('(')?
+			.strip_suffix(')')?
+			.trim()
+			.strip_prefix("state ")?;
+		Some(state.trim() == "playing")
+	})
+}
+
+/// Parses VLC's human-formatted track listing, e.g.
+/// ```text
+/// +----[ Audio Track ]
+/// | -1 - Disable
+/// | 1 - Track 1 [*]
+/// +----[ end of Audio Track ]
+/// ```
+/// The "Disable" pseudo-track is skipped.
+fn parse_track_list(output: &str) -> Result<TrackList, VlcError> {
+	let malformed = || VlcError::API(output.to_string());
+	let mut list = TrackList::default();
+	let mut seen_header = false;
+	for line in output.lines().map(str::trim).filter(|l| !l.is_empty()) {
+		if line.starts_with("+----[") {
+			seen_header = true;
+			continue;
+		}
+		let entry = line.strip_prefix('|').ok_or_else(malformed)?.trim();
+		let (id, name) = entry.split_once(" - ").ok_or_else(malformed)?;
+		let id: i64 = id.trim().parse().map_err(|_| malformed())?;
+		let Ok(id) = u32::try_from(id) else {
+			continue;
+		};
+		let (name, active) = match name.trim().strip_suffix("[*]") {
+			Some(name) => (name.trim(), true),
+			None => (name.trim(), false),
+		};
+		if active {
+			list.active = Some(id);
+		}
+		list.tracks.push((id, name.to_string()));
+	}
+	if !seen_header {
+		return Err(malformed());
+	}
+	Ok(list)
 }
 
 #[cfg(test)]
 mod tests {
-	use crate::vlc::Vlc;
+	use crate::vlc::{
+		parse_is_playing, parse_now_playing, parse_status_state, parse_track_list, NowPlaying,
+		TrackList, Vlc,
+	};
 
 	#[tokio::test]
 	async fn vlc_works_ok() {
@@ -96,4 +396,59 @@ mod tests {
 		let output = vlc.is_playing().await.unwrap();
 		assert!(!output);
 	}
+
+	#[test]
+	fn parses_track_list() {
+		let output = "+----[ Audio Track ]\n| -1 - Disable\n| 1 - Track 1 [*]\n| 2 - Commentary - [English]\n+----[ end of Audio Track ]";
+		assert_eq!(
+			parse_track_list(output).unwrap(),
+			TrackList {
+				tracks: vec![
+					(1, "Track 1".to_string()),
+					(2, "Commentary - [English]".to_string())
+				],
+				active: Some(1),
+			}
+		);
+	}
+
+	#[test]
+	fn rejects_unrecognized_track_list() {
+		assert!(parse_track_list("unknown command `atrack'").is_err());
+	}
+
+	#[test]
+	fn parses_now_playing() {
+		let output = "+----[ Stream 0 ]\n| Type: Audio\n| Codec: MPEG Audio layer 1/2 (mpga)\n+----[ end of Stream 0 ]\n+----[ Meta data ]\n| title: Episode 12: Rust\n| artist: Some Podcast\n| filename: ep12.mp3\n+----[ end of Meta data ]";
+		assert_eq!(
+			parse_now_playing(output).unwrap(),
+			NowPlaying {
+				title: Some("Episode 12: Rust".to_string()),
+				artist: Some("Some Podcast".to_string()),
+				album: None,
+			}
+		);
+		assert!(parse_now_playing("garbage").is_err());
+	}
+
+	#[test]
+	fn parses_noisy_is_playing() {
+		assert_eq!(parse_is_playing("1"), Some(true));
+		assert_eq!(
+			parse_is_playing("status change: ( play state: 3 )\n1"),
+			Some(true)
+		);
+		assert_eq!(
+			parse_is_playing("status change: ( audio volume: 256 ) 0"),
+			Some(false)
+		);
+		assert_eq!(parse_is_playing("status change: ( play state: 1 )"), None);
+		assert_eq!(
+			parse_status_state(
+				"( new input: file:///ep.mp3 )\n( audio volume: 256 )\n( state playing )"
+			),
+			Some(true)
+		);
+		assert_eq!(parse_status_state("( state stopped )"), Some(false));
+	}
 }